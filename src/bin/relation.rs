@@ -15,13 +15,17 @@ use relation::datamanager::app::App;
 use relation::datamanager::async_app::App;
 
 fn main() -> Result<()> {
-    let mut datamanager = App::new("relation")?;
-
     #[cfg(feature = "autocomlete")]
     CompleteEnv::with_factory(Cli::command).complete();
 
     let mut cli = Cli::parse();
 
-    cli.run(&mut datamanager)?;
+    let res = App::new("relation").and_then(|mut datamanager| cli.run(&mut datamanager));
+    if let Err(error) = res {
+        if cli.print_error(&error) {
+            std::process::exit(1);
+        }
+        return Err(error);
+    }
     Ok(())
 }
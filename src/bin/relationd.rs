@@ -7,6 +7,7 @@ use interprocess::local_socket::{
 use relation::socket_name;
 #[cfg(unix)]
 use relation::socket_path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::{
     env, fs,
@@ -23,14 +24,20 @@ use tokio::{
 use relation::DaemonStatus;
 
 use relation::bridge;
+use relation::clashapi::{ClashClient, format_bytes};
 use relation::consts::*;
+use relation::datamanager::Settings;
+use relation::datamanager::render;
 use relation::minireq::*;
+use relation::secrets::Key;
+use relation::usage::{Bytes, Quota, UsageStore, month_key, now};
 use relation::{Command as ClientCommand, Request, Response};
 
 const DETACHED_ENV: &str = "RELATION_DETACHED";
 const FOREGROUND_FLAG: &str = "--foreground";
 
 type SharedStatus = Arc<Mutex<DaemonStatus>>;
+type SharedKey = Arc<Mutex<Option<Key>>>;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -47,14 +54,25 @@ async fn main() -> Result<()> {
     let _socket_guard = prepare_socket_file()?;
 
     let listener = ListenerOptions::new().name(socket_name()?).create_tokio()?;
+    // Socket carries key of encrypted credentials
+    #[cfg(unix)]
+    fs::set_permissions(
+        socket_path(),
+        std::os::unix::fs::PermissionsExt::from_mode(0o600),
+    )?;
     let (shutdown_tx, mut shutdown_rx) = mpsc::unbounded_channel();
     let status = Arc::new(Mutex::new(DaemonStatus {
         file: String::new(),
         sys_proxy: false,
         ping: None,
         ip: None,
+        warning: None,
     }));
 
+    let key: SharedKey = Arc::new(Mutex::new(None));
+
+    tokio::spawn(record_usage(status.clone(), key.clone()));
+
     eprintln!("daemon listening");
 
     loop {
@@ -64,9 +82,10 @@ async fn main() -> Result<()> {
 
                 let shutdown_tx = shutdown_tx.clone();
                 let status = status.clone();
+                let key = key.clone();
 
                 tokio::spawn(async move {
-                    if let Err(error) = handle_client(stream, shutdown_tx, status).await {
+                    if let Err(error) = handle_client(stream, shutdown_tx, status, key).await {
                         eprintln!("client error: {error}");
                     }
                 });
@@ -85,6 +104,9 @@ async fn main() -> Result<()> {
         }
     }
 
+    if let Err(error) = render::clear().and_then(|_| render::remove_clash_api()) {
+        eprintln!("runtime error: {error}");
+    }
     Ok(())
 }
 
@@ -220,6 +242,7 @@ async fn handle_client(
     stream: Stream,
     shutdown_tx: mpsc::UnboundedSender<()>,
     status: SharedStatus,
+    key: SharedKey,
 ) -> Result<()> {
     let mut reader = BufReader::new(&stream);
     let mut writer = &stream;
@@ -279,22 +302,49 @@ async fn handle_client(
                 }
             }
 
-            ClientCommand::Start(config_path) => match bridge::start_safe(&config_path, 0) {
-                Some(error) => Response::Error(error),
-                None => {
-                    let mut status = status.lock().await;
-                    status.file = config_path;
+            ClientCommand::Start(config_path) => {
+                match start_core(&key, &config_path, false).await {
+                    Some(error) => Response::Error(error),
+                    None => {
+                        let mut status = status.lock().await;
+                        status.file = config_path;
+                        status.warning = None;
+
+                        Response::Ok
+                    }
+                }
+            }
 
+            ClientCommand::Reload(config_path) => {
+                match start_core(&key, &config_path, true).await {
+                    Some(error) => Response::Error(error),
+                    None => {
+                        let mut status = status.lock().await;
+                        status.file = config_path;
+
+                        Response::Ok
+                    }
+                }
+            }
+
+            ClientCommand::Unlock(value) => match Key::from_base64(&value) {
+                Ok(x) => {
+                    *key.lock().await = Some(x);
                     Response::Ok
                 }
+                Err(error) => Response::Error(error.to_string()),
             },
 
             ClientCommand::Stop => match bridge::stop_safe() {
                 Some(error) => Response::Error(error),
                 None => {
+                    if let Err(error) = render::clear().and_then(|_| render::remove_clash_api()) {
+                        eprintln!("runtime error: {error}");
+                    }
                     let mut status = status.lock().await;
                     status.file.clear();
                     status.sys_proxy = false;
+                    status.warning = None;
 
                     Response::Ok
                 }
@@ -371,3 +421,151 @@ async fn handle_client(
 
     Ok(())
 }
+
+/// Data dir and config name of `<data dir>/config/<name>.json`
+fn config_location(file: &str) -> Option<(PathBuf, String)> {
+    let path = Path::new(file);
+    let name = path.file_stem()?.to_str()?.to_string();
+    let data_dir = path.parent()?.parent()?.to_path_buf();
+    Some((data_dir, name))
+}
+
+fn load_clash_client(file: &str) -> Option<ClashClient> {
+    ClashClient::from_config(&render::clash_api(Path::new(file))?)
+}
+
+/// Starts core with config rendered into runtime dir
+async fn start_core(key: &SharedKey, config_path: &str, restart: bool) -> Option<String> {
+    let path = match render::render(Path::new(config_path), key.lock().await.as_ref()) {
+        Ok(x) => x.to_string_lossy().to_string(),
+        Err(error) => return Some(error.to_string()),
+    };
+    let res = match restart {
+        true => bridge::restart_safe(&path, 0),
+        false => bridge::start_safe(&path, 0),
+    };
+    // Decrypted config is not kept on disk once the core has read it
+    if let Err(error) = render::clear() {
+        eprintln!("runtime error: {error}");
+    }
+    res
+}
+
+/// Records traffic of the running config every `USAGE_SAMPLE` and applies its quota
+async fn record_usage(status: SharedStatus, key: SharedKey) {
+    let mut tracked = String::new();
+    let mut client: Option<ClashClient> = None;
+    let mut prev: Option<Bytes> = None;
+
+    loop {
+        tokio::time::sleep(timing::USAGE_SAMPLE).await;
+
+        let file = status.lock().await.file.clone();
+        if file != tracked {
+            tracked = file.clone();
+            client = load_clash_client(&file);
+            prev = None;
+        }
+        let Some(c) = client.clone() else {
+            continue;
+        };
+
+        let totals = match tokio::task::spawn_blocking(move || c.connections()).await {
+            Ok(Ok(x)) => Bytes {
+                up: x.upload_total,
+                down: x.download_total,
+            },
+            _ => continue,
+        };
+        // Core counters start from zero with every start of the core
+        let delta = match prev {
+            Some(p) if totals.up >= p.up && totals.down >= p.down => Bytes {
+                up: totals.up - p.up,
+                down: totals.down - p.down,
+            },
+            _ => totals,
+        };
+        prev = Some(totals);
+
+        let Some((data_dir, name)) = config_location(&file) else {
+            continue;
+        };
+        if delta.total() > 0 {
+            let path = UsageStore::path(&data_dir);
+            let result = UsageStore::update(&path, |x| x.record(&name, delta, now()));
+            if let Err(error) = result {
+                eprintln!("usage error: {error}");
+            }
+        }
+
+        if let Err(error) = apply_quota(&status, &key, &data_dir, &name).await {
+            eprintln!("quota error: {error}");
+        }
+    }
+}
+
+/// Used bytes of this month when config passed its quota
+fn passed_quota(data_dir: &Path, settings: &Settings, name: &str) -> Result<Option<(u64, Quota)>> {
+    let Some(quota) = settings.get_quota(name) else {
+        return Ok(None);
+    };
+    let used = UsageStore::load(&UsageStore::path(data_dir))?
+        .get(name)
+        .map(|x| x.total(&month_key(now())).total())
+        .unwrap_or_default();
+    Ok((used >= quota.limit()?).then(|| (used, quota.clone())))
+}
+
+async fn apply_quota(
+    status: &SharedStatus,
+    key: &SharedKey,
+    data_dir: &Path,
+    name: &str,
+) -> Result<()> {
+    let mut settings = Settings::default();
+    settings.read(data_dir.join("settings.toml"))?;
+    let Some((used, quota)) = passed_quota(data_dir, &settings, name)? else {
+        return Ok(());
+    };
+
+    let mut warning = format!(
+        "{name} passed monthly quota: {} of {}",
+        format_bytes(used),
+        quota.monthly
+    );
+    let mut status = status.lock().await;
+
+    match (quota.action(), quota.switch_to.as_deref()) {
+        ("switch", Some(target)) if passed_quota(data_dir, &settings, target)?.is_none() => {
+            let path = data_dir.join("config").join(format!("{target}.json"));
+            let path = path.to_string_lossy().to_string();
+            if let Some(error) = start_core(key, &path, true).await {
+                bail!(error);
+            }
+            status.file = path;
+            warning.push_str(&format!(", switched to {target}"));
+        }
+        ("stop" | "switch", _) => {
+            if status.sys_proxy
+                && let Some(error) = bridge::disable_system_proxy_safe()
+            {
+                bail!(error);
+            }
+            if let Some(error) = bridge::stop_safe() {
+                bail!(error);
+            }
+            render::clear()?;
+            render::remove_clash_api()?;
+            status.file.clear();
+            status.sys_proxy = false;
+            warning.push_str(", stopped");
+        }
+        _ => {}
+    }
+
+    if status.warning.as_deref() != Some(warning.as_str()) {
+        eprintln!("{warning}");
+    }
+    status.warning = Some(warning);
+    Ok(())
+}
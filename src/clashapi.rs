@@ -0,0 +1,300 @@
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+use crate::configurator::experimental::ClashApiConfig;
+use crate::minireq::parse_status_code;
+
+/// Default modes of sing-box clash api, rules may add others with `clash_mode`
+pub const MODES: &[&str] = &["rule", "global", "direct"];
+
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Random 32 hex chars for `secret`
+pub fn generate_secret() -> Result<String> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).map_err(|e| anyhow!("No random source: {e}"))?;
+    Ok(bytes.iter().map(|x| format!("{x:02x}")).collect())
+}
+
+/// First port from `port` which is free on loopback
+pub fn free_port(port: u16) -> u16 {
+    (port..port.saturating_add(16))
+        .find(|x| TcpListener::bind(("127.0.0.1", *x)).is_ok())
+        .unwrap_or(port)
+}
+
+/// Bytes with binary unit, `1.5 MB`
+pub fn format_bytes(bytes: u64) -> String {
+    let units = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{bytes} B"),
+        _ => format!("{value:.1} {}", units[unit]),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Connections {
+    pub download_total: u64,
+    pub upload_total: u64,
+    pub connections: Option<Vec<Connection>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Connection {
+    pub id: String,
+    pub metadata: ConnectionMetadata,
+    pub upload: u64,
+    pub download: u64,
+    /// RFC 3339 time
+    pub start: String,
+    /// Outbounds from the last one to the first
+    pub chains: Vec<String>,
+    pub rule: String,
+    pub rule_payload: String,
+}
+
+impl Connection {
+    /// Host or destination ip with port
+    pub fn destination(&self) -> String {
+        let x = &self.metadata;
+        let host = match x.host.is_empty() {
+            true => &x.destination_ip,
+            false => &x.host,
+        };
+        format!("{host}:{}", x.destination_port)
+    }
+
+    /// Outbound which carried the connection
+    pub fn outbound(&self) -> &str {
+        self.chains.first().map_or("", |x| x.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct ConnectionMetadata {
+    pub network: String,
+    #[serde(rename = "type")]
+    pub conn_type: String,
+    #[serde(rename = "sourceIP")]
+    pub source_ip: String,
+    #[serde(rename = "destinationIP")]
+    pub destination_ip: String,
+    #[serde(rename = "sourcePort")]
+    pub source_port: String,
+    #[serde(rename = "destinationPort")]
+    pub destination_port: String,
+    pub host: String,
+    #[serde(rename = "processPath")]
+    pub process_path: String,
+}
+
+/// Rates in bytes per second
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default)]
+pub struct Traffic {
+    pub up: u64,
+    pub down: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Proxy {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub proxy_type: String,
+    /// Selected member of selector or urltest
+    pub now: Option<String>,
+    pub all: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Proxies {
+    proxies: BTreeMap<String, Proxy>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Configs {
+    mode: String,
+}
+
+/// Client of sing-box clash api
+#[derive(Debug, Clone)]
+pub struct ClashClient {
+    addr: String,
+    secret: Option<String>,
+}
+
+impl ClashClient {
+    pub fn new(addr: &str, secret: Option<&str>) -> Self {
+        Self {
+            addr: addr.to_string(),
+            secret: secret.filter(|x| !x.is_empty()).map(|x| x.to_string()),
+        }
+    }
+
+    /// Controller listening on all addresses is reached through loopback
+    pub fn from_config(config: &ClashApiConfig) -> Option<Self> {
+        let addr = config.external_controller.as_deref()?;
+        let addr = match addr.rsplit_once(':') {
+            Some(("" | "0.0.0.0" | "[::]", port)) => format!("127.0.0.1:{port}"),
+            _ => addr.to_string(),
+        };
+        Some(Self::new(&addr, config.secret.as_deref()))
+    }
+
+    pub fn connections(&self) -> Result<Connections> {
+        let body = self.request("GET", "/connections", None, false)?;
+        serde_json::from_str(&body).context("Incorrect connections response")
+    }
+
+    pub fn close_connection(&self, id: &str) -> Result<()> {
+        let path = format!("/connections/{}", urlencoding::encode(id));
+        self.request("DELETE", &path, None, false).map(|_| ())
+    }
+
+    pub fn close_all_connections(&self) -> Result<()> {
+        self.request("DELETE", "/connections", None, false)
+            .map(|_| ())
+    }
+
+    /// Current rates, `/traffic` streams one sample per second
+    pub fn traffic(&self) -> Result<Traffic> {
+        let body = self.request("GET", "/traffic", None, true)?;
+        serde_json::from_str(body.trim()).context("Incorrect traffic response")
+    }
+
+    pub fn proxies(&self) -> Result<BTreeMap<String, Proxy>> {
+        let body = self.request("GET", "/proxies", None, false)?;
+        let proxies: Proxies = serde_json::from_str(&body).context("Incorrect proxies response")?;
+        Ok(proxies.proxies)
+    }
+
+    /// Switches selector outbound to `member`
+    pub fn select(&self, selector: &str, member: &str) -> Result<()> {
+        let path = format!("/proxies/{}", urlencoding::encode(selector));
+        let body = serde_json::json!({ "name": member }).to_string();
+        self.request("PUT", &path, Some(&body), false).map(|_| ())
+    }
+
+    pub fn mode(&self) -> Result<String> {
+        let body = self.request("GET", "/configs", None, false)?;
+        let configs: Configs = serde_json::from_str(&body).context("Incorrect configs response")?;
+        Ok(configs.mode)
+    }
+
+    pub fn set_mode(&self, mode: &str) -> Result<()> {
+        let body = serde_json::json!({ "mode": mode }).to_string();
+        self.request("PATCH", "/configs", Some(&body), false)
+            .map(|_| ())
+    }
+
+    /// Sends request and returns body, `stream` reads only the first line of endless body
+    fn request(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&str>,
+        stream: bool,
+    ) -> Result<String> {
+        let mut conn = TcpStream::connect(&self.addr)
+            .with_context(|| format!("Cant connect to clash api at {}", self.addr))?;
+        conn.set_read_timeout(Some(TIMEOUT))?;
+        conn.set_write_timeout(Some(TIMEOUT))?;
+
+        let mut request = format!(
+            "{method} {path} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n",
+            self.addr
+        );
+        if let Some(x) = &self.secret {
+            request.push_str(&format!("Authorization: Bearer {x}\r\n"));
+        }
+        if let Some(x) = body {
+            request.push_str(&format!(
+                "Content-Type: application/json\r\nContent-Length: {}\r\n",
+                x.len()
+            ));
+        }
+        request.push_str("\r\n");
+        request.push_str(body.unwrap_or_default());
+        conn.write_all(request.as_bytes())?;
+
+        let mut reader = BufReader::new(conn);
+        let mut status = String::new();
+        reader.read_line(&mut status)?;
+        let code = parse_status_code(&status).map_err(|e| anyhow!("{e}"))?;
+
+        let mut chunked = false;
+        let mut length = None;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+                break;
+            }
+            if let Some((key, value)) = line.split_once(':') {
+                match key.trim().to_ascii_lowercase().as_str() {
+                    "transfer-encoding" => chunked = value.trim().eq_ignore_ascii_case("chunked"),
+                    "content-length" => length = value.trim().parse::<usize>().ok(),
+                    _ => {}
+                }
+            }
+        }
+
+        let body = match (chunked, length) {
+            (true, _) => read_chunks(&mut reader, stream)?,
+            (false, Some(x)) => {
+                let mut buf = vec![0; x];
+                reader.read_exact(&mut buf)?;
+                String::from_utf8_lossy(&buf).to_string()
+            }
+            (false, None) if stream => {
+                let mut line = String::new();
+                reader.read_line(&mut line)?;
+                line
+            }
+            (false, None) => {
+                let mut buf = String::new();
+                reader.read_to_string(&mut buf)?;
+                buf
+            }
+        };
+
+        match code {
+            200..300 => Ok(body),
+            401 => Err(anyhow!("Clash api rejected the secret")),
+            x => Err(anyhow!("Clash api {method} {path}: {x} {}", body.trim())),
+        }
+    }
+}
+
+/// Chunked body, with `first_line` stops after the first full line
+fn read_chunks(reader: &mut impl BufRead, first_line: bool) -> Result<String> {
+    let mut body = String::new();
+    loop {
+        let mut size = String::new();
+        reader.read_line(&mut size)?;
+        let size = usize::from_str_radix(size.trim().split(';').next().unwrap_or_default(), 16)
+            .context("Incorrect chunk size")?;
+        if size == 0 {
+            return Ok(body);
+        }
+
+        let mut buf = vec![0; size + 2];
+        reader.read_exact(&mut buf)?;
+        body.push_str(&String::from_utf8_lossy(&buf[..size]));
+        if first_line && body.contains('\n') {
+            return Ok(body.lines().next().unwrap_or_default().to_string());
+        }
+    }
+}
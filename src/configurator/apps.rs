@@ -0,0 +1,191 @@
+use anyhow::{Result, anyhow};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::configurator::inbound::tun::TunConfig;
+use crate::configurator::route::routerule::{DefaultRouteRule, RouteRule, RuleAction};
+
+/// Application matched by an app rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AppTarget {
+    /// Process name, for example `firefox`
+    Name(String),
+    /// Absolute path of executable
+    Path(String),
+    Uid(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AppPolicy {
+    /// Send traffic to direct outbound
+    Bypass,
+    /// Send traffic to proxy outbound
+    Proxy,
+}
+
+/// Route rule which matches one application and sends it to an outbound
+#[derive(Debug, Clone)]
+pub struct AppRule {
+    pub target: AppTarget,
+    pub policy: AppPolicy,
+    pub outbound: String,
+}
+
+impl AppTarget {
+    pub fn to_route_rule(&self, outbound: String) -> DefaultRouteRule {
+        let rule = DefaultRouteRule::route_action(outbound);
+        match self {
+            AppTarget::Name(x) => rule.add_process_name(x),
+            AppTarget::Path(x) => rule.add_process_path(x),
+            AppTarget::Uid(x) => rule.add_user_id(*x),
+        }
+    }
+
+    pub fn uid(&self) -> Option<u32> {
+        match self {
+            AppTarget::Uid(x) => Some(*x),
+            _ => None,
+        }
+    }
+}
+
+/// Plain name or absolute path
+impl FromStr for AppTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(anyhow!("Application name is empty"));
+        }
+        if s.starts_with('/') {
+            Ok(AppTarget::Path(s.to_string()))
+        } else if s.contains('/') {
+            Err(anyhow!("'{s}' is not a process name or an absolute path"))
+        } else {
+            Ok(AppTarget::Name(s.to_string()))
+        }
+    }
+}
+
+impl fmt::Display for AppTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppTarget::Name(x) => write!(f, "process {x}"),
+            AppTarget::Path(x) => write!(f, "path {x}"),
+            AppTarget::Uid(x) => write!(f, "uid {x}"),
+        }
+    }
+}
+
+impl AppPolicy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AppPolicy::Bypass => "bypass",
+            AppPolicy::Proxy => "proxy",
+        }
+    }
+}
+
+impl AppRule {
+    /// Recognizes route rules with route action and a single process name,
+    /// process path or user id condition
+    pub fn from_route_rule(rule: &RouteRule, direct: Option<&str>) -> Option<Self> {
+        let RouteRule::Default(rule) = rule else {
+            return None;
+        };
+        let Some(RuleAction::Route(action)) = &rule.action else {
+            return None;
+        };
+
+        // `action`, `outbound` and route options belong to the action, not the condition
+        let action_value = serde_json::to_value(rule.action.as_ref()?).ok()?;
+        let action_keys = action_value.as_object()?;
+        let value = serde_json::to_value(rule).ok()?;
+        let keys: Vec<&String> = value
+            .as_object()?
+            .keys()
+            .filter(|x| !action_keys.contains_key(*x))
+            .collect();
+        if keys.len() != 1 {
+            return None;
+        }
+
+        let target = match keys[0].as_str() {
+            "process_name" => AppTarget::Name(single(&rule.process_name)?.clone()),
+            "process_path" => AppTarget::Path(single(&rule.process_path)?.clone()),
+            "user_id" => AppTarget::Uid(*single(&rule.user_id)?),
+            _ => return None,
+        };
+
+        let policy = if direct == Some(action.outbound.as_str()) {
+            AppPolicy::Bypass
+        } else {
+            AppPolicy::Proxy
+        };
+
+        Some(Self {
+            target,
+            policy,
+            outbound: action.outbound.clone(),
+        })
+    }
+}
+
+impl fmt::Display for AppRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:8}{} -> {}",
+            self.policy.name(),
+            self.target,
+            self.outbound
+        )
+    }
+}
+
+fn single<T>(list: &Option<Vec<T>>) -> Option<&T> {
+    match list.as_deref() {
+        Some([x]) => Some(x),
+        _ => None,
+    }
+}
+
+fn remove_uid(list: &mut Option<Vec<u32>>, uid: u32) {
+    if let Some(x) = list {
+        x.retain(|v| *v != uid);
+        if x.is_empty() {
+            *list = None;
+        }
+    }
+}
+
+fn push_uid(list: &mut Option<Vec<u32>>, uid: u32) {
+    let x = list.get_or_insert_with(Vec::new);
+    if !x.contains(&uid) {
+        x.push(uid);
+    }
+}
+
+/// Keeps tun uid filters in line with the uid rule: bypassed uids never
+/// enter tun, proxied uids are kept in `include_uid` when it is used
+pub fn apply_uid_filter(tun: &mut TunConfig, uid: u32, policy: AppPolicy) {
+    match policy {
+        AppPolicy::Bypass => {
+            remove_uid(&mut tun.include_uid, uid);
+            push_uid(&mut tun.exclude_uid, uid);
+        }
+        AppPolicy::Proxy => {
+            remove_uid(&mut tun.exclude_uid, uid);
+            if tun.include_uid.is_some() {
+                push_uid(&mut tun.include_uid, uid);
+            }
+        }
+    }
+}
+
+/// Drops uid from both tun uid filters when its rule is removed
+pub fn clear_uid_filter(tun: &mut TunConfig, uid: u32) {
+    remove_uid(&mut tun.include_uid, uid);
+    remove_uid(&mut tun.exclude_uid, uid);
+}
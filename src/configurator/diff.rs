@@ -0,0 +1,207 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::Configurator;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// One difference between two configs
+#[derive(Debug, Clone, Serialize)]
+pub struct Change {
+    /// `route`, `dns`, `outbounds` or `other`
+    pub section: &'static str,
+    pub kind: ChangeKind,
+    pub item: String,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mark = match self.kind {
+            ChangeKind::Added => '+',
+            ChangeKind::Removed => '-',
+            ChangeKind::Changed => '~',
+        };
+        write!(f, "{:10}{mark} {}", self.section, self.item)
+    }
+}
+
+/// Changes of route, dns and outbounds from `old` to `new`, rules are compared in order
+pub fn diff(old: &Configurator, new: &Configurator) -> Vec<Change> {
+    let mut res = vec![];
+
+    let rules =
+        |x: &Configurator| -> Vec<String> { x.route.rules.iter().map(|x| x.to_string()).collect() };
+    let rule_sets =
+        |x: &Configurator| tagged(x.route.get_rule_sets().iter().map(|x| (x.get_tag(), x)));
+    list_diff("route", &rules(old), &rules(new), &mut res);
+    option_diff("route", &old.route.default, &new.route.default, &mut res);
+    tagged_diff(
+        "route",
+        "rule set",
+        &rule_sets(old),
+        &rule_sets(new),
+        &mut res,
+    );
+
+    let rules = |x: &Configurator| -> Vec<String> {
+        x.dns.get_rules().iter().map(|x| x.to_string()).collect()
+    };
+    let servers = |x: &Configurator| tagged(x.dns.servers.iter().map(|x| (Some(x.get_tag()), x)));
+    list_diff("dns", &rules(old), &rules(new), &mut res);
+    option_diff("dns", &old.dns.default, &new.dns.default, &mut res);
+    tagged_diff("dns", "server", &servers(old), &servers(new), &mut res);
+
+    let outbounds =
+        |x: &Configurator| tagged(x.outbounds.servers.iter().map(|x| (Some(x.get_tag()), x)));
+    tagged_diff("outbounds", "", &outbounds(old), &outbounds(new), &mut res);
+
+    // Sections without semantic diff are reported by name
+    let (old_tree, new_tree) = (old.to_tree().ok(), new.to_tree().ok());
+    for section in ["relation", "log", "inbounds", "experimental"] {
+        let value = |x: &Option<Value>| x.as_ref().and_then(|x| x.get(section)).cloned();
+        if value(&old_tree) != value(&new_tree) {
+            res.push(Change {
+                section: "other",
+                kind: ChangeKind::Changed,
+                item: section.to_string(),
+            });
+        }
+    }
+    res
+}
+
+/// Items by tag as json, untagged ones are keyed by position
+fn tagged<'a, T: Serialize + 'a>(
+    items: impl Iterator<Item = (Option<String>, &'a T)>,
+) -> BTreeMap<String, Value> {
+    items
+        .enumerate()
+        .map(|(i, (tag, x))| {
+            (
+                tag.unwrap_or_else(|| format!("#{}", i + 1)),
+                serde_json::to_value(x).unwrap_or_default(),
+            )
+        })
+        .collect()
+}
+
+fn tagged_diff(
+    section: &'static str,
+    kind: &str,
+    old: &BTreeMap<String, Value>,
+    new: &BTreeMap<String, Value>,
+    res: &mut Vec<Change>,
+) {
+    let name = |tag: &str| match kind {
+        "" => tag.to_string(),
+        _ => format!("{kind} {tag}"),
+    };
+    for (tag, x) in old {
+        match new.get(tag) {
+            None => res.push(Change {
+                section,
+                kind: ChangeKind::Removed,
+                item: name(tag),
+            }),
+            Some(y) => {
+                let mut fields = vec![];
+                value_diff("", x, y, &mut fields);
+                for field in fields {
+                    res.push(Change {
+                        section,
+                        kind: ChangeKind::Changed,
+                        item: format!("{}: {field}", name(tag)),
+                    });
+                }
+            }
+        }
+    }
+    for tag in new.keys().filter(|x| !old.contains_key(*x)) {
+        res.push(Change {
+            section,
+            kind: ChangeKind::Added,
+            item: name(tag),
+        });
+    }
+}
+
+/// Changed leaves as `path: old -> new`
+fn value_diff(path: &str, old: &Value, new: &Value, res: &mut Vec<String>) {
+    let join = |key: &str| match path {
+        "" => key.to_string(),
+        _ => format!("{path}.{key}"),
+    };
+    match (old, new) {
+        (Value::Object(x), Value::Object(y)) => {
+            for (key, a) in x {
+                value_diff(&join(key), a, y.get(key).unwrap_or(&Value::Null), res);
+            }
+            for (key, b) in y.iter().filter(|(key, _)| !x.contains_key(*key)) {
+                value_diff(&join(key), &Value::Null, b, res);
+            }
+        }
+        (x, y) if x != y => res.push(format!("{path}: {x} -> {y}")),
+        _ => {}
+    }
+}
+
+/// Change of `final`
+fn option_diff(
+    section: &'static str,
+    old: &Option<String>,
+    new: &Option<String>,
+    res: &mut Vec<Change>,
+) {
+    if old != new {
+        let show = |x: &Option<String>| x.clone().unwrap_or_else(|| "none".to_string());
+        res.push(Change {
+            section,
+            kind: ChangeKind::Changed,
+            item: format!("final: {} -> {}", show(old), show(new)),
+        });
+    }
+}
+
+/// Removed and added items by longest common subsequence, numbers are 1-based
+fn list_diff(section: &'static str, old: &[String], new: &[String], res: &mut Vec<Change>) {
+    let (n, m) = (old.len(), new.len());
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = match old[i] == new[j] {
+                true => lcs[i + 1][j + 1] + 1,
+                false => lcs[i + 1][j].max(lcs[i][j + 1]),
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old[i] == new[j] {
+            i += 1;
+            j += 1;
+        } else if i < n && (j == m || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            res.push(Change {
+                section,
+                kind: ChangeKind::Removed,
+                item: format!("[{:2}]: {}", i + 1, old[i]),
+            });
+            i += 1;
+        } else {
+            res.push(Change {
+                section,
+                kind: ChangeKind::Added,
+                item: format!("[{:2}]: {}", j + 1, new[j]),
+            });
+            j += 1;
+        }
+    }
+}
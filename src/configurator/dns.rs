@@ -2,16 +2,20 @@ use serde::{Deserialize, Serialize};
 pub mod dnsrule;
 pub mod dnsruleaction;
 pub mod dnsserver;
+pub mod dnsurl;
+pub mod fakeip;
 
+use anyhow::{Context, Result, anyhow};
 use macros::auto_skip_none;
 
+use dnsrule::DnsRule;
 use dnsserver::*;
 
 #[auto_skip_none]
 #[derive(Serialize, Deserialize, Default)]
 pub struct DnsConfig {
     pub servers: Vec<DnsServer>,
-    pub rules: Option<Vec<String>>,
+    pub rules: Option<Vec<DnsRule>>,
     #[serde(rename = "final")]
     pub default: Option<String>,
     pub strategy: Option<String>,
@@ -101,6 +105,22 @@ impl DnsConfig {
         self
     }
 
+    pub fn get_tags(&self) -> Vec<String> {
+        self.servers.iter().map(|x| x.get_tag()).collect()
+    }
+
+    /// `tag`, `tag-2`, `tag-3`... first one which is not used yet
+    pub fn unique_tag(&self, tag: &str) -> String {
+        let tags = self.get_tags();
+        let mut res = tag.to_string();
+        let mut n = 2;
+        while tags.contains(&res) {
+            res = format!("{tag}-{n}");
+            n += 1;
+        }
+        res
+    }
+
     pub fn remove_server_by_type(&mut self, name: &str) -> &mut Self {
         self.servers.retain(|x| x.get_type() != name);
         self
@@ -120,18 +140,46 @@ impl DnsConfig {
         self.servers.insert(to, value);
     }
 
-    pub fn remove_rule(&mut self, index: usize) -> String {
-        match self.rules {
-            Some(_) => self.rules.as_mut().unwrap().remove(index),
-            None => "".to_string(),
+    pub fn add_rule(&mut self, rule: DnsRule) -> &mut Self {
+        self.rules.get_or_insert_with(Vec::new).push(rule);
+        self
+    }
+
+    pub fn get_rules(&self) -> &[DnsRule] {
+        self.rules.as_deref().unwrap_or_default()
+    }
+
+    /// Errors name rules by 1-based number as `relation dns list` shows them
+    pub fn remove_rule(&mut self, index: usize) -> Result<DnsRule> {
+        let rules = self.rules.as_mut().context("There are no dns rules")?;
+        if index >= rules.len() {
+            return Err(anyhow!(
+                "There is no dns rule {}, the config has {}",
+                index + 1,
+                rules.len()
+            ));
         }
+
+        let value = rules.remove(index);
+        if rules.is_empty() {
+            self.rules = None;
+        }
+        Ok(value)
     }
 
-    pub fn move_rule(&mut self, from: usize, to: usize) {
-        let value = self.remove_rule(from);
-        if !value.is_empty() {
-            self.rules.as_mut().unwrap().insert(to, value);
+    pub fn move_rule(&mut self, from: usize, to: usize) -> Result<()> {
+        let len = self.get_rules().len();
+        if let Some(x) = [from, to].into_iter().find(|x| *x >= len) {
+            return Err(anyhow!(
+                "There is no dns rule {}, the config has {len}",
+                x + 1
+            ));
         }
+
+        let rules = self.rules.as_mut().context("There are no dns rules")?;
+        let value = rules.remove(from);
+        rules.insert(to, value);
+        Ok(())
     }
 
     pub fn clean(&mut self) -> &mut Self {
@@ -1,35 +1,90 @@
 use crate::configurator::dns::dnsruleaction::*;
+use anyhow::{Context, Result, anyhow};
 use macros::auto_skip_none;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, de};
 use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
 
 use crate::configurator::shared::Listable;
 use crate::configurator::shared::ListableString;
 use crate::configurator::shared::ListableU16;
+use crate::configurator::shared::push_listable;
+use crate::configurator::shared::{is_logical_rule, rule_error};
 
-#[derive(Debug, Serialize, Deserialize)]
+/// `outbound` value of dns rule which matches queries of every outbound
+pub const ANY_OUTBOUND: &str = "any";
+
+pub const QUERY_TYPES: &[&str] = &[
+    "A", "NS", "CNAME", "SOA", "PTR", "MX", "TXT", "AAAA", "SRV", "SVCB", "HTTPS", "ANY",
+];
+
+/// Keys accepted in rule spec, see [`DnsDefaultRule::from_spec`]
+pub const SPEC_KEYS: &[&str] = &[
+    "domain",
+    "domain_suffix",
+    "domain_keyword",
+    "domain_regex",
+    "rule_set",
+    "query_type",
+    "outbound",
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(untagged)]
 pub enum QueryType {
     Name(String),
     Code(u16),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl FromStr for QueryType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Ok(code) = s.parse::<u16>() {
+            return Ok(QueryType::Code(code));
+        }
+        let name = s.to_uppercase();
+        if QUERY_TYPES.contains(&name.as_str()) {
+            Ok(QueryType::Name(name))
+        } else {
+            Err(anyhow!("'{s}' is not a dns query type"))
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(untagged)]
 pub enum InterfaceAddress {
     Map(HashMap<String, Vec<String>>),
     List(Vec<String>),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Clone)]
 #[serde(untagged)]
 pub enum DnsRule {
-    Default(DnsDefaultRule),
     Logical(DnsLogicalRule),
+    Default(DnsDefaultRule),
+}
+
+/// Variant is picked before deserializing so errors name the field of the rule
+impl<'de> Deserialize<'de> for DnsRule {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        match is_logical_rule(&value) {
+            true => DnsLogicalRule::deserialize(&value)
+                .map(Self::Logical)
+                .map_err(|e| rule_error::<DnsLogicalRule>(&value, e)),
+            false => DnsDefaultRule::deserialize(&value)
+                .map(Self::Default)
+                .map_err(|e| rule_error::<DnsDefaultRule>(&value, e)),
+        }
+        .map_err(de::Error::custom)
+    }
 }
 
 #[auto_skip_none]
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct DnsDefaultRule {
     pub inbound: Option<ListableString>,
     pub ip_version: Option<u8>,
@@ -37,6 +92,7 @@ pub struct DnsDefaultRule {
     pub network: Option<String>,
     pub auth_user: Option<ListableString>,
     pub protocol: Option<ListableString>,
+    pub outbound: Option<ListableString>,
     pub domain: Option<ListableString>,
     pub domain_suffix: Option<ListableString>,
     pub domain_keyword: Option<ListableString>,
@@ -65,7 +121,7 @@ pub struct DnsDefaultRule {
     pub rule_set: Option<ListableString>,
     pub rule_set_ip_cidr_match_source: Option<bool>,
     pub invert: Option<bool>,
-    #[serde(flatten)]
+    #[serde(flatten, deserialize_with = "deserialize_optional")]
     pub action: Option<DnsRuleAction>,
     pub ip_cidr: Option<ListableString>,
     pub ip_is_private: Option<bool>,
@@ -79,17 +135,197 @@ impl DnsDefaultRule {
             ..Default::default()
         }
     }
+
+    pub fn add_domain(mut self, domain: &str) -> Self {
+        push_listable(&mut self.domain, domain.to_string());
+        self
+    }
+
+    pub fn add_domain_suffix(mut self, domain_suffix: &str) -> Self {
+        push_listable(&mut self.domain_suffix, domain_suffix.to_string());
+        self
+    }
+
+    pub fn add_domain_keyword(mut self, domain_keyword: &str) -> Self {
+        push_listable(&mut self.domain_keyword, domain_keyword.to_string());
+        self
+    }
+
+    pub fn add_domain_regex(mut self, domain_regex: &str) -> Self {
+        push_listable(&mut self.domain_regex, domain_regex.to_string());
+        self
+    }
+
+    pub fn add_rule_set(mut self, rule_set: &str) -> Self {
+        push_listable(&mut self.rule_set, rule_set.to_string());
+        self
+    }
+
+    pub fn add_query_type(mut self, query_type: QueryType) -> Self {
+        push_listable(&mut self.query_type, query_type);
+        self
+    }
+
+    pub fn add_outbound(mut self, outbound: &str) -> Self {
+        push_listable(&mut self.outbound, outbound.to_string());
+        self
+    }
+
+    pub fn set_action(mut self, action: DnsRuleAction) -> Self {
+        self.action = Some(action);
+        self
+    }
+
+    /// Adds condition by spec key, see [`SPEC_KEYS`]
+    pub fn add_condition(self, key: &str, value: &str) -> Result<Self> {
+        Ok(match key {
+            "domain" => self.add_domain(value),
+            "domain_suffix" | "suffix" => self.add_domain_suffix(value),
+            "domain_keyword" | "keyword" => self.add_domain_keyword(value),
+            "domain_regex" | "regex" => self.add_domain_regex(value),
+            "rule_set" => self.add_rule_set(value),
+            "query_type" => self.add_query_type(value.parse()?),
+            "outbound" => self.add_outbound(value),
+            x => return Err(anyhow!("Unknown dns rule condition '{x}'")),
+        })
+    }
+
+    /// Parses rule in format `<KEY>=<VALUE>[,<VALUE>] ... -> <ACTION>`
+    ///
+    /// ACTION:
+    /// `<SERVER TAG>` or `route <SERVER TAG>`
+    /// `reject [default|drop]`
+    /// `predefined <RCODE> [<ANSWER> ...]`
+    ///
+    /// Example: `domain_suffix=corp.local,lan query_type=A -> dns-corp`
+    pub fn from_spec(spec: &str) -> Result<Self> {
+        let (conditions, action) = spec
+            .split_once("->")
+            .context("Dns rule has no action, use '<conditions> -> <action>'")?;
+
+        let mut rule = Self::new();
+        for item in conditions.split_whitespace() {
+            let (key, values) = item
+                .split_once('=')
+                .with_context(|| format!("'{item}' is not <key>=<value>"))?;
+            for value in values.split(',').filter(|x| !x.is_empty()) {
+                rule = rule.add_condition(key, value)?;
+            }
+        }
+
+        let action: Vec<&str> = action.split_whitespace().collect();
+        let action = match action.as_slice() {
+            ["reject"] => DnsRuleAction::Reject(DnsRejectAction::new()),
+            ["reject", method] => DnsRuleAction::Reject(DnsRejectAction::new().set_method(method)),
+            ["predefined", rcode, answers @ ..] => {
+                let mut action = DnsPredefinedAction::new().set_rcode(rcode);
+                for answer in answers {
+                    action = action.add_answer(answer);
+                }
+                DnsRuleAction::Predefined(action)
+            }
+            ["route", server] | [server] => {
+                DnsRuleAction::Route(DnsRouteAction::with_server(server))
+            }
+            _ => return Err(anyhow!("Incorrect dns rule action")),
+        };
+
+        Ok(rule.set_action(action))
+    }
+
+    pub fn has_conditions(&self) -> bool {
+        serde_json::to_value(self)
+            .ok()
+            .and_then(|x| {
+                x.as_object()
+                    .map(|map| map.keys().any(|key| !is_action_key(key)))
+            })
+            .unwrap_or(false)
+    }
+}
+
+fn is_action_key(key: &str) -> bool {
+    matches!(
+        key,
+        "action"
+            | "server"
+            | "strategy"
+            | "disable_cache"
+            | "rewrite_ttl"
+            | "client_subnet"
+            | "method"
+            | "no_drop"
+            | "rcode"
+            | "answer"
+            | "ns"
+            | "extra"
+    )
+}
+
+fn format_value(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(x) => x.clone(),
+        serde_json::Value::Array(x) => x.iter().map(format_value).collect::<Vec<_>>().join(","),
+        x => x.to_string(),
+    }
+}
+
+/// Short one line form: `domain_suffix=corp.local -> route dns-corp`
+impl fmt::Display for DnsRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (conditions, action) = match self {
+            DnsRule::Default(x) => {
+                let value = serde_json::to_value(x).map_err(|_| fmt::Error)?;
+                let conditions: Vec<String> = value
+                    .as_object()
+                    .map(|map| {
+                        map.iter()
+                            .filter(|(key, _)| !is_action_key(key))
+                            .map(|(key, value)| format!("{key}={}", format_value(value)))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                (conditions.join(" "), x.action.as_ref())
+            }
+            DnsRule::Logical(x) => (
+                format!(
+                    "{}({} rules)",
+                    x.mode.as_deref().unwrap_or("and"),
+                    x.rules.len()
+                ),
+                x.action.as_ref(),
+            ),
+        };
+
+        let action = match action {
+            Some(DnsRuleAction::Route(x)) => {
+                format!("route {}", x.server.as_deref().unwrap_or_default())
+            }
+            Some(DnsRuleAction::Reject(x)) => {
+                format!("reject {}", x.method.as_deref().unwrap_or("default"))
+            }
+            Some(DnsRuleAction::Predefined(x)) => format!(
+                "predefined {} {}",
+                x.rcode.as_deref().unwrap_or("NOERROR"),
+                x.answer.as_deref().unwrap_or_default().join(" ")
+            ),
+            Some(x) => x.name().to_string(),
+            None => "route".to_string(),
+        };
+
+        write!(f, "{conditions} -> {}", action.trim_end())
+    }
 }
 
 #[auto_skip_none]
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct DnsLogicalRule {
     #[serde(rename = "type")]
     pub rule_type: Option<String>,
     pub mode: Option<String>,
-    pub rules: Option<Vec<DnsRule>>,
+    pub rules: Vec<DnsRule>,
     pub invert: Option<bool>,
-    #[serde(flatten)]
+    #[serde(flatten, deserialize_with = "deserialize_optional")]
     pub action: Option<DnsRuleAction>,
 }
 
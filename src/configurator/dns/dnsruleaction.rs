@@ -1,8 +1,13 @@
 use macros::auto_skip_none;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, de};
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(untagged)]
+pub const REJECT_METHODS: &[&str] = &["default", "drop"];
+pub const RCODES: &[&str] = &[
+    "NOERROR", "FORMERR", "SERVFAIL", "NXDOMAIN", "NOTIMP", "REFUSED",
+];
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "action", rename_all = "kebab-case")]
 pub enum DnsRuleAction {
     Route(DnsRouteAction),
     RouteOptions(DnsRouteOptionsAction),
@@ -10,8 +15,61 @@ pub enum DnsRuleAction {
     Predefined(DnsPredefinedAction),
 }
 
+/// Flattened action of a rule: `None` when rule has no action fields
+/// (sub rules of logical rules)
+pub fn deserialize_optional<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<DnsRuleAction>, D::Error> {
+    let value = serde_json::Value::deserialize(deserializer)?;
+    let has_action = value.as_object().is_some_and(|map| {
+        ["action", "server", "method", "rcode"]
+            .iter()
+            .any(|x| map.contains_key(*x))
+    });
+
+    match has_action {
+        true => DnsRuleAction::deserialize(value)
+            .map(Some)
+            .map_err(de::Error::custom),
+        false => Ok(None),
+    }
+}
+
+/// `action` field selects the variant, rules without it are routed
+/// as in sing-box
+impl<'de> Deserialize<'de> for DnsRuleAction {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let action = value
+            .get("action")
+            .and_then(|x| x.as_str())
+            .unwrap_or("route")
+            .to_string();
+
+        match action.as_str() {
+            "route" => DnsRouteAction::deserialize(value).map(Self::Route),
+            "route-options" => DnsRouteOptionsAction::deserialize(value).map(Self::RouteOptions),
+            "reject" => DnsRejectAction::deserialize(value).map(Self::Reject),
+            "predefined" => DnsPredefinedAction::deserialize(value).map(Self::Predefined),
+            x => return Err(de::Error::custom(format!("unknown dns rule action '{x}'"))),
+        }
+        .map_err(de::Error::custom)
+    }
+}
+
+impl DnsRuleAction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DnsRuleAction::Route(_) => "route",
+            DnsRuleAction::RouteOptions(_) => "route-options",
+            DnsRuleAction::Reject(_) => "reject",
+            DnsRuleAction::Predefined(_) => "predefined",
+        }
+    }
+}
+
 #[auto_skip_none]
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct DnsRouteAction {
     pub server: Option<String>,
     pub strategy: Option<String>,
@@ -26,10 +84,17 @@ impl DnsRouteAction {
             ..Default::default()
         }
     }
+
+    pub fn with_server(server: &str) -> Self {
+        Self {
+            server: Some(server.to_string()),
+            ..Default::default()
+        }
+    }
 }
 
 #[auto_skip_none]
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct DnsRouteOptionsAction {
     pub disable_cache: Option<bool>,
     pub rewrite_ttl: Option<u32>,
@@ -45,7 +110,7 @@ impl DnsRouteOptionsAction {
 }
 
 #[auto_skip_none]
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct DnsRejectAction {
     pub method: Option<String>,
     pub no_drop: Option<bool>,
@@ -57,10 +122,15 @@ impl DnsRejectAction {
             ..Default::default()
         }
     }
+
+    pub fn set_method(mut self, method: &str) -> Self {
+        self.method = Some(method.to_string());
+        self
+    }
 }
 
 #[auto_skip_none]
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct DnsPredefinedAction {
     pub rcode: Option<String>,
     pub answer: Option<Vec<String>>,
@@ -74,4 +144,16 @@ impl DnsPredefinedAction {
             ..Default::default()
         }
     }
+
+    pub fn set_rcode(mut self, rcode: &str) -> Self {
+        self.rcode = Some(rcode.to_string());
+        self
+    }
+
+    pub fn add_answer(mut self, answer: &str) -> Self {
+        self.answer
+            .get_or_insert_with(Vec::new)
+            .push(answer.to_string());
+        self
+    }
 }
@@ -70,6 +70,37 @@ impl DnsServer {
             DnsServer::Resolved(s) => s.get_tag(),
         }
     }
+
+    pub fn set_tag(&mut self, tag: String) -> &mut Self {
+        match self {
+            DnsServer::Local(s) => s.tag = tag,
+            DnsServer::Hosts(s) => s.tag = tag,
+            DnsServer::Tcp(s) => s.tag = tag,
+            DnsServer::Udp(s) => s.tag = tag,
+            DnsServer::Tls(s) => s.tag = tag,
+            DnsServer::Quic(s) => s.tag = tag,
+            DnsServer::Https(s) => s.tag = tag,
+            DnsServer::Http3(s) => s.tag = tag,
+            DnsServer::Dhcp(s) => s.tag = tag,
+            DnsServer::FakeIp(s) => s.tag = tag,
+            DnsServer::Tailscale(s) => s.tag = tag,
+            DnsServer::Resolved(s) => s.tag = tag,
+        }
+        self
+    }
+
+    pub fn get_dial_ref(&self) -> Option<&DialFields> {
+        match self {
+            DnsServer::Tcp(s) => s.dial.as_ref(),
+            DnsServer::Udp(s) => s.dial.as_ref(),
+            DnsServer::Tls(s) => s.dial.as_ref(),
+            DnsServer::Quic(s) => s.dial.as_ref(),
+            DnsServer::Https(s) => s.dial.as_ref(),
+            DnsServer::Http3(s) => s.dial.as_ref(),
+            DnsServer::Dhcp(s) => s.dial.as_ref(),
+            _ => None,
+        }
+    }
 }
 
 #[auto_skip_none]
@@ -140,7 +171,7 @@ pub struct DnsServerTcp {
 impl DnsServerTcp {
     pub fn new() -> Self {
         Self {
-            tag: "dns-udp".to_string(),
+            tag: "dns-tcp".to_string(),
             server: "8.8.8.8".to_string(),
             ..Default::default()
         }
@@ -148,7 +179,7 @@ impl DnsServerTcp {
 
     pub fn with_server(server: String, server_port: Option<u16>) -> Self {
         Self {
-            tag: "dns-udp".to_string(),
+            tag: "dns-tcp".to_string(),
             server,
             server_port,
             ..Default::default()
@@ -377,12 +408,10 @@ impl DnsServerDhcp {
 #[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct DnsServerFakeIp {
     pub tag: String,
-    pub address: String,
     pub inet4_range: Option<String>,
     pub inet6_range: Option<String>,
 }
 
-// TODO find out how to set up
 impl DnsServerFakeIp {
     pub fn new() -> Self {
         Self {
@@ -0,0 +1,262 @@
+use anyhow::{Context, Result, anyhow};
+use std::net::IpAddr;
+use url::{Host, Url};
+
+use crate::configurator::dns::dnsserver::*;
+use crate::configurator::shared::ListableString;
+use crate::configurator::shared::cidr::Cidr;
+use crate::configurator::shared::dialfields::DialFields;
+use crate::configurator::shared::tls::TlsConfig;
+
+pub const SCHEMES: &[&str] = &[
+    "udp", "tcp", "tls", "quic", "https", "h3", "dhcp", "fakeip", "hosts", "local",
+];
+
+pub const FAKEIP_INET4_RANGE: &str = "198.18.0.0/15";
+pub const FAKEIP_INET6_RANGE: &str = "fc00::/18";
+
+/// Options from the query part of dns url
+#[derive(Default)]
+struct DnsOptions {
+    tag: Option<String>,
+    detour: Option<String>,
+    resolver: Option<String>,
+    sni: Option<String>,
+    insecure: Option<bool>,
+    inet4: Option<String>,
+    inet6: Option<String>,
+}
+
+impl DnsOptions {
+    fn parse(url: &Url) -> Result<Self> {
+        let mut res = Self::default();
+        for (key, value) in url.query_pairs() {
+            let value = value.to_string();
+            if value.is_empty() {
+                return Err(anyhow!("Option '{key}' has no value"));
+            }
+            match key.as_ref() {
+                "tag" => res.tag = Some(value),
+                "detour" => res.detour = Some(value),
+                "resolver" => res.resolver = Some(value),
+                "sni" => res.sni = Some(value),
+                "insecure" => {
+                    res.insecure = Some(
+                        value
+                            .parse()
+                            .with_context(|| format!("'{value}' is not true or false"))?,
+                    )
+                }
+                "inet4" => res.inet4 = Some(value),
+                "inet6" => res.inet6 = Some(value),
+                x => return Err(anyhow!("Unknown dns option '{x}'")),
+            }
+        }
+        Ok(res)
+    }
+
+    fn dial(&self) -> Option<DialFields> {
+        if self.detour.is_none() && self.resolver.is_none() {
+            return None;
+        }
+        let mut dial = DialFields::new();
+        dial.detour = self.detour.clone();
+        dial.domain_resolver = self.resolver.clone();
+        Some(dial)
+    }
+
+    fn tls(&self) -> Option<TlsConfig> {
+        if self.sni.is_none() && self.insecure.is_none() {
+            return None;
+        }
+        let mut tls = TlsConfig::new();
+        tls.server_name = self.sni.clone();
+        tls.insecure = self.insecure;
+        Some(tls)
+    }
+
+    /// Options which make no sense for the server type
+    fn check_unused(&self, scheme: &str) -> Result<()> {
+        let tls = matches!(scheme, "tls" | "quic" | "https" | "h3");
+        let dial = !matches!(scheme, "fakeip" | "hosts" | "local");
+        let unused = [
+            ("detour", self.detour.is_some() && !dial),
+            ("resolver", self.resolver.is_some() && !dial),
+            ("sni", self.sni.is_some() && !tls),
+            ("insecure", self.insecure.is_some() && !tls),
+            ("inet4", self.inet4.is_some() && scheme != "fakeip"),
+            ("inet6", self.inet6.is_some() && scheme != "fakeip"),
+        ];
+        match unused.iter().find(|x| x.1) {
+            Some((name, _)) => Err(anyhow!("Option '{name}' is not supported by {scheme}")),
+            None => Ok(()),
+        }
+    }
+}
+
+fn host(url: &Url) -> Result<String> {
+    match url.host() {
+        Some(Host::Domain(x)) if !x.is_empty() => Ok(x.to_string()),
+        Some(Host::Ipv4(x)) => Ok(x.to_string()),
+        Some(Host::Ipv6(x)) => Ok(x.to_string()),
+        _ => Err(anyhow!("Dns server address is empty")),
+    }
+}
+
+fn check_range(value: &str, v6: bool) -> Result<String> {
+    let cidr: Cidr = value.parse()?;
+    if cidr.addr.is_ipv6() != v6 {
+        return Err(anyhow!(
+            "'{value}' is not an ipv{} range",
+            if v6 { 6 } else { 4 }
+        ));
+    }
+    Ok(cidr.to_string())
+}
+
+/// Parses dns server from url-style string:
+///
+/// `udp://8.8.8.8:53`, `tcp://1.1.1.1`, `tls://dns.google`,
+/// `quic://dns.adguard-dns.com`, `https://1.1.1.1/dns-query`,
+/// `h3://dns.google/dns-query`, `dhcp://eth0` (`dhcp://auto` for default
+/// interface), `fakeip`, `hosts`, `hosts:/etc/hosts`, `local`.
+/// Plain address is udp server.
+///
+/// Query options:
+/// `tag`      -> server tag
+/// `detour`   -> outbound tag used to connect
+/// `resolver` -> dns server tag used to resolve server domain
+/// `sni`, `insecure` -> tls settings of tls/quic/https/h3
+/// `inet4`, `inet6`  -> fakeip ranges
+pub fn parse_dns_url(input: &str) -> Result<DnsServer> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(anyhow!("Dns server is empty"));
+    }
+
+    // Schemes are case insensitive, `HTTPS://` is https
+    let prefix = SCHEMES.iter().copied().find(|x| {
+        input
+            .get(..x.len())
+            .filter(|head| head.eq_ignore_ascii_case(x))
+            .map(|_| &input[x.len()..])
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with([':', '?']))
+    });
+
+    let (scheme, url) = match prefix {
+        Some(scheme) => {
+            let body = &input[scheme.len()..];
+            let body = body.strip_prefix(':').unwrap_or(body);
+            // `hosts:/etc/hosts` and `fakeip` have no authority
+            match body.starts_with("//") {
+                true => (scheme, Url::parse(&format!("{scheme}:{body}"))),
+                false => (scheme, Url::parse(&format!("{scheme}://{body}"))),
+            }
+        }
+        None => match input.parse::<IpAddr>() {
+            Ok(IpAddr::V6(ip)) => ("udp", Url::parse(&format!("udp://[{ip}]"))),
+            _ => ("udp", Url::parse(&format!("udp://{input}"))),
+        },
+    };
+    let url = url.with_context(|| format!("'{input}' is not a dns url"))?;
+
+    let opts = DnsOptions::parse(&url)?;
+    opts.check_unused(scheme)?;
+
+    let server_port = url.port();
+    if server_port == Some(0) {
+        return Err(anyhow!("Port of '{input}' is out of range"));
+    }
+
+    let path = match url.path() {
+        "" | "/" => None,
+        x => Some(x.to_string()),
+    };
+    if path.is_some() && !matches!(scheme, "https" | "h3" | "hosts") {
+        return Err(anyhow!("{scheme} dns server has no path"));
+    }
+    if server_port.is_some() && matches!(scheme, "dhcp" | "fakeip" | "hosts" | "local") {
+        return Err(anyhow!("{scheme} dns server has no port"));
+    }
+
+    let mut server = match scheme {
+        "udp" => DnsServer::Udp(DnsServerUdp {
+            dial: opts.dial(),
+            ..DnsServerUdp::with_server(host(&url)?, server_port)
+        }),
+        "tcp" => DnsServer::Tcp(DnsServerTcp {
+            dial: opts.dial(),
+            ..DnsServerTcp::with_server(host(&url)?, server_port)
+        }),
+        "tls" => DnsServer::Tls(DnsServerTls {
+            tls: opts.tls(),
+            dial: opts.dial(),
+            ..DnsServerTls::with_server(host(&url)?, server_port)
+        }),
+        "quic" => DnsServer::Quic(DnsServerQuic {
+            tls: opts.tls(),
+            dial: opts.dial(),
+            ..DnsServerQuic::with_server(host(&url)?, server_port)
+        }),
+        "https" => DnsServer::Https(DnsServerHttps {
+            path,
+            tls: opts.tls(),
+            dial: opts.dial(),
+            ..DnsServerHttps::with_server(host(&url)?, server_port)
+        }),
+        "h3" => DnsServer::Http3(DnsServerHttp3 {
+            path,
+            tls: opts.tls(),
+            dial: opts.dial(),
+            ..DnsServerHttp3::with_server(host(&url)?, server_port)
+        }),
+        "dhcp" => {
+            let server = match host(&url) {
+                Ok(x) if x != "auto" => DnsServerDhcp::with_interface(&x),
+                _ => DnsServerDhcp::new(),
+            };
+            DnsServer::Dhcp(DnsServerDhcp {
+                dial: opts.dial(),
+                ..server
+            })
+        }
+        "fakeip" => DnsServer::FakeIp(DnsServerFakeIp::new().add_ips((
+            check_range(opts.inet4.as_deref().unwrap_or(FAKEIP_INET4_RANGE), false)?,
+            check_range(opts.inet6.as_deref().unwrap_or(FAKEIP_INET6_RANGE), true)?,
+        ))),
+        "hosts" => {
+            let mut server = DnsServerHosts::new();
+            if url.host().is_some() {
+                return Err(anyhow!("Use hosts:/path/to/file for hosts dns server"));
+            }
+            server.path = path.map(ListableString::One);
+            DnsServer::Hosts(server)
+        }
+        "local" => DnsServer::Local(DnsServerLocal::new()),
+        x => return Err(anyhow!("Cant parse {x} dns server")),
+    };
+
+    if let Some(tag) = opts.tag {
+        server.set_tag(tag);
+    }
+    Ok(server)
+}
+
+/// Builds dns url from separate type, address and port values,
+/// address may contain path and query options
+pub fn build_dns_url(scheme: &str, address: Option<&str>, port: Option<&str>) -> String {
+    let address = address.unwrap_or_default();
+    match scheme {
+        "fakeip" | "local" => format!("{scheme}{address}"),
+        "hosts" if address.is_empty() => scheme.to_string(),
+        "hosts" => format!("hosts:{address}"),
+        _ => {
+            let index = address.find(['/', '?']).unwrap_or(address.len());
+            let (host, rest) = address.split_at(index);
+            match port {
+                Some(port) if !port.is_empty() => format!("{scheme}://{host}:{port}{rest}"),
+                _ => format!("{scheme}://{host}{rest}"),
+            }
+        }
+    }
+}
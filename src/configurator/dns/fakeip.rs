@@ -0,0 +1,70 @@
+use crate::configurator::dns::dnsrule::{DnsDefaultRule, QueryType};
+use crate::configurator::dns::dnsruleaction::{DnsRouteAction, DnsRuleAction};
+use crate::configurator::route::routerule::{RouteRule, RuleAction};
+
+/// Route rule keys which can be copied to dns rule as is
+const DOMAIN_KEYS: &[&str] = &[
+    "domain",
+    "domain_suffix",
+    "domain_keyword",
+    "domain_regex",
+    "rule_set",
+];
+
+/// Sends A and AAAA queries to fakeip server
+pub fn fakeip_rule(server: &str) -> DnsDefaultRule {
+    DnsDefaultRule::new()
+        .add_query_type(QueryType::Name("A".to_string()))
+        .add_query_type(QueryType::Name("AAAA".to_string()))
+        .set_action(DnsRuleAction::Route(DnsRouteAction::with_server(server)))
+}
+
+/// Collects domains of route rules with `direct` outbound into one dns rule,
+/// so these domains keep resolving to real addresses.
+/// Rules with other conditions are skipped, they dont send whole domain direct
+pub fn direct_domain_rule(
+    rules: &[RouteRule],
+    direct: &str,
+    server: &str,
+) -> Option<DnsDefaultRule> {
+    let mut res = DnsDefaultRule::new();
+    for rule in rules {
+        let RouteRule::Default(rule) = rule else {
+            continue;
+        };
+        match &rule.action {
+            Some(RuleAction::Route(x)) if x.outbound == direct => {}
+            _ => continue,
+        }
+
+        let Ok(serde_json::Value::Object(map)) = serde_json::to_value(rule) else {
+            continue;
+        };
+        let conditions: Vec<(&String, &serde_json::Value)> = map
+            .iter()
+            .filter(|(key, _)| !matches!(key.as_str(), "action" | "outbound"))
+            .collect();
+        if conditions.is_empty()
+            || conditions
+                .iter()
+                .any(|(key, _)| !DOMAIN_KEYS.contains(&key.as_str()))
+        {
+            continue;
+        }
+
+        for (key, value) in conditions {
+            let values = match value {
+                serde_json::Value::Array(x) => x.iter().filter_map(|x| x.as_str()).collect(),
+                x => x.as_str().into_iter().collect::<Vec<_>>(),
+            };
+            for value in values {
+                res = res.add_condition(key, value).ok()?;
+            }
+        }
+    }
+
+    match res.has_conditions() {
+        true => Some(res.set_action(DnsRuleAction::Route(DnsRouteAction::with_server(server)))),
+        false => None,
+    }
+}
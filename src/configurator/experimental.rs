@@ -4,6 +4,7 @@ use serde::{Deserialize, Serialize};
 #[auto_skip_none]
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct ExperimentalConfig {
+    pub cache_file: Option<CacheFileConfig>,
     pub clash_api: Option<ClashApiConfig>,
 }
 
@@ -19,6 +20,10 @@ impl ExperimentalConfig {
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct ClashApiConfig {
     pub external_controller: Option<String>,
+    pub external_ui: Option<String>,
+    pub secret: Option<String>,
+    pub default_mode: Option<String>,
+    pub access_control_allow_origin: Option<Vec<String>>,
 }
 
 impl ClashApiConfig {
@@ -27,4 +32,38 @@ impl ClashApiConfig {
             ..Default::default()
         }
     }
+
+    /// Controller on loopback, reachable only from this machine
+    pub fn local(port: u16, secret: String) -> Self {
+        Self {
+            external_controller: Some(format!("127.0.0.1:{port}")),
+            secret: Some(secret),
+            ..Default::default()
+        }
+    }
+}
+
+#[auto_skip_none]
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct CacheFileConfig {
+    pub enabled: Option<bool>,
+    pub path: Option<String>,
+    pub cache_id: Option<String>,
+    pub store_fakeip: Option<bool>,
+    pub store_rdrc: Option<bool>,
+    pub rdrc_timeout: Option<String>,
+}
+
+impl CacheFileConfig {
+    pub fn new() -> Self {
+        Self {
+            enabled: Some(true),
+            ..Default::default()
+        }
+    }
+
+    pub fn set_store_fakeip(mut self, value: bool) -> Self {
+        self.store_fakeip = Some(value);
+        self
+    }
 }
@@ -70,6 +70,20 @@ impl InboundConfig {
         self.servers.iter_mut().find(|x| x.get_tag() == tag)
     }
 
+    pub fn get_tun_ref(&self) -> Option<&TunConfig> {
+        self.servers.iter().find_map(|x| match x {
+            Inbound::Tun(cfg) => Some(cfg),
+            _ => None,
+        })
+    }
+
+    pub fn get_tun_mut(&mut self) -> Option<&mut TunConfig> {
+        self.servers.iter_mut().find_map(|x| match x {
+            Inbound::Tun(cfg) => Some(cfg),
+            _ => None,
+        })
+    }
+
     pub fn get_tag_by_type(&self, name: &str) -> Option<String> {
         self.servers
             .iter()
@@ -1,6 +1,111 @@
+use crate::configurator::shared::cidr::Cidr;
 use crate::configurator::shared::listenfields::ListenFields;
+use anyhow::{Context, Result, anyhow};
 use macros::auto_skip_none;
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+pub const STACKS: &[&str] = &["system", "gvisor", "mixed"];
+
+/// Tun fields editable from cli and tui
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TunField {
+    InterfaceName,
+    Stack,
+    Mtu,
+    Address,
+    AutoRoute,
+    StrictRoute,
+    IncludeUid,
+    ExcludeUid,
+    RouteAddress,
+    RouteExcludeAddress,
+}
+
+impl TunField {
+    pub const ALL: &'static [TunField] = &[
+        TunField::InterfaceName,
+        TunField::Stack,
+        TunField::Mtu,
+        TunField::Address,
+        TunField::AutoRoute,
+        TunField::StrictRoute,
+        TunField::IncludeUid,
+        TunField::ExcludeUid,
+        TunField::RouteAddress,
+        TunField::RouteExcludeAddress,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TunField::InterfaceName => "interface_name",
+            TunField::Stack => "stack",
+            TunField::Mtu => "mtu",
+            TunField::Address => "address",
+            TunField::AutoRoute => "auto_route",
+            TunField::StrictRoute => "strict_route",
+            TunField::IncludeUid => "include_uid",
+            TunField::ExcludeUid => "exclude_uid",
+            TunField::RouteAddress => "route_address",
+            TunField::RouteExcludeAddress => "route_exclude_address",
+        }
+    }
+}
+
+impl FromStr for TunField {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.replace('-', "_");
+        TunField::ALL
+            .iter()
+            .find(|x| x.name() == name)
+            .copied()
+            .ok_or_else(|| {
+                let names: Vec<&str> = TunField::ALL.iter().map(|x| x.name()).collect();
+                format!("unknown tun field, possible: {}", names.join(", "))
+            })
+    }
+}
+
+fn split_list(value: &str) -> Vec<&str> {
+    value
+        .split(',')
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+fn parse_cidr_list(value: &str) -> Result<Vec<String>> {
+    split_list(value)
+        .into_iter()
+        .map(|x| Ok(x.parse::<Cidr>()?.to_string()))
+        .collect()
+}
+
+fn parse_uid_list(value: &str) -> Result<Vec<u32>> {
+    split_list(value)
+        .into_iter()
+        .map(|x| x.parse().with_context(|| format!("'{x}' is not a uid")))
+        .collect()
+}
+
+fn parse_bool(value: &str) -> Result<bool> {
+    match value.trim() {
+        "true" | "on" | "yes" | "1" => Ok(true),
+        "false" | "off" | "no" | "0" => Ok(false),
+        x => Err(anyhow!("'{x}' is not a boolean")),
+    }
+}
+
+fn join_list<T: ToString>(list: &Option<Vec<T>>) -> Option<String> {
+    list.as_ref().filter(|x| !x.is_empty()).map(|x| {
+        x.iter()
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    })
+}
 
 #[auto_skip_none]
 #[derive(Serialize, Deserialize, Debug, Default)]
@@ -28,7 +133,8 @@ pub struct TunConfig {
     pub address: Vec<String>,
     pub mtu: Option<u16>,
     pub auto_route: Option<bool>,
-    pub iprote2_table_index: Option<u16>,
+    #[serde(alias = "iprote2_table_index")]
+    pub iproute2_table_index: Option<u16>,
     pub iproute_rule_index: Option<u16>,
     pub auto_redirect: Option<bool>,
     pub auto_redirect_input_mark: Option<String>,
@@ -36,7 +142,8 @@ pub struct TunConfig {
     pub auto_redirect_reset_mark: Option<String>,
     pub auto_redirect_nfqueue: Option<u16>,
     pub auto_redirect_iproute2_fallback_rule_index: Option<u16>,
-    pub exlude_mptcp: Option<bool>,
+    #[serde(alias = "exlude_mptcp")]
+    pub exclude_mptcp: Option<bool>,
     pub loopback_address: Option<Vec<String>>,
     pub strict_route: Option<bool>,
     pub route_address: Option<Vec<String>>,
@@ -48,9 +155,9 @@ pub struct TunConfig {
     pub stack: Option<String>,
     pub include_interface: Option<Vec<String>>,
     pub exclude_interface: Option<Vec<String>>,
-    pub include_uid: Option<Vec<u16>>,
+    pub include_uid: Option<Vec<u32>>,
     pub include_uid_range: Option<Vec<String>>,
-    pub exclude_uid: Option<Vec<u16>>,
+    pub exclude_uid: Option<Vec<u32>>,
     pub exclude_uid_range: Option<Vec<String>>,
     pub include_android_user: Option<Vec<u16>>,
     pub include_package: Option<Vec<String>>,
@@ -101,7 +208,7 @@ impl TunConfig {
         self
     }
 
-    /// stack: "system" | "mixed"
+    /// stack: "system" | "gvisor" | "mixed"
     pub fn set_stack(mut self, stack: String) -> Self {
         self.stack = Some(stack);
         self
@@ -110,4 +217,84 @@ impl TunConfig {
     pub fn get_tag(&self) -> String {
         self.tag.clone().expect("[ERROR] No tag")
     }
+
+    pub fn get_field(&self, field: TunField) -> Option<String> {
+        match field {
+            TunField::InterfaceName => self.interface_name.clone(),
+            TunField::Stack => self.stack.clone(),
+            TunField::Mtu => self.mtu.map(|x| x.to_string()),
+            TunField::Address => join_list(&Some(self.address.clone())),
+            TunField::AutoRoute => self.auto_route.map(|x| x.to_string()),
+            TunField::StrictRoute => self.strict_route.map(|x| x.to_string()),
+            TunField::IncludeUid => join_list(&self.include_uid),
+            TunField::ExcludeUid => join_list(&self.exclude_uid),
+            TunField::RouteAddress => join_list(&self.route_address),
+            TunField::RouteExcludeAddress => join_list(&self.route_exclude_address),
+        }
+    }
+
+    /// Validates and sets field, lists are separated by `,`
+    pub fn set_field(&mut self, field: TunField, value: &str) -> Result<&mut Self> {
+        let value = value.trim();
+        match field {
+            TunField::InterfaceName => {
+                if value.is_empty() || value.len() > 15 || value.contains(['/', ' ']) {
+                    return Err(anyhow!(
+                        "'{value}' is not an interface name (1-15 chars without '/' and spaces)"
+                    ));
+                }
+                self.interface_name = Some(value.to_string());
+            }
+            TunField::Stack => {
+                if !STACKS.contains(&value) {
+                    return Err(anyhow!(
+                        "'{value}' is not a stack, possible: {}",
+                        STACKS.join(", ")
+                    ));
+                }
+                self.stack = Some(value.to_string());
+            }
+            TunField::Mtu => {
+                let mtu: u16 = value
+                    .parse()
+                    .with_context(|| format!("'{value}' is not a mtu"))?;
+                if mtu < 576 {
+                    return Err(anyhow!("mtu {mtu} is lower than 576"));
+                }
+                self.mtu = Some(mtu);
+            }
+            TunField::Address => {
+                let address = parse_cidr_list(value)?;
+                if address.is_empty() {
+                    return Err(anyhow!("tun needs at least one address"));
+                }
+                self.address = address;
+            }
+            TunField::AutoRoute => self.auto_route = Some(parse_bool(value)?),
+            TunField::StrictRoute => self.strict_route = Some(parse_bool(value)?),
+            TunField::IncludeUid => self.include_uid = Some(parse_uid_list(value)?),
+            TunField::ExcludeUid => self.exclude_uid = Some(parse_uid_list(value)?),
+            TunField::RouteAddress => self.route_address = Some(parse_cidr_list(value)?),
+            TunField::RouteExcludeAddress => {
+                self.route_exclude_address = Some(parse_cidr_list(value)?)
+            }
+        }
+        Ok(self)
+    }
+
+    pub fn unset_field(&mut self, field: TunField) -> Result<&mut Self> {
+        match field {
+            TunField::InterfaceName => self.interface_name = None,
+            TunField::Stack => self.stack = None,
+            TunField::Mtu => self.mtu = None,
+            TunField::Address => return Err(anyhow!("tun needs at least one address")),
+            TunField::AutoRoute => self.auto_route = None,
+            TunField::StrictRoute => self.strict_route = None,
+            TunField::IncludeUid => self.include_uid = None,
+            TunField::ExcludeUid => self.exclude_uid = None,
+            TunField::RouteAddress => self.route_address = None,
+            TunField::RouteExcludeAddress => self.route_exclude_address = None,
+        }
+        Ok(self)
+    }
 }
@@ -1,10 +1,15 @@
+pub mod apps;
+pub mod diff;
 pub mod dns;
 pub mod experimental;
 pub mod inbound;
 pub mod log;
 pub mod outbound;
+pub mod preset;
+pub mod profile;
 pub mod route;
 pub mod shared;
+pub mod tree;
 
 use dns::*;
 use inbound::*;
@@ -13,12 +18,26 @@ use route::*;
 use serde::{Deserialize, Serialize};
 
 use crate::datamanager::InboundMod;
+use apps::{AppPolicy, AppRule, AppTarget};
+use dns::dnsrule::{ANY_OUTBOUND, DnsDefaultRule, DnsRule};
+use dns::dnsruleaction::{DnsRuleAction, RCODES, REJECT_METHODS};
 use dns::dnsserver::*;
-use experimental::ExperimentalConfig;
+use dns::dnsurl::{FAKEIP_INET4_RANGE, FAKEIP_INET6_RANGE, parse_dns_url};
+use experimental::{CacheFileConfig, ClashApiConfig, ExperimentalConfig};
 use inbound::tun::TunConfig;
 use log::LogConfig;
+use preset::Preset;
+use profile::ProfileMeta;
+use route::geo::{self, GeoTemplates};
 use route::routerule::DefaultRouteRule;
 use route::routerule::LogicalRouteRule;
+use route::routerule::{
+    DOMAIN_STRATEGIES, HijackDnsAction, RejectAction, ResolveAction, RouteAction, RouteOptions,
+    RouteRule, RuleAction, SNIFFERS, SniffAction,
+};
+use route::ruleexpr::{ExprAction, RuleExpr};
+use route::ruleset::RuleSet;
+use shared::cidr::Cidr;
 
 use anyhow::{Context, Result, anyhow};
 use std::fs::File;
@@ -28,26 +47,63 @@ use std::path::PathBuf;
 
 #[derive(Serialize, Deserialize)]
 pub struct Configurator {
+    /// Relation metadata, removed when config is rendered for the core
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    relation: Option<ProfileMeta>,
     log: LogConfig,
     dns: DnsConfig,
     inbounds: InboundConfig,
     outbounds: OutboundConfig,
     route: RouteConfig,
     experimental: ExperimentalConfig,
+    #[serde(skip)]
+    geo: GeoTemplates,
+    #[serde(skip)]
+    geo_report: Vec<String>,
 }
 
 impl Configurator {
     pub fn new() -> Self {
         Self {
+            relation: None,
             log: LogConfig::new(),
             dns: DnsConfig::new(),
             inbounds: InboundConfig::new(),
             outbounds: OutboundConfig::new(),
             route: RouteConfig::new(),
             experimental: ExperimentalConfig::new(),
+            geo: GeoTemplates::default(),
+            geo_report: vec![],
         }
     }
 
+    pub fn meta_ref(&self) -> Option<&ProfileMeta> {
+        self.relation.as_ref()
+    }
+
+    pub fn meta_mut(&mut self) -> &mut ProfileMeta {
+        self.relation.get_or_insert_default()
+    }
+
+    pub fn set_geo_templates(&mut self, templates: GeoTemplates) -> &mut Self {
+        self.geo = templates;
+        self
+    }
+
+    /// Replaces geosite and geoip matchers with rule sets,
+    /// returns true when config was changed
+    pub fn migrate_geo(&mut self) -> bool {
+        let report = geo::migrate(&mut self.route, &self.geo);
+        let changed = !report.is_empty();
+        self.geo_report.extend(report);
+        changed
+    }
+
+    /// Changes made by geo migration since last call
+    pub fn take_geo_report(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.geo_report)
+    }
+
     /// DNS:
     /// udp `8.8.8.8`
     /// local
@@ -100,7 +156,7 @@ impl Configurator {
     /// strict_route     `true`
     /// stack            `system`
     /// mtu              `1500`
-    /// ip               `198.18.0.1/30`
+    /// ip               `172.19.0.1/30`
     /// ROUTE:
     /// sniff            `1s`
     /// hijack port      `53`
@@ -120,7 +176,7 @@ impl Configurator {
                     .set_strict_route(true)
                     .set_stack("system".to_string())
                     .set_mtu(1500)
-                    .add_ip("198.18.0.1/30"),
+                    .add_ip("172.19.0.1/30"),
             ))
             .add_direct(None);
 
@@ -143,6 +199,18 @@ impl Configurator {
         self
     }
 
+    /// Replaces dns, inbounds and route with the preset skeleton
+    pub fn apply_preset(&mut self, preset: Preset) -> &mut Self {
+        self.dns = preset.dns;
+        self.inbounds = preset.inbounds;
+        self.route = preset.route;
+
+        if self.outbounds.get_tag_by_type("direct").is_none() {
+            self.outbounds.add_direct();
+        }
+        self
+    }
+
     pub fn set_outbound_from_url(&mut self, url: &str) -> Result<&mut Self> {
         self.outbounds.add_server_from_url(url)?;
         if self.get_inbounds_types().contains(&"tun".to_string()) {
@@ -198,6 +266,16 @@ impl Configurator {
         &self.inbounds
     }
 
+    pub fn get_tun_ref(&self) -> Option<&TunConfig> {
+        self.inbounds.get_tun_ref()
+    }
+
+    pub fn get_tun_mut(&mut self) -> Result<&mut TunConfig> {
+        self.inbounds
+            .get_tun_mut()
+            .context("Config has no tun inbound")
+    }
+
     pub fn get_outbound_ref(&self) -> &OutboundConfig {
         &self.outbounds
     }
@@ -206,12 +284,114 @@ impl Configurator {
         &self.route
     }
 
+    pub fn get_route_mut(&mut self) -> &mut RouteConfig {
+        &mut self.route
+    }
+
+    pub fn get_clash_api_ref(&self) -> Option<&ClashApiConfig> {
+        self.experimental.clash_api.as_ref()
+    }
+
+    /// Clash modes the core knows: defaults, `default_mode` and `clash_mode` of route and dns rules
+    pub fn clash_modes(&self) -> Vec<String> {
+        let mut res: Vec<String> = crate::clashapi::MODES
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        res.extend(
+            self.get_clash_api_ref()
+                .and_then(|x| x.default_mode.clone()),
+        );
+        for rules in [
+            serde_json::to_value(&self.route.rules),
+            serde_json::to_value(self.dns.get_rules()),
+        ] {
+            collect_clash_modes(&rules.unwrap_or_default(), &mut res);
+        }
+        let mut seen = std::collections::HashSet::new();
+        res.retain(|x| seen.insert(x.to_lowercase()));
+        res
+    }
+
+    /// App rules in route order
+    pub fn get_app_rules(&self) -> Vec<AppRule> {
+        let direct = self.outbounds.get_tag_by_type("direct");
+        self.route
+            .rules
+            .iter()
+            .filter_map(|x| AppRule::from_route_rule(x, direct.as_deref()))
+            .collect()
+    }
+
+    /// First rule with a final action, app rules are inserted before it
+    /// so sniff and hijack-dns rules keep working for matched apps
+    fn app_rules_position(&self) -> usize {
+        self.route
+            .rules
+            .iter()
+            .position(|rule| match rule {
+                RouteRule::Default(x) => {
+                    matches!(x.action, Some(RuleAction::Route(_) | RuleAction::Reject(_)))
+                }
+                RouteRule::Logical(x) => {
+                    matches!(x.action, Some(RuleAction::Route(_) | RuleAction::Reject(_)))
+                }
+            })
+            .unwrap_or(self.route.rules.len())
+    }
+
+    /// Routes application to direct (bypass) or proxy outbound, previous
+    /// rule of the same application is replaced. Uid rules also update
+    /// tun uid filters
+    pub fn add_app_rule(&mut self, target: AppTarget, policy: AppPolicy) -> Result<&mut Self> {
+        let outbound = match policy {
+            AppPolicy::Bypass => self
+                .outbounds
+                .get_tag_by_type("direct")
+                .context("Config has no direct outbound")?,
+            AppPolicy::Proxy => self.get_outbound_tag()?,
+        };
+
+        self.remove_app_rules(&target);
+        let index = self.app_rules_position();
+        self.route
+            .rules
+            .insert(index, RouteRule::Default(target.to_route_rule(outbound)));
+
+        if let (Some(uid), Some(tun)) = (target.uid(), self.inbounds.get_tun_mut()) {
+            apps::apply_uid_filter(tun, uid, policy);
+        }
+        Ok(self)
+    }
+
+    pub fn remove_app_rule(&mut self, target: &AppTarget) -> Result<&mut Self> {
+        if self.remove_app_rules(target) == 0 {
+            return Err(anyhow!("There is no rule for {target}"));
+        }
+
+        if let (Some(uid), Some(tun)) = (target.uid(), self.inbounds.get_tun_mut()) {
+            apps::clear_uid_filter(tun, uid);
+        }
+        Ok(self)
+    }
+
+    fn remove_app_rules(&mut self, target: &AppTarget) -> usize {
+        let direct = self.outbounds.get_tag_by_type("direct");
+        let len = self.route.rules.len();
+        self.route.rules.retain(|x| {
+            AppRule::from_route_rule(x, direct.as_deref()).is_none_or(|x| x.target != *target)
+        });
+        len - self.route.rules.len()
+    }
+
     /// Set route rules in format: <ACTION>:<TYPE>:<VALUE>
     /// ACTIONS:
     /// "r"      -> Reject
     /// "h"      -> Hijack-dns
     /// "s"      -> Shiff
     /// "<NAME>" -> Route outbound with NAME type (for example "vless")
+    /// any other action of rule expression with options, for example
+    /// "proxy tls_fragment=true" or "reject drop", see [`RuleExpr::parse`]
     ///
     /// TYPES:                 VALUE type:
     /// "ib" -> inbound type    `str`
@@ -223,9 +403,10 @@ impl Configurator {
     /// "ds" -> domain suffix   `str`
     /// "dk" -> domain keyword  `str`
     /// "dr" -> domain regex    `str`
-    /// "gs" -> geosite         `str`
-    /// "sg" -> source geoip    `str`
-    /// "gp" -> geoip           `str`
+    /// "gs" -> geosite         `str` migrated to rule set
+    /// "rs" -> rule set tag    `str`
+    /// "sg" -> source geoip    `str` migrated to rule set
+    /// "gp" -> geoip           `str` migrated to rule set
     /// "sc" -> source ip cidr  `str`
     /// "si" -> ^ ip is private `bool`
     /// "ic" -> ip cidr         `str`
@@ -239,7 +420,7 @@ impl Configurator {
     /// "pg" -> ^ regex         `str`
     /// "kn" -> package name    `str`
     /// "ur" -> user            `str`
-    /// "ui" -> user id         `u16`
+    /// "ui" -> user id         `u32`
     /// "cm" -> clash mode      `str`
     /// "nt" -> network type    `str`
     /// "nk" -> network         `str`
@@ -253,6 +434,11 @@ impl Configurator {
     /// "h" dont need values at all
     pub fn add_route_rules(&mut self, rules: &Vec<String>) -> Result<&mut Self> {
         for i in rules {
+            if !is_legacy_rule(i) {
+                self.add_route_expr(i)?;
+                continue;
+            }
+
             let mut rh;
             let ri: Vec<&str> = i.split(":").collect();
             let mut value_flag = false;
@@ -265,7 +451,9 @@ impl Configurator {
                 "h" => rh = DefaultRouteRule::hijack_dns_action(),
                 "s" => rh = DefaultRouteRule::sniff_action(*ri.get(1).unwrap_or(&"1s")),
                 x => {
-                    rh = DefaultRouteRule::route_action_by_type(&self.outbounds, x);
+                    let expr = RuleExpr::parse(x).map_err(|e| anyhow!("{}\n{e}", e.pointer(x)))?;
+                    rh = DefaultRouteRule::new()
+                        .set_action(self.resolve_expr_action(&expr.action, &expr.options)?);
                     value_flag = true;
                 }
             }
@@ -287,6 +475,11 @@ impl Configurator {
                     "dk" => rh = rh.add_domain_keyword(ri.get(2).context("Incorrect input")?),
                     "dr" => rh = rh.add_domain_regex(ri.get(2).context("Incorrect input")?),
                     "gs" => rh = rh.add_geosite(ri.get(2).context("Incorrect input")?),
+                    "rs" => {
+                        let tag = ri.get(2).context("Incorrect input")?;
+                        self.check_rule_sets([*tag])?;
+                        rh = rh.add_rule_set(tag)
+                    }
                     "sg" => rh = rh.add_source_geoip(ri.get(2).context("Incorrect input")?),
                     "gp" => rh = rh.add_geoip(ri.get(2).context("Incorrect input")?),
                     "si" => {
@@ -326,77 +519,386 @@ impl Configurator {
             }
             self.route.add_default_rule(rh);
         }
+        self.migrate_geo();
         Ok(self)
     }
 
-    /// Set route rules in format: <TYPE>:<VALUE1>:<VALUE2>
-    /// TYPES:
+    /// Adds rule written as expression, see [`RuleExpr::parse`]:
+    /// `proxy if domain_suffix in [google.com, youtube.com] and port 443`
+    pub fn add_route_expr(&mut self, input: &str) -> Result<&mut Self> {
+        let expr = RuleExpr::parse(input).map_err(|e| anyhow!("{}\n{e}", e.pointer(input)))?;
+        let rule = expr
+            .to_route_rule(self.resolve_expr_action(&expr.action, &expr.options)?)
+            .map_err(|e| anyhow!("{}\n{e}", e.pointer(input)))?;
+
+        let mut rule_sets = vec![];
+        collect_rule_sets(&serde_json::to_value(&rule)?, &mut rule_sets);
+        self.check_rule_sets(rule_sets.iter().map(String::as_str))?;
+
+        self.route.rules.push(rule);
+        self.migrate_geo();
+        Ok(self)
+    }
+
+    /// `proxy` is final outbound, `direct` is direct outbound,
+    /// other names are outbound tags or types.
+    /// Options must be fields of the action in sing-box names
+    fn resolve_expr_action(
+        &self,
+        action: &ExprAction,
+        options: &serde_json::Map<String, serde_json::Value>,
+    ) -> Result<RuleAction> {
+        let action = match action {
+            ExprAction::Route(name) => {
+                let tag = match name.as_str() {
+                    "proxy" => self.route.default.clone().or_else(|| {
+                        self.outbounds
+                            .get_types_except_direct()
+                            .first()
+                            .and_then(|x| self.outbounds.get_tag_by_type(x))
+                    }),
+                    "direct" => self.outbounds.get_tag_by_type("direct"),
+                    x if self.outbounds.get_ref_by_tag(x).is_some() => Some(x.to_string()),
+                    x => self.outbounds.get_tag_by_type(x),
+                };
+                let tag = tag.with_context(|| format!("Outbound '{name}' does not exist"))?;
+                RuleAction::Route(RouteAction::new(tag))
+            }
+            ExprAction::RouteOptions => RuleAction::RouteOptions(RouteOptions::new()),
+            ExprAction::Reject(method) => {
+                let mut action = RejectAction::new();
+                if let Some(x) = method {
+                    action = action.set_method(x.clone());
+                }
+                RuleAction::Reject(action)
+            }
+            ExprAction::HijackDns => RuleAction::HijackDns(HijackDnsAction::new()),
+            ExprAction::Sniff(timeout) => {
+                let mut action = SniffAction::new();
+                if let Some(x) = timeout {
+                    action = action.set_timeout(x.clone());
+                }
+                RuleAction::Sniff(action)
+            }
+            ExprAction::Resolve(server) => {
+                let mut action = ResolveAction::new();
+                if let Some(x) = server {
+                    action = action.set_server(x);
+                }
+                RuleAction::Resolve(action)
+            }
+        };
+
+        let mut value = serde_json::to_value(&action)?;
+        let map = value.as_object_mut().context("Action is not an object")?;
+        for (key, x) in options {
+            if key == "action" || key == "outbound" {
+                return Err(anyhow!("Set '{key}' with action part of the rule"));
+            }
+            map.insert(key.clone(), x.clone());
+        }
+        let action: RuleAction = serde_json::from_value(value)
+            .with_context(|| format!("Incorrect options of {} action", action.name()))?;
+
+        let value = serde_json::to_value(&action)?;
+        if let Some(key) = options.keys().find(|x| value.get(x.as_str()).is_none()) {
+            return Err(anyhow!("{} action has no option '{key}'", action.name()));
+        }
+
+        match &action {
+            RuleAction::RouteOptions(_) if options.is_empty() => {
+                return Err(anyhow!(
+                    "route-options action needs options, e.g. 'route-options tls_fragment=true'"
+                ));
+            }
+            RuleAction::Reject(RejectAction {
+                method: Some(x), ..
+            }) if !REJECT_METHODS.contains(&x.as_str()) => {
+                return Err(anyhow!("Reject method must be one of {REJECT_METHODS:?}"));
+            }
+            RuleAction::Sniff(SniffAction {
+                sniffer: Some(x), ..
+            }) => {
+                if let Some(x) = x.iter().find(|x| !SNIFFERS.contains(&x.as_str())) {
+                    return Err(anyhow!("Unknown sniffer '{x}', use one of {SNIFFERS:?}"));
+                }
+            }
+            RuleAction::Resolve(x) => {
+                if let Some(server) = &x.server
+                    && !self.dns.get_tags().contains(server)
+                {
+                    return Err(anyhow!("Dns server '{server}' does not exist"));
+                }
+                if let Some(strategy) = &x.strategy
+                    && !DOMAIN_STRATEGIES.contains(&strategy.as_str())
+                {
+                    return Err(anyhow!("Strategy must be one of {DOMAIN_STRATEGIES:?}"));
+                }
+            }
+            _ => {}
+        }
+        Ok(action)
+    }
+
+    /// Add dns servers in url format, see [`dnsurl::parse_dns_url`]:
+    /// `https://1.1.1.1/dns-query`, `tls://dns.google?resolver=dns-local`,
+    /// `quic://`, `h3://`, `dhcp://eth0`, `fakeip`, `hosts:/etc/hosts`
+    ///
+    /// Old format <TYPE>:<VALUE1>:<VALUE2> is still accepted:
     /// "up" -> udp <ADDR>:<PORT>
     /// "tp" -> tcp <ADDR>:<PORT>
     pub fn add_dns_servers(&mut self, dns: &Vec<String>) -> Result<&mut Self> {
         for i in dns {
-            let dh;
-            let df: Vec<&str> = i.split(":").collect();
-            let df_port;
-            let df_type;
-            let df_addr;
-
-            if df.len() < 3 {
-                df_port = None;
-                if df.len() < 2 {
-                    df_type = "up";
-                    df_addr = df[0];
-                } else {
-                    df_type = df[0];
-                    df_addr = df[1];
-                }
-            } else {
-                df_type = df[0];
-                df_port = Some(df[2].parse::<u16>()?);
-                df_addr = df[1];
+            let mut dh = match i.split_once(':') {
+                Some(("up" | "tp", _)) => Self::parse_legacy_dns(i)?,
+                _ => dnsurl::parse_dns_url(i).with_context(|| format!("Incorrect dns '{i}'"))?,
+            };
+
+            // default tags get a number, explicit ones must be unique
+            let tag = dh.get_tag();
+            if self.dns.get_tags().contains(&tag) {
+                if tag != format!("dns-{}", dh.get_type()) {
+                    return Err(anyhow!("Dns server with tag '{tag}' already exists"));
+                }
+                dh.set_tag(self.dns.unique_tag(&tag));
             }
 
-            match df_type {
-                "tp" => {
-                    dh = DnsServer::Tcp(DnsServerTcp::with_server(df_addr.to_string(), df_port))
+            if let Some(dial) = dh.get_dial_ref() {
+                if let Some(detour) = &dial.detour
+                    && self.outbounds.get_ref_by_tag(detour).is_none()
+                {
+                    return Err(anyhow!("Detour '{detour}' is not an outbound tag"));
                 }
-                "up" => {
-                    dh = DnsServer::Udp(DnsServerUdp::with_server(df_addr.to_string(), df_port))
+                if let Some(resolver) = &dial.domain_resolver
+                    && !self.dns.get_tags().contains(resolver)
+                {
+                    return Err(anyhow!("Resolver '{resolver}' is not a dns server tag"));
                 }
-                _ => return Err(anyhow!("Cant parse that type of dns yet")),
             }
             self.dns.add_server(dh);
         }
         Ok(self)
     }
 
+    fn parse_legacy_dns(value: &str) -> Result<DnsServer> {
+        let df: Vec<&str> = value.split(":").collect();
+        let df_addr = df.get(1).context("Incorrect dns input")?.to_string();
+        let df_port = match df.get(2) {
+            Some(x) => Some(x.parse::<u16>()?),
+            None => None,
+        };
+
+        match df[0] {
+            "tp" => Ok(DnsServer::Tcp(DnsServerTcp::with_server(df_addr, df_port))),
+            _ => Ok(DnsServer::Udp(DnsServerUdp::with_server(df_addr, df_port))),
+        }
+    }
+
+    /// Answers A/AAAA queries of tun config with fake addresses from
+    /// `inet4`/`inet6` ranges, so domains reach outbounds without real lookup.
+    ///
+    /// Domains of direct route rules and other query types keep using
+    /// the real dns server, fakeip mappings are stored in cache file
+    pub fn enable_fakeip(&mut self, inet4: Option<&str>, inet6: Option<&str>) -> Result<&mut Self> {
+        if self.dns.get_tag_by_type("fakeip").is_some() {
+            return Err(anyhow!("Fakeip is already enabled"));
+        }
+
+        let mut server = parse_dns_url(&format!(
+            "fakeip?inet4={}&inet6={}",
+            inet4.unwrap_or(FAKEIP_INET4_RANGE),
+            inet6.unwrap_or(FAKEIP_INET6_RANGE)
+        ))?;
+        let DnsServer::FakeIp(fakeip) = &server else {
+            return Err(anyhow!("Cant build fakeip server"));
+        };
+        let ranges: Vec<String> = [&fakeip.inet4_range, &fakeip.inet6_range]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect();
+
+        let tun = self.get_tun_ref().context("Fakeip needs tun inbound")?;
+        for range in &ranges {
+            let range: Cidr = range.parse()?;
+            for address in &tun.address {
+                let address: Cidr = address.parse()?;
+                if range.contains(&address.addr) || address.contains(&range.addr) {
+                    return Err(anyhow!(
+                        "Tun address {address} overlaps fakeip range {range}"
+                    ));
+                }
+            }
+        }
+
+        let real = self
+            .dns
+            .default
+            .clone()
+            .or_else(|| {
+                self.dns
+                    .servers
+                    .iter()
+                    .find(|x| !matches!(x.get_type(), "fakeip" | "hosts"))
+                    .map(|x| x.get_tag())
+            })
+            .context("Fakeip needs a real dns server, add one with --dns")?;
+
+        let tag = self.dns.unique_tag(&server.get_tag());
+        server.set_tag(tag.clone());
+        self.dns.add_server(server);
+
+        if let Some(direct) = self.outbounds.get_tag_by_type("direct")
+            && let Some(rule) = fakeip::direct_domain_rule(&self.route.rules, &direct, &real)
+        {
+            self.dns.add_rule(DnsRule::Default(rule));
+        }
+        self.dns
+            .add_rule(DnsRule::Default(fakeip::fakeip_rule(&tag)));
+        self.dns.default = Some(real.clone());
+        self.route.default_domain_resolver.get_or_insert(real);
+
+        self.experimental.cache_file = Some(CacheFileConfig::new().set_store_fakeip(true));
+
+        let tun = self.get_tun_mut()?;
+        if let Some(route_address) = tun.route_address.as_mut() {
+            for range in ranges {
+                if !route_address.contains(&range) {
+                    route_address.push(range);
+                }
+            }
+        }
+        Ok(self)
+    }
+
+    /// Fails on the first tag missing in `route.rule_set`
+    fn check_rule_sets<'a>(&self, tags: impl IntoIterator<Item = &'a str>) -> Result<()> {
+        let rule_sets = self.route.get_rule_set_tags();
+        for tag in tags {
+            if !rule_sets.iter().any(|x| x == tag) {
+                return Err(anyhow!("Rule set '{tag}' does not exist"));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn add_rule_set(&mut self, rule_set: RuleSet) -> Result<&mut Self> {
+        if let Some(detour) = rule_set.get_download_detour()
+            && self.outbounds.get_ref_by_tag(detour).is_none()
+        {
+            return Err(anyhow!("Outbound '{detour}' does not exist"));
+        }
+        self.route.add_rule_set(rule_set)?;
+        Ok(self)
+    }
+
+    /// Removes rule set which is not used by route and dns rules
+    pub fn remove_rule_set(&mut self, tag: &str) -> Result<&mut Self> {
+        let used = serde_json::to_value(&self.route.rules)?;
+        let used_dns = serde_json::to_value(self.dns.get_rules())?;
+        if uses_rule_set(&used, tag) || uses_rule_set(&used_dns, tag) {
+            return Err(anyhow!("Rule set '{tag}' is used by rules"));
+        }
+        self.route.remove_rule_set(tag)?;
+        Ok(self)
+    }
+
+    /// Checks tags referenced by dns rule and appends it
+    pub fn add_dns_rule(&mut self, rule: DnsDefaultRule) -> Result<&mut Self> {
+        if !rule.has_conditions() {
+            return Err(anyhow!("Dns rule has no conditions"));
+        }
+
+        let outbounds = rule.outbound.iter().flat_map(|x| x.to_vec());
+        for tag in outbounds {
+            if tag != ANY_OUTBOUND && self.outbounds.get_ref_by_tag(tag).is_none() {
+                return Err(anyhow!("Outbound '{tag}' does not exist"));
+            }
+        }
+
+        self.check_rule_sets(
+            rule.rule_set
+                .iter()
+                .flat_map(|x| x.to_vec())
+                .map(String::as_str),
+        )?;
+
+        match &rule.action {
+            Some(DnsRuleAction::Route(x)) => {
+                let server = x.server.as_ref().context("Route action needs dns server")?;
+                if !self.dns.get_tags().contains(server) {
+                    return Err(anyhow!("Dns server '{server}' does not exist"));
+                }
+            }
+            Some(DnsRuleAction::Reject(x)) => {
+                if let Some(method) = &x.method
+                    && !REJECT_METHODS.contains(&method.as_str())
+                {
+                    return Err(anyhow!("Reject method must be one of {REJECT_METHODS:?}"));
+                }
+            }
+            Some(DnsRuleAction::Predefined(x)) => {
+                if let Some(rcode) = &x.rcode
+                    && !RCODES.contains(&rcode.as_str())
+                {
+                    return Err(anyhow!("Rcode must be one of {RCODES:?}"));
+                }
+            }
+            Some(DnsRuleAction::RouteOptions(_)) => {}
+            None => return Err(anyhow!("Dns rule has no action")),
+        }
+
+        self.dns.add_rule(DnsRule::Default(rule));
+        Ok(self)
+    }
+
+    /// Removes dns rule by index
+    pub fn remove_dns_rule(&mut self, index: usize) -> Result<DnsRule> {
+        self.dns.remove_rule(index)
+    }
+
+    pub fn move_dns_rule(&mut self, from: usize, to: usize) -> Result<&mut Self> {
+        self.dns.move_rule(from, to)?;
+        Ok(self)
+    }
+
     /// Manage listables values in format: <ACTION>:<VALUE1>:<VALUE2>
     ///
     ///If action contains one value you need only:
     ///     <ACTION>:<VALUE>
     ///
     /// ACTION:             VALUES:
-    /// "rr" -> Remove rule    `usize`         -> remove by index <VALUE>
+    /// "rr" -> Remove rule    `usize`         -> remove by number <VALUE>
     /// "mr" -> Move rule      `usize`:`usize`   -> move from <VALUE1> to <VALUE2>
     /// "fr" -> final outbound     `str`           -> set default outbound by type
     /// "rd" -> Remove dns server `usize`         -> remove by index <VALUE>
     /// "md" -> Move dns server `usize`:`usize`   -> move from <VALUE1> to <VALUE2>
     /// "fd" -> final dns server `str`           -> set default dns server by type
-    /// "rs" -> Remove dns server `usize`         -> remove by index <VALUE>
-    /// "ms" -> Move dns server `usize`:`usize`   -> move from <VALUE1> to <VALUE2>
+    /// "rs" -> Remove dns rule `usize`         -> remove by number <VALUE>
+    /// "ms" -> Move dns rule `usize`:`usize`   -> move from <VALUE1> to <VALUE2>
+    /// "gr" -> Group rules   `usize,..`:`str` -> join rules <VALUE1> into logical rule with mode <VALUE2>
+    /// "nr" -> Nest rule     `usize`:`path`   -> move rule <VALUE1> into logical rule <VALUE2>
+    /// "rp" -> Remove by path `path`          -> remove rule or nested rule <VALUE>
+    /// "er" -> Extract rule  `path`           -> move nested rule <VALUE> out of logical rule
+    /// "ir" -> Invert rule   `path`           -> toggle invert of rule <VALUE>
+    /// "lm" -> Logical mode  `path`:`str`     -> set mode of logical rule <VALUE1>
+    ///
+    /// Route and dns rules are numbered from 1 like `relation route list` and `relation dns list`,
+    /// dns servers are indexed from 0.
+    /// `path` is rule number or numbers of nested rules joined by dots: `2.1`
     pub fn manage(&mut self, values: &Vec<String>) -> Result<&mut Self> {
         for i in values {
             let ri: Vec<&str> = i.split(":").collect();
             match *ri.first().context("Incorrect manage input")? {
                 "rr" => {
-                    let _ = self
-                        .route
-                        .remove_rule(ri.get(1).context("Incorrect manage input")?.parse()?);
+                    let _ = self.route.remove_rule(parse_rule_number(
+                        ri.get(1).context("Incorrect manage input")?,
+                    )?);
                 }
                 "mr" => {
                     self.route.move_rule(
-                        ri.get(1).context("Incorrect manage input")?.parse()?,
-                        ri.get(2).context("Incorrect manage input")?.parse()?,
+                        parse_rule_number(ri.get(1).context("Incorrect manage input")?)?,
+                        parse_rule_number(ri.get(2).context("Incorrect manage input")?)?,
                     );
                 }
                 "fr" => {
@@ -421,15 +923,52 @@ impl Configurator {
                         .set_final_by_type(ri.get(1).context("Incorrect manage input")?);
                 }
                 "rs" => {
-                    let _ = self
-                        .dns
-                        .remove_rule(ri.get(1).context("Incorrect manage input")?.parse()?);
+                    let _ = self.dns.remove_rule(parse_rule_number(
+                        ri.get(1).context("Incorrect manage input")?,
+                    )?)?;
                 }
                 "ms" => {
                     self.dns.move_rule(
-                        ri.get(1).context("Incorrect manage input")?.parse()?,
-                        ri.get(2).context("Incorrect manage input")?.parse()?,
-                    );
+                        parse_rule_number(ri.get(1).context("Incorrect manage input")?)?,
+                        parse_rule_number(ri.get(2).context("Incorrect manage input")?)?,
+                    )?;
+                }
+                "gr" => {
+                    let indices = ri
+                        .get(1)
+                        .context("Incorrect manage input")?
+                        .split(',')
+                        .map(parse_rule_number)
+                        .collect::<Result<Vec<usize>>>()?;
+                    self.route
+                        .group_rules(&indices, ri.get(2).context("Incorrect manage input")?)?;
+                }
+                "nr" => {
+                    self.route.nest_rule(
+                        parse_rule_number(ri.get(1).context("Incorrect manage input")?)?,
+                        &parse_rule_path(ri.get(2).context("Incorrect manage input")?)?,
+                    )?;
+                }
+                "rp" => {
+                    self.route.remove_rule_by_path(&parse_rule_path(
+                        ri.get(1).context("Incorrect manage input")?,
+                    )?)?;
+                }
+                "er" => {
+                    self.route.extract_rule(&parse_rule_path(
+                        ri.get(1).context("Incorrect manage input")?,
+                    )?)?;
+                }
+                "ir" => {
+                    self.route.invert_rule(&parse_rule_path(
+                        ri.get(1).context("Incorrect manage input")?,
+                    )?)?;
+                }
+                "lm" => {
+                    self.route.set_rule_mode(
+                        &parse_rule_path(ri.get(1).context("Incorrect manage input")?)?,
+                        ri.get(2).context("Incorrect manage input")?,
+                    )?;
                 }
                 _ => {}
             }
@@ -474,17 +1013,90 @@ impl Configurator {
         Ok(file_name.to_string())
     }
 
+    /// Config as json tree for editing by path
+    pub fn to_tree(&self) -> Result<serde_json::Value> {
+        Ok(serde_json::to_value(self)?)
+    }
+
+    /// Replaces config with edited tree, fails when tree does not fit typed config
+    pub fn set_tree(&mut self, tree: serde_json::Value) -> Result<&mut Self> {
+        let mut configurator: Self = serde_json::from_value(tree)?;
+        configurator.geo = std::mem::take(&mut self.geo);
+        configurator.geo_report = std::mem::take(&mut self.geo_report);
+
+        *self = configurator;
+        Ok(self)
+    }
+
+    /// Checks what typed config can not: unique tags and references between sections
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = vec![];
+
+        let outbounds: Vec<String> = self.outbounds.servers.iter().map(|x| x.get_tag()).collect();
+        let inbounds: Vec<String> = self.inbounds.servers.iter().map(|x| x.get_tag()).collect();
+        let servers = self.dns.get_tags();
+        for (kind, tags) in [
+            ("outbound", &outbounds),
+            ("inbound", &inbounds),
+            ("dns server", &servers),
+        ] {
+            for (i, tag) in tags.iter().enumerate() {
+                if tag.is_empty() {
+                    problems.push(format!("{kind} {i} has no tag"));
+                } else if tags[..i].contains(tag) {
+                    problems.push(format!("{kind} tag '{tag}' is used twice"));
+                }
+            }
+        }
+
+        for x in &self.outbounds.servers {
+            if x.get_type() != "direct" && (x.get_addr().is_empty() || x.get_port() == Some(0)) {
+                problems.push(format!("outbound '{}' has no server", x.get_tag()));
+            }
+        }
+
+        let mut route_outbounds = vec![];
+        collect_route_outbounds(&self.route.rules, &mut route_outbounds);
+        if let Some(x) = &self.route.default {
+            route_outbounds.push(x.clone());
+        }
+        for x in route_outbounds {
+            if !outbounds.contains(&x) {
+                problems.push(format!("route uses unknown outbound '{x}'"));
+            }
+        }
+
+        let mut dns_servers = vec![];
+        collect_dns_servers(self.dns.get_rules(), &mut dns_servers);
+        if let Some(x) = &self.dns.default {
+            dns_servers.push(x.clone());
+        }
+        for x in dns_servers {
+            if !servers.contains(&x) {
+                problems.push(format!("dns uses unknown server '{x}'"));
+            }
+        }
+
+        match problems.is_empty() {
+            true => Ok(()),
+            false => Err(anyhow!(problems.join("\n"))),
+        }
+    }
+
     pub fn load_from_file(&mut self, path: PathBuf) -> Result<&mut Self> {
         let file = File::open(path)?;
         let reader = BufReader::new(file);
 
-        let configurator: Self = serde_json::from_reader(reader)?;
+        let mut configurator: Self = serde_json::from_reader(reader)?;
+        configurator.geo = std::mem::take(&mut self.geo);
+        configurator.geo_report = std::mem::take(&mut self.geo_report);
 
         *self = configurator;
         Ok(self)
     }
 
     pub fn clean(&mut self) -> &mut Self {
+        self.relation = None;
         self.log.clean();
         self.dns.clean();
         self.inbounds.clean();
@@ -493,3 +1105,84 @@ impl Configurator {
         self
     }
 }
+
+fn collect_route_outbounds(rules: &[RouteRule], res: &mut Vec<String>) {
+    for rule in rules {
+        if let Some(RuleAction::Route(x)) = rule.get_action() {
+            res.push(x.outbound.clone());
+        }
+        if let RouteRule::Logical(x) = rule {
+            collect_route_outbounds(&x.rules, res);
+        }
+    }
+}
+
+fn collect_dns_servers(rules: &[DnsRule], res: &mut Vec<String>) {
+    for rule in rules {
+        let action = match rule {
+            DnsRule::Default(x) => &x.action,
+            DnsRule::Logical(x) => &x.action,
+        };
+        if let Some(DnsRuleAction::Route(x)) = action
+            && let Some(server) = &x.server
+        {
+            res.push(server.clone());
+        }
+        if let DnsRule::Logical(x) = rule {
+            collect_dns_servers(&x.rules, res);
+        }
+    }
+}
+
+/// `<ACTION>:<TYPE>:<VALUE>` form of route rule, anything else is expression
+fn is_legacy_rule(rule: &str) -> bool {
+    let mut parts = rule.trim().split(':');
+    match (parts.next(), parts.next()) {
+        (Some("h"), None) | (Some("s"), _) => true,
+        (_, Some(x)) => crate::consts::route::TYPES
+            .iter()
+            .any(|(_, code)| *code == x),
+        _ => false,
+    }
+}
+
+/// Tags from `rule_set` keys of rules, nested logical rules included
+fn collect_rule_sets(value: &serde_json::Value, res: &mut Vec<String>) {
+    match value {
+        serde_json::Value::Array(x) => x.iter().for_each(|x| collect_rule_sets(x, res)),
+        serde_json::Value::Object(map) => {
+            for (key, value) in map {
+                match (key.as_str(), value) {
+                    ("rule_set", serde_json::Value::String(x)) => res.push(x.clone()),
+                    ("rule_set", serde_json::Value::Array(x)) => {
+                        res.extend(x.iter().filter_map(|x| x.as_str()).map(|x| x.to_string()))
+                    }
+                    _ => collect_rule_sets(value, res),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Values of `clash_mode` keys of rules, nested logical rules included
+fn collect_clash_modes(value: &serde_json::Value, res: &mut Vec<String>) {
+    match value {
+        serde_json::Value::Array(x) => x.iter().for_each(|x| collect_clash_modes(x, res)),
+        serde_json::Value::Object(map) => {
+            for (key, value) in map {
+                match (key.as_str(), value) {
+                    ("clash_mode", serde_json::Value::String(x)) => res.push(x.clone()),
+                    _ => collect_clash_modes(value, res),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn uses_rule_set(value: &serde_json::Value, tag: &str) -> bool {
+    let mut res = vec![];
+    collect_rule_sets(value, &mut res);
+    res.iter().any(|x| x == tag)
+}
@@ -154,4 +154,11 @@ impl Outbound {
             Outbound::Vless(cfg) => cfg.get_server_ip(),
         }
     }
+
+    pub fn get_port(&self) -> Option<u16> {
+        match self {
+            Outbound::Direct(_) => None,
+            Outbound::Vless(cfg) => Some(cfg.get_server_port()),
+        }
+    }
 }
@@ -127,6 +127,10 @@ impl VlessConfig {
         self.server.clone()
     }
 
+    pub fn get_server_port(&self) -> u16 {
+        self.server_port
+    }
+
     fn parser(input: &str) -> Result<Vec<(PossibleKeys, PossibleValues)>> {
         let parsed_input = Url::parse(input)?;
         let mut values: Vec<(PossibleKeys, PossibleValues)> = vec![
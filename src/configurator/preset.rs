@@ -0,0 +1,148 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::configurator::Configurator;
+use crate::configurator::dns::DnsConfig;
+use crate::configurator::inbound::InboundConfig;
+use crate::configurator::route::RouteConfig;
+
+pub const BUILTIN_PRESETS: &[&str] = &["default", "tun"];
+
+/// Skeleton of a config without outbounds: dns, inbounds and route rules.
+/// Route rules may reference `outbound-direct`, the proxy outbound is
+/// added later from url.
+#[derive(Serialize, Deserialize)]
+pub struct Preset {
+    pub dns: DnsConfig,
+    pub inbounds: InboundConfig,
+    pub route: RouteConfig,
+}
+
+impl Preset {
+    pub fn builtin(name: &str) -> Option<Self> {
+        let mut cfg = Configurator::new();
+        match name {
+            "default" => cfg.default(),
+            "tun" => cfg.default_tun(),
+            _ => return None,
+        };
+
+        Some(Self {
+            dns: cfg.dns,
+            inbounds: cfg.inbounds,
+            route: cfg.route,
+        })
+    }
+
+    pub fn is_builtin(name: &str) -> bool {
+        BUILTIN_PRESETS.contains(&name)
+    }
+
+    /// Reads preset from `.toml` or `.json` file
+    pub fn load_from_file(path: &Path) -> Result<Self> {
+        Self::parse(&fs::read_to_string(path)?, path)
+    }
+
+    /// Parses content in the format of `path` extension
+    pub fn parse(content: &str, path: &Path) -> Result<Self> {
+        match path.extension().and_then(|x| x.to_str()) {
+            Some("toml") => Ok(toml::from_str(content)?),
+            Some("json") => Ok(serde_json::from_str(content)?),
+            _ => Err(anyhow!("Unknown preset format: {}", path.display())),
+        }
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let content = match path.extension().and_then(|x| x.to_str()) {
+            Some("toml") => toml::to_string_pretty(self)?,
+            Some("json") => serde_json::to_string_pretty(self)?,
+            _ => return Err(anyhow!("Unknown preset format: {}", path.display())),
+        };
+
+        fs::write(path, content)?;
+        Ok(())
+    }
+}
+
+/// Presets directory of the data dir. User files take precedence over
+/// builtin presets with the same name.
+pub struct PresetStore {
+    dir: PathBuf,
+}
+
+impl PresetStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    pub fn find_file(&self, name: &str) -> Option<PathBuf> {
+        ["toml", "json"]
+            .iter()
+            .map(|ext| self.dir.join(format!("{name}.{ext}")))
+            .find(|path| path.is_file())
+    }
+
+    pub fn load(&self, name: &str) -> Result<Preset> {
+        if let Some(path) = self.find_file(name) {
+            return Preset::load_from_file(&path)
+                .map_err(|err| anyhow!("Broken preset '{}': {}", path.display(), err));
+        }
+        Preset::builtin(name).ok_or_else(|| anyhow!("Preset '{name}' does not exist"))
+    }
+
+    /// (name, is user file)
+    pub fn get_list(&self) -> Result<Vec<(String, bool)>> {
+        let mut result: Vec<(String, bool)> = BUILTIN_PRESETS
+            .iter()
+            .map(|x| (x.to_string(), false))
+            .collect();
+
+        if let Ok(entries) = fs::read_dir(&self.dir) {
+            for entry in entries {
+                let path = entry?.path();
+                let is_preset = matches!(
+                    path.extension().and_then(|x| x.to_str()),
+                    Some("toml") | Some("json")
+                );
+                if !path.is_file() || !is_preset {
+                    continue;
+                }
+                if let Some(stem) = path.file_stem().and_then(|x| x.to_str()) {
+                    match result.iter_mut().find(|(name, _)| name == stem) {
+                        Some(found) => found.1 = true,
+                        None => result.push((stem.to_string(), true)),
+                    }
+                }
+            }
+        }
+
+        result.sort();
+        Ok(result)
+    }
+
+    /// Path of preset file, a new `.toml` file for builtin and new names
+    pub fn file_path(&self, name: &str) -> PathBuf {
+        self.find_file(name)
+            .unwrap_or_else(|| self.dir.join(format!("{name}.toml")))
+    }
+
+    /// Content to edit: the user file, or builtin (or `default` for new
+    /// names) preset as toml. Nothing is written to the presets dir
+    pub fn source(&self, name: &str) -> Result<String> {
+        if let Some(path) = self.find_file(name) {
+            return Ok(fs::read_to_string(path)?);
+        }
+
+        let preset = match Preset::builtin(name) {
+            Some(x) => x,
+            None => Preset::builtin("default").unwrap(),
+        };
+        Ok(toml::to_string_pretty(&preset)?)
+    }
+}
@@ -0,0 +1,22 @@
+use macros::auto_skip_none;
+use serde::{Deserialize, Serialize};
+
+/// Relation metadata of saved config, it is not passed to the core
+#[auto_skip_none]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct ProfileMeta {
+    /// Short id which stays with the config when others are added, removed or it is renamed
+    pub id: Option<String>,
+    /// Unix seconds
+    pub created: Option<u64>,
+    /// Subscription the config came from
+    pub subscription: Option<String>,
+    /// Preset used by `add`
+    pub preset: Option<String>,
+    pub notes: Option<String>,
+    pub tags: Option<Vec<String>>,
+    /// Listed before other configs
+    pub favourite: Option<bool>,
+    /// Country or region code like `DE`
+    pub region: Option<String>,
+}
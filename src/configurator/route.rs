@@ -1,13 +1,43 @@
+use anyhow::{Context, Result, anyhow};
 use macros::auto_skip_none;
 use serde::{Deserialize, Serialize};
 
+pub mod explain;
+pub mod geo;
 pub mod routerule;
+pub mod ruleexpr;
 pub mod ruleset;
 use crate::configurator::dns::DnsConfig;
 use crate::configurator::outbound::OutboundConfig;
-use crate::configurator::route::routerule::{DefaultRouteRule, LogicalRouteRule, RouteRule};
+use crate::configurator::route::routerule::{
+    DefaultRouteRule, LOGICAL_MODES, LogicalRouteRule, RouteRule,
+};
 use crate::configurator::route::ruleset::RuleSet;
 
+/// 1-based path of nested rule written as numbers joined by dots into indices: `2.1` -> `[1, 0]`
+pub fn parse_rule_path(value: &str) -> Result<Vec<usize>> {
+    value
+        .split('.')
+        .map(|x| {
+            x.trim()
+                .parse::<usize>()
+                .with_context(|| format!("'{value}' is not a rule path"))?
+                .checked_sub(1)
+                .context("Rules are numbered from 1")
+        })
+        .collect()
+}
+
+/// 1-based rule number into index
+pub fn parse_rule_number(value: &str) -> Result<usize> {
+    value
+        .trim()
+        .parse::<usize>()
+        .with_context(|| format!("'{value}' is not a rule number"))?
+        .checked_sub(1)
+        .context("Rules are numbered from 1")
+}
+
 #[auto_skip_none]
 #[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct RouteConfig {
@@ -44,6 +74,40 @@ impl RouteConfig {
         self
     }
 
+    pub fn get_rule_sets(&self) -> &[RuleSet] {
+        self.rule_set.as_deref().unwrap_or_default()
+    }
+
+    pub fn get_rule_set_tags(&self) -> Vec<String> {
+        self.get_rule_sets()
+            .iter()
+            .filter_map(|x| x.get_tag())
+            .collect()
+    }
+
+    pub fn add_rule_set(&mut self, rule_set: RuleSet) -> Result<&mut Self> {
+        let tag = rule_set.get_tag().context("Rule set has no tag")?;
+        if self.get_rule_set_tags().contains(&tag) {
+            return Err(anyhow!("Rule set '{tag}' already exists"));
+        }
+        self.rule_set.get_or_insert_with(Vec::new).push(rule_set);
+        Ok(self)
+    }
+
+    pub fn remove_rule_set(&mut self, tag: &str) -> Result<RuleSet> {
+        let rule_sets = self.rule_set.as_mut().context("There are no rule sets")?;
+        let index = rule_sets
+            .iter()
+            .position(|x| x.get_tag().as_deref() == Some(tag))
+            .with_context(|| format!("Rule set '{tag}' does not exist"))?;
+
+        let value = rule_sets.remove(index);
+        if rule_sets.is_empty() {
+            self.rule_set = None;
+        }
+        Ok(value)
+    }
+
     pub fn auto_detect_interface(&mut self, value: bool) -> &mut Self {
         self.auto_detect_interface = Some(value);
         self
@@ -76,6 +140,138 @@ impl RouteConfig {
         self.rules.insert(to, value);
     }
 
+    /// Rule by path of indices, `[1, 0]` is first nested rule of second rule
+    pub fn get_rule_mut(&mut self, path: &[usize]) -> Result<&mut RouteRule> {
+        let (first, rest) = path.split_first().context("Empty rule path")?;
+        let mut rule = self
+            .rules
+            .get_mut(*first)
+            .with_context(|| format!("Rule {} does not exist", first + 1))?;
+        for i in rest {
+            rule = match rule {
+                RouteRule::Logical(x) => x
+                    .rules
+                    .get_mut(*i)
+                    .with_context(|| format!("Nested rule {} does not exist", i + 1))?,
+                RouteRule::Default(_) => {
+                    return Err(anyhow!("Only logical rules have nested rules"));
+                }
+            };
+        }
+        Ok(rule)
+    }
+
+    fn get_logical_mut(&mut self, path: &[usize]) -> Result<&mut LogicalRouteRule> {
+        match self.get_rule_mut(path)? {
+            RouteRule::Logical(x) => Ok(x),
+            RouteRule::Default(_) => Err(anyhow!("Rule is not logical")),
+        }
+    }
+
+    /// Removes top level or nested rule, logical rule keeps at least one rule
+    pub fn remove_rule_by_path(&mut self, path: &[usize]) -> Result<RouteRule> {
+        match path.split_last().context("Empty rule path")? {
+            (index, []) => {
+                if *index >= self.rules.len() {
+                    return Err(anyhow!("Rule {} does not exist", index + 1));
+                }
+                Ok(self.rules.remove(*index))
+            }
+            (index, parent) => {
+                let parent = self.get_logical_mut(parent)?;
+                if *index >= parent.rules.len() {
+                    return Err(anyhow!("Nested rule {} does not exist", index + 1));
+                }
+                if parent.rules.len() == 1 {
+                    return Err(anyhow!(
+                        "Logical rule needs at least one rule, remove the logical rule instead"
+                    ));
+                }
+                Ok(parent.rules.remove(*index))
+            }
+        }
+    }
+
+    /// Replaces top level rules with one logical rule at place of the first of them.
+    /// Rules must have the same action, it becomes action of the logical rule
+    pub fn group_rules(&mut self, indices: &[usize], mode: &str) -> Result<&mut Self> {
+        if !LOGICAL_MODES.contains(&mode) {
+            return Err(anyhow!("Logical mode must be one of {LOGICAL_MODES:?}"));
+        }
+        let mut indices = indices.to_vec();
+        indices.sort_unstable();
+        indices.dedup();
+        let first = *indices.first().context("No rules to group")?;
+        if let Some(x) = indices.iter().find(|x| **x >= self.rules.len()) {
+            return Err(anyhow!("Rule {} does not exist", x + 1));
+        }
+
+        let action = self.rules[first].get_action().cloned();
+        let action_value = serde_json::to_value(&action)?;
+        for i in &indices {
+            if serde_json::to_value(self.rules[*i].get_action())? != action_value {
+                return Err(anyhow!("Grouped rules must have the same action"));
+            }
+        }
+
+        let mut rule = LogicalRouteRule::new()
+            .set_mode(mode.to_string())
+            .set_action(action);
+        for i in indices.iter().rev() {
+            rule = rule.add_rule(self.rules.remove(*i));
+        }
+        rule.rules.reverse();
+        self.rules.insert(first, RouteRule::Logical(rule));
+        Ok(self)
+    }
+
+    /// Moves top level rule into logical rule, its action is dropped
+    pub fn nest_rule(&mut self, index: usize, into: &[usize]) -> Result<&mut Self> {
+        let mut into = into.to_vec();
+        let root = into.first_mut().context("Empty rule path")?;
+        if *root == index {
+            return Err(anyhow!("Rule cant be nested into itself"));
+        }
+        if index >= self.rules.len() {
+            return Err(anyhow!("Rule {} does not exist", index + 1));
+        }
+        if *root > index {
+            *root -= 1;
+        }
+
+        self.get_logical_mut(&into).map(|_| ())?;
+        let mut rule = self.rules.remove(index);
+        rule.set_action(None);
+        self.get_logical_mut(&into)?.rules.push(rule);
+        Ok(self)
+    }
+
+    /// Moves nested rule out after its top level rule, it gets action of that rule
+    pub fn extract_rule(&mut self, path: &[usize]) -> Result<&mut Self> {
+        let root = *path.first().context("Empty rule path")?;
+        if path.len() < 2 {
+            return Err(anyhow!("Rule {} is not nested", root + 1));
+        }
+
+        let mut rule = self.remove_rule_by_path(path)?;
+        rule.set_action(self.rules[root].get_action().cloned());
+        self.rules.insert(root + 1, rule);
+        Ok(self)
+    }
+
+    pub fn set_rule_mode(&mut self, path: &[usize], mode: &str) -> Result<&mut Self> {
+        if !LOGICAL_MODES.contains(&mode) {
+            return Err(anyhow!("Logical mode must be one of {LOGICAL_MODES:?}"));
+        }
+        self.get_logical_mut(path)?.mode = Some(mode.to_string());
+        Ok(self)
+    }
+
+    pub fn invert_rule(&mut self, path: &[usize]) -> Result<&mut Self> {
+        self.get_rule_mut(path)?.toggle_invert();
+        Ok(self)
+    }
+
     pub fn get_list(&self) -> Vec<RouteRule> {
         self.rules.clone()
     }
@@ -0,0 +1,400 @@
+use regex::Regex;
+use std::net::IpAddr;
+use std::path::Path;
+
+use crate::configurator::route::RouteConfig;
+use crate::configurator::route::routerule::{
+    DefaultRouteRule, LogicalRouteRule, RouteRule, RuleAction,
+};
+use crate::configurator::shared::cidr::Cidr;
+
+/// Connection to test route rules against
+#[derive(Debug, Clone, Default)]
+pub struct Metadata {
+    pub inbound: Option<String>,
+    pub network: Option<String>,
+    pub protocol: Option<String>,
+    pub domain: Option<String>,
+    pub ip: Option<IpAddr>,
+    pub port: Option<u16>,
+    pub source_ip: Option<IpAddr>,
+    pub source_port: Option<u16>,
+    /// Process name or full path
+    pub process: Option<String>,
+}
+
+impl Metadata {
+    pub fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    /// Ip literal given as domain is destination ip
+    fn ip(&self) -> Option<IpAddr> {
+        self.ip
+            .or_else(|| self.domain.as_deref().and_then(|x| x.parse().ok()))
+    }
+
+    fn domain(&self) -> Option<String> {
+        self.domain
+            .as_deref()
+            .filter(|x| x.parse::<IpAddr>().is_err())
+            .map(|x| x.trim_end_matches('.').to_lowercase())
+    }
+}
+
+/// Result of matching rule, `Unknown` lists matchers which cant be checked here
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Match,
+    NoMatch,
+    Unknown(Vec<String>),
+}
+
+impl From<bool> for Verdict {
+    fn from(value: bool) -> Self {
+        match value {
+            true => Verdict::Match,
+            false => Verdict::NoMatch,
+        }
+    }
+}
+
+impl Verdict {
+    fn unknown(reason: String) -> Self {
+        Verdict::Unknown(vec![reason])
+    }
+
+    /// Match when any matches
+    fn any(items: Vec<Verdict>) -> Self {
+        let mut unknown = vec![];
+        for i in items {
+            match i {
+                Verdict::Match => return Verdict::Match,
+                Verdict::NoMatch => {}
+                Verdict::Unknown(x) => unknown.extend(x),
+            }
+        }
+        match unknown.is_empty() {
+            true => Verdict::NoMatch,
+            false => Verdict::Unknown(unknown),
+        }
+    }
+
+    /// Match when all match
+    fn all(items: Vec<Verdict>) -> Self {
+        let mut unknown = vec![];
+        for i in items {
+            match i {
+                Verdict::Match => {}
+                Verdict::NoMatch => return Verdict::NoMatch,
+                Verdict::Unknown(x) => unknown.extend(x),
+            }
+        }
+        match unknown.is_empty() {
+            true => Verdict::Match,
+            false => Verdict::Unknown(unknown),
+        }
+    }
+
+    fn invert(self, invert: Option<bool>) -> Self {
+        match (self, invert) {
+            (Verdict::Match, Some(true)) => Verdict::NoMatch,
+            (Verdict::NoMatch, Some(true)) => Verdict::Match,
+            (x, _) => x,
+        }
+    }
+}
+
+/// Rule which matched or may match
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub index: usize,
+    pub verdict: Verdict,
+}
+
+#[derive(Debug, Clone)]
+pub struct Explanation {
+    pub steps: Vec<Step>,
+    /// Rule with final action, `None` when `final` outbound is used
+    pub matched: Option<usize>,
+}
+
+/// `route`, `reject` and `hijack-dns` stop matching, other actions go on
+pub fn is_final(action: Option<&RuleAction>) -> bool {
+    matches!(
+        action,
+        None | Some(RuleAction::Route(_) | RuleAction::Reject(_) | RuleAction::HijackDns(_))
+    )
+}
+
+/// Evaluates rules in order as sing-box does
+pub fn explain(route: &RouteConfig, meta: &Metadata) -> Explanation {
+    let mut steps = vec![];
+    for (index, rule) in route.rules.iter().enumerate() {
+        let verdict = match_rule(rule, meta);
+        if verdict == Verdict::NoMatch {
+            continue;
+        }
+
+        let matched = verdict == Verdict::Match && is_final(rule.get_action());
+        steps.push(Step { index, verdict });
+        if matched {
+            return Explanation {
+                steps,
+                matched: Some(index),
+            };
+        }
+    }
+
+    Explanation {
+        steps,
+        matched: None,
+    }
+}
+
+pub fn match_rule(rule: &RouteRule, meta: &Metadata) -> Verdict {
+    match rule {
+        RouteRule::Default(x) => match_default(x, meta),
+        RouteRule::Logical(x) => match_logical(x, meta),
+    }
+}
+
+fn match_logical(rule: &LogicalRouteRule, meta: &Metadata) -> Verdict {
+    let items = rule.rules.iter().map(|x| match_rule(x, meta)).collect();
+    let res = match rule.mode.as_deref() {
+        Some("or") => Verdict::any(items),
+        _ => Verdict::all(items),
+    };
+    res.invert(rule.invert)
+}
+
+/// Fields of one group are joined with OR, groups and other fields with AND
+fn match_default(rule: &DefaultRouteRule, meta: &Metadata) -> Verdict {
+    let domain = meta.domain();
+    let ip = meta.ip();
+    let mut destination = vec![];
+    let mut source = vec![];
+    let mut port = vec![];
+    let mut source_port = vec![];
+    let mut other = vec![];
+
+    if let Some(x) = &rule.domain {
+        destination.push(
+            domain
+                .as_deref()
+                .is_some_and(|d| x.iter().any(|x| x.eq_ignore_ascii_case(d)))
+                .into(),
+        );
+    }
+    if let Some(x) = &rule.domain_suffix {
+        destination.push(
+            domain
+                .as_deref()
+                .is_some_and(|d| x.iter().any(|x| suffix_match(d, x)))
+                .into(),
+        );
+    }
+    if let Some(x) = &rule.domain_keyword {
+        destination.push(
+            domain
+                .as_deref()
+                .is_some_and(|d| x.iter().any(|x| d.contains(&x.to_lowercase())))
+                .into(),
+        );
+    }
+    if let Some(x) = &rule.domain_regex {
+        destination.push(
+            domain
+                .as_deref()
+                .is_some_and(|d| x.iter().any(|x| Regex::new(x).is_ok_and(|x| x.is_match(d))))
+                .into(),
+        );
+    }
+    if let Some(x) = &rule.ip_cidr {
+        destination.push(ip.is_some_and(|ip| cidr_match(x, &ip)).into());
+    }
+    if let Some(x) = rule.ip_is_private {
+        destination.push(ip.is_some_and(|ip| is_private(&ip) == x).into());
+    }
+    if let Some(x) = &rule.source_ip_cidr {
+        source.push(given(meta.source_ip, "source ip", |ip| cidr_match(x, &ip)));
+    }
+    if let Some(x) = rule.source_ip_is_private {
+        source.push(given(meta.source_ip, "source ip", |ip| {
+            is_private(&ip) == x
+        }));
+    }
+    if let Some(x) = &rule.port {
+        port.push(given(meta.port, "port", |p| x.contains(&p)));
+    }
+    if let Some(x) = &rule.port_range {
+        port.push(given(meta.port, "port", |p| {
+            x.iter().any(|x| range_match(x, p))
+        }));
+    }
+    if let Some(x) = &rule.source_port {
+        source_port.push(given(meta.source_port, "source port", |p| x.contains(&p)));
+    }
+    if let Some(x) = &rule.source_port_range {
+        source_port.push(given(meta.source_port, "source port", |p| {
+            x.iter().any(|x| range_match(x, p))
+        }));
+    }
+
+    for (field, values) in [
+        ("geosite", &rule.geosite),
+        ("geoip", &rule.geoip),
+        ("rule_set", &rule.rule_set),
+    ] {
+        if let Some(x) = values {
+            let verdict = Verdict::unknown(format!("{field} {}", x.join(",")));
+            match (field, rule.rule_set_ip_cidr_match_source) {
+                ("rule_set", Some(true)) => source.push(verdict),
+                _ => destination.push(verdict),
+            }
+        }
+    }
+    if let Some(x) = &rule.source_geoip {
+        source.push(Verdict::unknown(format!("source_geoip {}", x.join(","))));
+    }
+
+    if let Some(x) = &rule.inbound {
+        other.push(given(meta.inbound.as_ref(), "inbound", |i| x.contains(i)));
+    }
+    if let Some(x) = &rule.network {
+        other.push(given(meta.network.as_ref(), "network", |n| x.contains(n)));
+    }
+    if let Some(x) = &rule.protocol {
+        other.push(given(meta.protocol.as_ref(), "protocol", |p| x.contains(p)));
+    }
+    if let Some(x) = rule.ip_version {
+        other.push(given(ip, "ip", |ip| match ip {
+            IpAddr::V4(_) => x == 4,
+            IpAddr::V6(_) => x == 6,
+        }));
+    }
+    if rule.process_name.is_some()
+        || rule.process_path.is_some()
+        || rule.process_path_regex.is_some()
+    {
+        other.push(match_process(rule, meta.process.as_deref()));
+    }
+
+    let unsupported = [
+        ("auth_user", rule.auth_user.is_some()),
+        ("client", rule.client.is_some()),
+        ("package_name", rule.package_name.is_some()),
+        ("user", rule.user.is_some()),
+        ("user_id", rule.user_id.is_some()),
+        ("clash_mode", rule.clash_mode.is_some()),
+        ("network_type", rule.network_type.is_some()),
+        ("network_is_expensive", rule.network_is_expensive.is_some()),
+        (
+            "network_is_constrained",
+            rule.network_is_constrained.is_some(),
+        ),
+    ];
+    for (field, _) in unsupported.iter().filter(|x| x.1) {
+        other.push(Verdict::unknown(field.to_string()));
+    }
+
+    for group in [destination, source, port, source_port] {
+        if !group.is_empty() {
+            other.push(Verdict::any(group));
+        }
+    }
+    Verdict::all(other).invert(rule.invert)
+}
+
+/// Unknown when connection has no such value
+fn given<T>(value: Option<T>, name: &str, f: impl FnOnce(T) -> bool) -> Verdict {
+    match value {
+        Some(x) => f(x).into(),
+        None => Verdict::unknown(format!("{name} not given")),
+    }
+}
+
+/// `example.com` matches itself and subdomains, `.example.com` only subdomains
+fn suffix_match(domain: &str, suffix: &str) -> bool {
+    let suffix = suffix.to_lowercase();
+    match suffix.starts_with('.') {
+        true => domain.ends_with(&suffix),
+        false => domain == suffix || domain.ends_with(&format!(".{suffix}")),
+    }
+}
+
+fn cidr_match(cidrs: &[String], ip: &IpAddr) -> bool {
+    cidrs
+        .iter()
+        .filter_map(|x| x.parse::<Cidr>().ok())
+        .any(|x| x.contains(ip))
+}
+
+/// `1000:2000`, `:3000` or `4000:`
+fn range_match(range: &str, port: u16) -> bool {
+    let Some((start, end)) = range.split_once(':') else {
+        return false;
+    };
+    let start = match start {
+        "" => Some(0),
+        x => x.parse().ok(),
+    };
+    let end = match end {
+        "" => Some(u16::MAX),
+        x => x.parse().ok(),
+    };
+    matches!((start, end), (Some(start), Some(end)) if (start..=end).contains(&port))
+}
+
+fn is_private(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(x) => {
+            x.is_private()
+                || x.is_loopback()
+                || x.is_link_local()
+                || x.is_unspecified()
+                || x.is_broadcast()
+        }
+        IpAddr::V6(x) => {
+            x.is_loopback()
+                || x.is_unspecified()
+                || (x.segments()[0] & 0xfe00) == 0xfc00
+                || (x.segments()[0] & 0xffc0) == 0xfe80
+        }
+    }
+}
+
+/// Name is enough for `process_name`, path matchers need full path
+fn match_process(rule: &DefaultRouteRule, process: Option<&str>) -> Verdict {
+    let Some(process) = process else {
+        return Verdict::unknown("process not given".to_string());
+    };
+    let path = process.contains('/').then_some(process);
+    let name = Path::new(process)
+        .file_name()
+        .and_then(|x| x.to_str())
+        .unwrap_or(process);
+
+    let mut items = vec![];
+    if let Some(x) = &rule.process_name {
+        items.push(x.iter().any(|x| x == name).into());
+    }
+    if let Some(x) = &rule.process_path {
+        items.push(match path {
+            Some(path) => x.iter().any(|x| x == path).into(),
+            None => Verdict::unknown("process_path, give full path".to_string()),
+        });
+    }
+    if let Some(x) = &rule.process_path_regex {
+        items.push(match path {
+            Some(path) => x
+                .iter()
+                .any(|x| Regex::new(x).is_ok_and(|x| x.is_match(path)))
+                .into(),
+            None => Verdict::unknown("process_path_regex, give full path".to_string()),
+        });
+    }
+    Verdict::all(items)
+}
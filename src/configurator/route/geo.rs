@@ -0,0 +1,152 @@
+use crate::configurator::route::RouteConfig;
+use crate::configurator::route::routerule::{DefaultRouteRule, LogicalRouteRule, RouteRule};
+use crate::configurator::route::ruleset::{RuleSet, RuleSetRemote};
+
+pub const GEOSITE_URL: &str =
+    "https://raw.githubusercontent.com/SagerNet/sing-geosite/rule-set/geosite-{name}.srs";
+pub const GEOIP_URL: &str =
+    "https://raw.githubusercontent.com/SagerNet/sing-geoip/rule-set/geoip-{name}.srs";
+
+/// Urls of remote rule sets which replace geosite and geoip,
+/// `{name}` is replaced with geosite or geoip code
+#[derive(Debug, Clone)]
+pub struct GeoTemplates {
+    pub geosite: String,
+    pub geoip: String,
+}
+
+impl Default for GeoTemplates {
+    fn default() -> Self {
+        Self {
+            geosite: GEOSITE_URL.to_string(),
+            geoip: GEOIP_URL.to_string(),
+        }
+    }
+}
+
+impl GeoTemplates {
+    fn url(&self, kind: &str, name: &str) -> String {
+        let template = match kind {
+            "geosite" => &self.geosite,
+            _ => &self.geoip,
+        };
+        template.replace("{name}", name)
+    }
+}
+
+/// Rewrites `geosite`, `geoip` and `source_geoip` of route rules into
+/// `rule_set` references and adds missing remote rule sets.
+/// Returns one line per change
+pub fn migrate(route: &mut RouteConfig, templates: &GeoTemplates) -> Vec<String> {
+    let mut report = vec![];
+    let mut tags = vec![];
+
+    for (i, rule) in route.rules.iter_mut().enumerate() {
+        migrate_rule(
+            rule,
+            &format!("route rule {}", i + 1),
+            &mut tags,
+            &mut report,
+        );
+    }
+
+    for (kind, tag) in tags {
+        if route.get_rule_set_tags().contains(&tag) {
+            continue;
+        }
+
+        let name = tag.trim_start_matches(&format!("{kind}-")).to_string();
+        let url = templates.url(&kind, &name);
+        let rule_set = RuleSetRemote {
+            tag: Some(tag.clone()),
+            format: Some("binary".to_string()),
+            url: Some(url.clone()),
+            ..Default::default()
+        };
+        if route.add_rule_set(RuleSet::Remote(rule_set)).is_ok() {
+            report.push(format!("rule set {tag} added: {url}"));
+        }
+    }
+    report
+}
+
+fn migrate_rule(
+    rule: &mut RouteRule,
+    place: &str,
+    tags: &mut Vec<(String, String)>,
+    report: &mut Vec<String>,
+) {
+    match rule {
+        RouteRule::Default(x) if needs_split(x) => {
+            let mut source = DefaultRouteRule {
+                source_geoip: x.source_geoip.take(),
+                ..Default::default()
+            };
+            migrate_default(x, place, tags, report);
+            migrate_default(&mut source, place, tags, report);
+            report.push(format!(
+                "{place}: source_geoip mixed with other rule sets, split into logical and rule"
+            ));
+
+            let mut destination = std::mem::take(x);
+            let logical = LogicalRouteRule {
+                mode: Some("and".to_string()),
+                invert: destination.invert.take(),
+                action: destination.action.take(),
+                ..LogicalRouteRule::new()
+            };
+            *rule = RouteRule::Logical(logical.add_rule(destination).add_rule(source));
+        }
+        RouteRule::Default(x) => migrate_default(x, place, tags, report),
+        RouteRule::Logical(x) => {
+            for (i, rule) in x.rules.iter_mut().enumerate() {
+                migrate_rule(rule, &format!("{place}.{}", i + 1), tags, report);
+            }
+        }
+    }
+}
+
+/// `rule_set_ip_cidr_match_source` applies to every rule set of the rule,
+/// so source geoip sets can't share a rule with destination ones
+fn needs_split(rule: &DefaultRouteRule) -> bool {
+    rule.source_geoip.as_ref().is_some_and(|x| !x.is_empty())
+        && rule.rule_set_ip_cidr_match_source != Some(true)
+        && (rule.geosite.is_some() || rule.geoip.is_some() || rule.rule_set.is_some())
+}
+
+fn migrate_default(
+    rule: &mut DefaultRouteRule,
+    place: &str,
+    tags: &mut Vec<(String, String)>,
+    report: &mut Vec<String>,
+) {
+    let source = rule.source_geoip.take().unwrap_or_default();
+    if !source.is_empty() {
+        rule.rule_set_ip_cidr_match_source = Some(true);
+    }
+
+    let fields = [
+        (
+            "geosite",
+            "geosite",
+            rule.geosite.take().unwrap_or_default(),
+        ),
+        ("geoip", "geoip", rule.geoip.take().unwrap_or_default()),
+        ("source_geoip", "geoip", source),
+    ];
+
+    for (field, kind, names) in fields {
+        for name in names {
+            let tag = format!("{kind}-{name}");
+            report.push(format!("{place}: {field} {name} -> rule_set {tag}"));
+
+            let rule_set = rule.rule_set.get_or_insert_with(Vec::new);
+            if !rule_set.contains(&tag) {
+                rule_set.push(tag.clone());
+            }
+            if !tags.iter().any(|x| x.1 == tag) {
+                tags.push((kind.to_string(), tag));
+            }
+        }
+    }
+}
@@ -1,16 +1,155 @@
 use macros::auto_skip_none;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, de};
+use std::fmt;
 
 use crate::configurator::inbound::InboundConfig;
 use crate::configurator::outbound::OutboundConfig;
+use crate::configurator::shared::{is_logical_rule, rule_error};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Clone)]
 #[serde(untagged)]
 pub enum RouteRule {
     Logical(LogicalRouteRule),
     Default(DefaultRouteRule),
 }
 
+/// Variant is picked before deserializing so errors name the field of the rule
+impl<'de> Deserialize<'de> for RouteRule {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        match is_logical_rule(&value) {
+            true => LogicalRouteRule::deserialize(&value)
+                .map(Self::Logical)
+                .map_err(|e| rule_error::<LogicalRouteRule>(&value, e)),
+            false => DefaultRouteRule::deserialize(&value)
+                .map(Self::Default)
+                .map_err(|e| rule_error::<DefaultRouteRule>(&value, e)),
+        }
+        .map_err(de::Error::custom)
+    }
+}
+
+pub const LOGICAL_MODES: &[&str] = &["and", "or"];
+
+impl RouteRule {
+    pub fn get_action(&self) -> Option<&RuleAction> {
+        match self {
+            RouteRule::Default(x) => x.action.as_ref(),
+            RouteRule::Logical(x) => x.action.as_ref(),
+        }
+    }
+
+    pub fn set_action(&mut self, action: Option<RuleAction>) {
+        match self {
+            RouteRule::Default(x) => x.action = action,
+            RouteRule::Logical(x) => x.action = action,
+        }
+    }
+
+    pub fn toggle_invert(&mut self) {
+        let invert = match self {
+            RouteRule::Default(x) => &mut x.invert,
+            RouteRule::Logical(x) => &mut x.invert,
+        };
+        *invert = match invert {
+            Some(true) => None,
+            _ => Some(true),
+        };
+    }
+}
+
+impl From<DefaultRouteRule> for RouteRule {
+    fn from(value: DefaultRouteRule) -> Self {
+        RouteRule::Default(value)
+    }
+}
+
+impl From<LogicalRouteRule> for RouteRule {
+    fn from(value: LogicalRouteRule) -> Self {
+        RouteRule::Logical(value)
+    }
+}
+
+fn format_value(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(x) => x.clone(),
+        serde_json::Value::Array(x) => x.iter().map(format_value).collect::<Vec<_>>().join(","),
+        x => x.to_string(),
+    }
+}
+
+/// Short one line form: `domain_suffix=google.com port=443 -> route proxy`,
+/// nested rules have no action part
+impl fmt::Display for RouteRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let conditions = match self {
+            RouteRule::Default(x) => {
+                let mut value = serde_json::to_value(x).map_err(|_| fmt::Error)?;
+                if let (Some(map), Some(action)) = (value.as_object_mut(), &x.action) {
+                    let action = serde_json::to_value(action).map_err(|_| fmt::Error)?;
+                    for key in action.as_object().into_iter().flat_map(|x| x.keys()) {
+                        map.remove(key);
+                    }
+                }
+                value
+                    .as_object()
+                    .map(|map| {
+                        map.iter()
+                            .map(|(key, value)| format!("{key}={}", format_value(value)))
+                            .collect::<Vec<_>>()
+                            .join(" ")
+                    })
+                    .unwrap_or_default()
+            }
+            RouteRule::Logical(x) => format!(
+                "{}{}({} rules)",
+                match x.invert {
+                    Some(true) => "not ",
+                    _ => "",
+                },
+                x.mode.as_deref().unwrap_or("and"),
+                x.rules.len()
+            ),
+        };
+
+        match self.get_action() {
+            Some(action) if conditions.is_empty() => write!(f, "{action}"),
+            Some(action) => write!(f, "{conditions} -> {action}"),
+            None => write!(f, "{conditions}"),
+        }
+    }
+}
+
+/// Action name with its main argument and other options:
+/// `route proxy tls_fragment=true`, `sniff 1s sniffer=http,tls`
+impl fmt::Display for RuleAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut value = serde_json::to_value(self).map_err(|_| fmt::Error)?;
+        let Some(map) = value.as_object_mut() else {
+            return write!(f, "{}", self.name());
+        };
+        map.remove("action");
+
+        write!(f, "{}", self.name())?;
+        let argument = match self {
+            RuleAction::Route(_) => "outbound",
+            RuleAction::Reject(_) => "method",
+            RuleAction::Sniff(_) => "timeout",
+            RuleAction::Resolve(_) => "server",
+            _ => "",
+        };
+        match map.remove(argument) {
+            Some(x) => write!(f, " {}", format_value(&x))?,
+            None if matches!(self, RuleAction::Reject(_)) => write!(f, " default")?,
+            None => {}
+        }
+        for (key, x) in map.iter() {
+            write!(f, " {key}={}", format_value(x))?;
+        }
+        Ok(())
+    }
+}
+
 #[auto_skip_none]
 #[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct DefaultRouteRule {
@@ -40,15 +179,15 @@ pub struct DefaultRouteRule {
     pub process_path_regex: Option<Vec<String>>,
     pub package_name: Option<Vec<String>>,
     pub user: Option<Vec<String>>,
-    pub user_id: Option<Vec<u16>>,
+    pub user_id: Option<Vec<u32>>,
     pub clash_mode: Option<String>,
     pub network_type: Option<Vec<String>>,
     pub network_is_expensive: Option<bool>,
     pub network_is_constrained: Option<bool>,
     pub rule_set: Option<Vec<String>>,
-    pub rule_set_ip_cidr_match_source: Option<Vec<String>>,
+    pub rule_set_ip_cidr_match_source: Option<bool>,
     pub invert: Option<bool>,
-    #[serde(flatten)]
+    #[serde(flatten, deserialize_with = "deserialize_optional")]
     pub action: Option<RuleAction>,
 }
 
@@ -108,6 +247,11 @@ impl DefaultRouteRule {
         }
     }
 
+    pub fn set_action(mut self, action: RuleAction) -> Self {
+        self.action = Some(action);
+        self
+    }
+
     pub fn add_inbound(mut self, inbound: &str) -> Self {
         self.inbound
             .get_or_insert_with(Vec::new)
@@ -190,6 +334,13 @@ impl DefaultRouteRule {
         self
     }
 
+    pub fn add_rule_set(mut self, rule_set: &str) -> Self {
+        self.rule_set
+            .get_or_insert_with(Vec::new)
+            .push(rule_set.to_string());
+        self
+    }
+
     pub fn add_source_geoip(mut self, source_geoip: &str) -> Self {
         self.source_geoip
             .get_or_insert_with(Vec::new)
@@ -289,7 +440,7 @@ impl DefaultRouteRule {
         self
     }
 
-    pub fn add_user_id(mut self, user_id: u16) -> Self {
+    pub fn add_user_id(mut self, user_id: u32) -> Self {
         self.user_id.get_or_insert_with(Vec::new).push(user_id);
         self
     }
@@ -329,15 +480,15 @@ pub struct LogicalRouteRule {
     pub mode: Option<String>,
     pub rules: Vec<RouteRule>,
     pub invert: Option<bool>,
-    #[serde(flatten)]
-    pub action: RuleAction,
+    #[serde(flatten, deserialize_with = "deserialize_optional")]
+    pub action: Option<RuleAction>,
 }
 
 impl LogicalRouteRule {
     pub fn new() -> Self {
         Self {
             rule_type: Some("logical".to_string()),
-            action: RuleAction::Reject(RejectAction::new()),
+            action: Some(RuleAction::Reject(RejectAction::new())),
             rules: vec![],
             ..Default::default()
         }
@@ -346,7 +497,7 @@ impl LogicalRouteRule {
     pub fn or() -> Self {
         Self {
             rule_type: Some("logical".to_string()),
-            action: RuleAction::Reject(RejectAction::new()),
+            action: Some(RuleAction::Reject(RejectAction::new())),
             rules: vec![],
             mode: Some("or".to_string()),
             ..Default::default()
@@ -358,76 +509,207 @@ impl LogicalRouteRule {
         self
     }
 
-    pub fn add_rule(mut self, rule: DefaultRouteRule) -> Self {
-        self.rules.push(RouteRule::Default(rule));
+    /// Adds default or logical rule, action of nested rule is dropped
+    pub fn add_rule(mut self, rule: impl Into<RouteRule>) -> Self {
+        let mut rule = rule.into();
+        rule.set_action(None);
+        self.rules.push(rule);
+        self
+    }
+
+    pub fn set_invert(mut self, invert: bool) -> Self {
+        self.invert = invert.then_some(true);
+        self
+    }
+
+    pub fn set_action(mut self, action: Option<RuleAction>) -> Self {
+        self.action = action;
         self
     }
 
     pub fn set_sniff_action(mut self, timeout: String) -> Self {
-        self.action = RuleAction::Sniff(SniffAction::new().set_timeout(timeout));
+        self.action = Some(RuleAction::Sniff(SniffAction::new().set_timeout(timeout)));
         self
     }
 
     pub fn set_hijack_dns_action(mut self) -> Self {
-        self.action = RuleAction::HijackDns(HijackDnsAction::new());
+        self.action = Some(RuleAction::HijackDns(HijackDnsAction::new()));
         self
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-#[serde(untagged)]
+pub const SNIFFERS: &[&str] = &[
+    "http",
+    "tls",
+    "quic",
+    "stun",
+    "dns",
+    "bittorrent",
+    "dtls",
+    "ssh",
+    "rdp",
+    "ntp",
+];
+pub const DOMAIN_STRATEGIES: &[&str] = &["prefer_ipv4", "prefer_ipv6", "ipv4_only", "ipv6_only"];
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "action", rename_all = "kebab-case")]
 pub enum RuleAction {
     Route(RouteAction),
+    RouteOptions(RouteOptions),
     Reject(RejectAction),
     HijackDns(HijackDnsAction),
     Sniff(SniffAction),
     Resolve(ResolveAction),
 }
 
-#[auto_skip_none]
+/// Flattened action of a rule: `None` when rule has no action fields
+/// (sub rules of logical rules)
+pub fn deserialize_optional<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<RuleAction>, D::Error> {
+    let value = serde_json::Value::deserialize(deserializer)?;
+    let has_action = value
+        .as_object()
+        .is_some_and(|map| ["action", "outbound"].iter().any(|x| map.contains_key(*x)));
+
+    match has_action {
+        true => RuleAction::deserialize(value)
+            .map(Some)
+            .map_err(de::Error::custom),
+        false => Ok(None),
+    }
+}
+
+/// `action` field selects the variant, rules without it are routed
+/// as in sing-box
+impl<'de> Deserialize<'de> for RuleAction {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let action = value
+            .get("action")
+            .and_then(|x| x.as_str())
+            .unwrap_or("route")
+            .to_string();
+
+        match action.as_str() {
+            "route" => RouteAction::deserialize(value).map(Self::Route),
+            "route-options" => RouteOptions::deserialize(value).map(Self::RouteOptions),
+            "reject" => RejectAction::deserialize(value).map(Self::Reject),
+            "hijack-dns" => Ok(Self::HijackDns(HijackDnsAction::new())),
+            "sniff" => SniffAction::deserialize(value).map(Self::Sniff),
+            "resolve" => ResolveAction::deserialize(value).map(Self::Resolve),
+            x => {
+                return Err(de::Error::custom(format!(
+                    "unknown route rule action '{x}'"
+                )));
+            }
+        }
+        .map_err(de::Error::custom)
+    }
+}
+
+impl RuleAction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RuleAction::Route(_) => "route",
+            RuleAction::RouteOptions(_) => "route-options",
+            RuleAction::Reject(_) => "reject",
+            RuleAction::HijackDns(_) => "hijack-dns",
+            RuleAction::Sniff(_) => "sniff",
+            RuleAction::Resolve(_) => "resolve",
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct RouteAction {
     pub outbound: String,
+    #[serde(flatten)]
+    pub options: RouteOptions,
+}
+
+impl RouteAction {
+    pub fn new(outbound: String) -> Self {
+        Self {
+            outbound,
+            ..Default::default()
+        }
+    }
+
+    pub fn set_options(mut self, options: RouteOptions) -> Self {
+        self.options = options;
+        self
+    }
+}
+
+/// Options of `route` and `route-options` actions
+#[auto_skip_none]
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct RouteOptions {
     pub override_address: Option<String>,
     pub override_port: Option<u16>,
     pub network_strategy: Option<String>,
+    pub network_type: Option<Vec<String>>,
+    pub fallback_network_type: Option<Vec<String>>,
     pub fallback_delay: Option<String>,
     pub udp_disable_domain_unmapping: Option<bool>,
     pub udp_connect: Option<bool>,
     pub udp_timeout: Option<String>,
     pub tls_fragment: Option<bool>,
     pub tls_fragment_fallback_delay: Option<String>,
-    pub tls_record_fragment: Option<String>,
+    pub tls_record_fragment: Option<bool>,
 }
 
-impl RouteAction {
-    pub fn new(outbound: String) -> Self {
+impl RouteOptions {
+    pub fn new() -> Self {
         Self {
-            outbound,
             ..Default::default()
         }
     }
+
+    pub fn set_override_address(mut self, address: &str) -> Self {
+        self.override_address = Some(address.to_string());
+        self
+    }
+
+    pub fn set_override_port(mut self, port: u16) -> Self {
+        self.override_port = Some(port);
+        self
+    }
+
+    pub fn set_udp_connect(mut self, udp_connect: bool) -> Self {
+        self.udp_connect = Some(udp_connect);
+        self
+    }
+
+    pub fn set_tls_fragment(mut self, tls_fragment: bool) -> Self {
+        self.tls_fragment = Some(tls_fragment);
+        self
+    }
+
+    pub fn set_tls_record_fragment(mut self, tls_record_fragment: bool) -> Self {
+        self.tls_record_fragment = Some(tls_record_fragment);
+        self
+    }
 }
 
 #[auto_skip_none]
 #[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct RejectAction {
-    pub action: String,
-    pub method: String,
+    pub method: Option<String>,
     pub no_drop: Option<bool>,
 }
 
 impl RejectAction {
     pub fn new() -> Self {
         Self {
-            action: "reject".to_string(),
-            method: "default".to_string(),
             ..Default::default()
         }
     }
 
     pub fn set_method(mut self, method: String) -> Self {
-        self.method = method;
+        self.method = Some(method);
         self
     }
 
@@ -437,24 +719,18 @@ impl RejectAction {
     }
 }
 
-#[auto_skip_none]
 #[derive(Debug, Serialize, Deserialize, Default, Clone)]
-pub struct HijackDnsAction {
-    pub action: String,
-}
+pub struct HijackDnsAction {}
 
 impl HijackDnsAction {
     pub fn new() -> Self {
-        Self {
-            action: "hijack-dns".to_string(),
-        }
+        Self {}
     }
 }
 
 #[auto_skip_none]
 #[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct SniffAction {
-    pub action: String,
     pub sniffer: Option<Vec<String>>,
     pub timeout: Option<String>,
 }
@@ -462,21 +738,28 @@ pub struct SniffAction {
 impl SniffAction {
     pub fn new() -> Self {
         Self {
-            action: "sniff".to_string(),
             ..Default::default()
         }
     }
+
     pub fn set_timeout(mut self, timeout: String) -> Self {
         self.timeout = Some(timeout);
         self
     }
+
+    pub fn add_sniffer(mut self, sniffer: &str) -> Self {
+        self.sniffer
+            .get_or_insert_with(Vec::new)
+            .push(sniffer.to_string());
+        self
+    }
 }
 
 #[auto_skip_none]
 #[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct ResolveAction {
-    pub server: String,
-    pub strategy: String,
+    pub server: Option<String>,
+    pub strategy: Option<String>,
     pub disable_cache: Option<bool>,
     pub rewrite_ttl: Option<u32>,
     pub client_subnet: Option<String>,
@@ -485,11 +768,19 @@ pub struct ResolveAction {
 impl ResolveAction {
     pub fn new() -> Self {
         Self {
-            server: "".to_string(),
-            strategy: "".to_string(),
             ..Default::default()
         }
     }
+
+    pub fn set_server(mut self, server: &str) -> Self {
+        self.server = Some(server.to_string());
+        self
+    }
+
+    pub fn set_strategy(mut self, strategy: &str) -> Self {
+        self.strategy = Some(strategy.to_string());
+        self
+    }
 }
 
 impl Default for RuleAction {
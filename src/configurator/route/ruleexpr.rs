@@ -0,0 +1,589 @@
+use serde_json::{Map, Value};
+use std::fmt;
+
+use crate::configurator::route::routerule::{
+    DefaultRouteRule, LogicalRouteRule, RouteRule, RuleAction,
+};
+use crate::configurator::shared::cidr::Cidr;
+
+/// Value kind of rule field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    List,
+    Port,
+    Uid,
+    Bool,
+    Version,
+    Single,
+}
+
+/// Route rule fields usable in expressions and their value kinds
+const FIELDS: &[(&str, Kind)] = &[
+    ("inbound", Kind::List),
+    ("ip_version", Kind::Version),
+    ("network", Kind::List),
+    ("auth_user", Kind::List),
+    ("protocol", Kind::List),
+    ("client", Kind::List),
+    ("domain", Kind::List),
+    ("domain_suffix", Kind::List),
+    ("domain_keyword", Kind::List),
+    ("domain_regex", Kind::List),
+    ("geosite", Kind::List),
+    ("source_geoip", Kind::List),
+    ("geoip", Kind::List),
+    ("source_ip_cidr", Kind::List),
+    ("ip_is_private", Kind::Bool),
+    ("ip_cidr", Kind::List),
+    ("source_ip_is_private", Kind::Bool),
+    ("source_port", Kind::Port),
+    ("source_port_range", Kind::List),
+    ("port", Kind::Port),
+    ("port_range", Kind::List),
+    ("process_name", Kind::List),
+    ("process_path", Kind::List),
+    ("process_path_regex", Kind::List),
+    ("package_name", Kind::List),
+    ("user", Kind::List),
+    ("user_id", Kind::Uid),
+    ("clash_mode", Kind::Single),
+    ("network_type", Kind::List),
+    ("network_is_expensive", Kind::Bool),
+    ("network_is_constrained", Kind::Bool),
+    ("rule_set", Kind::List),
+];
+
+/// sing-box matches fields of one group with OR, groups with AND,
+/// so matchers of one group cant be joined by `and` in a single rule
+const GROUPS: &[&[&str]] = &[
+    &[
+        "domain",
+        "domain_suffix",
+        "domain_keyword",
+        "domain_regex",
+        "geosite",
+        "geoip",
+        "ip_cidr",
+        "ip_is_private",
+        "rule_set",
+    ],
+    &["port", "port_range"],
+    &["source_geoip", "source_ip_cidr", "source_ip_is_private"],
+    &["source_port", "source_port_range"],
+];
+
+fn group(field: &str) -> &str {
+    GROUPS
+        .iter()
+        .find(|x| x.contains(&field))
+        .map_or(field, |x| x[0])
+}
+
+/// Parse error with 1-based column of the wrong token
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExprError {
+    pub column: usize,
+    pub message: String,
+}
+
+impl ExprError {
+    fn new(column: usize, message: impl Into<String>) -> Self {
+        Self {
+            column,
+            message: message.into(),
+        }
+    }
+
+    /// Input with `^` under the wrong token
+    pub fn pointer(&self, input: &str) -> String {
+        format!("{input}\n{}^", " ".repeat(self.column.saturating_sub(1)))
+    }
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "column {}: {}", self.column, self.message)
+    }
+}
+
+impl std::error::Error for ExprError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Open,
+    Close,
+    ListOpen,
+    ListClose,
+    Comma,
+    End,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(x) => write!(f, "'{x}'"),
+            Token::Open => write!(f, "'('"),
+            Token::Close => write!(f, "')'"),
+            Token::ListOpen => write!(f, "'['"),
+            Token::ListClose => write!(f, "']'"),
+            Token::Comma => write!(f, "','"),
+            Token::End => write!(f, "end of rule"),
+        }
+    }
+}
+
+/// Words are split by spaces and `()[],`, double quotes keep them as is
+fn tokenize(input: &str) -> Result<Vec<(Token, usize)>, ExprError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut res = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        let column = i + 1;
+        let token = match chars[i] {
+            x if x.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '[' => Token::ListOpen,
+            ']' => Token::ListClose,
+            ',' => Token::Comma,
+            '"' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|x| *x == '"')
+                    .ok_or_else(|| ExprError::new(column, "unclosed '\"'"))?;
+                let word = chars[i + 1..i + 1 + end].iter().collect();
+                res.push((Token::Word(word), column));
+                i += end + 2;
+                continue;
+            }
+            _ => {
+                let end = chars[i..]
+                    .iter()
+                    .position(|x| x.is_whitespace() || "()[],\"".contains(*x))
+                    .unwrap_or(chars.len() - i);
+                res.push((Token::Word(chars[i..i + end].iter().collect()), column));
+                i += end;
+                continue;
+            }
+        };
+        res.push((token, column));
+        i += 1;
+    }
+
+    res.push((Token::End, chars.len() + 1));
+    Ok(res)
+}
+
+/// Action part of expression, outbound names are resolved by caller
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExprAction {
+    /// `proxy`, `direct`, `route <tag>` or plain outbound tag/type
+    Route(String),
+    /// `route-options`
+    RouteOptions,
+    /// `reject [method]`
+    Reject(Option<String>),
+    /// `hijack-dns`
+    HijackDns,
+    /// `sniff [timeout]`
+    Sniff(Option<String>),
+    /// `resolve [dns server tag]`
+    Resolve(Option<String>),
+}
+
+#[derive(Debug, Clone)]
+struct Matcher {
+    field: &'static str,
+    kind: Kind,
+    values: Vec<(String, usize)>,
+}
+
+#[derive(Debug, Clone)]
+enum Cond {
+    Match(Matcher),
+    Not(Box<Cond>),
+    And(Vec<Cond>),
+    Or(Vec<Cond>),
+}
+
+/// Parsed `<action> [options] [if <condition>]`
+#[derive(Debug, Clone)]
+pub struct RuleExpr {
+    pub action: ExprAction,
+    /// `key=value` options of action in sing-box names
+    pub options: Map<String, Value>,
+    cond: Option<Cond>,
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &(Token, usize) {
+        &self.tokens[self.pos]
+    }
+
+    fn next(&mut self) -> (Token, usize) {
+        let res = self.tokens[self.pos].clone();
+        if self.pos + 1 < self.tokens.len() {
+            self.pos += 1;
+        }
+        res
+    }
+
+    fn is_word(&self, word: &str) -> bool {
+        matches!(&self.peek().0, Token::Word(x) if x == word)
+    }
+
+    fn unexpected(&self, expected: &str) -> ExprError {
+        let (token, column) = self.peek();
+        ExprError::new(*column, format!("expected {expected}, found {token}"))
+    }
+
+    fn word(&mut self, expected: &str) -> Result<(String, usize), ExprError> {
+        match self.peek().clone() {
+            (Token::Word(x), column) => {
+                self.next();
+                Ok((x, column))
+            }
+            _ => Err(self.unexpected(expected)),
+        }
+    }
+
+    fn action(&mut self) -> Result<ExprAction, ExprError> {
+        let (word, _) = self.word("action")?;
+        let mut argument = || match self.peek() {
+            (Token::Word(x), _) if x != "if" && !x.contains('=') => {
+                Some(self.word("").map(|x| x.0))
+            }
+            _ => None,
+        };
+
+        Ok(match word.as_str() {
+            "reject" => ExprAction::Reject(argument().transpose()?),
+            "sniff" => ExprAction::Sniff(argument().transpose()?),
+            "resolve" => ExprAction::Resolve(argument().transpose()?),
+            "hijack-dns" => ExprAction::HijackDns,
+            "route-options" => ExprAction::RouteOptions,
+            "route" => ExprAction::Route(self.word("outbound tag")?.0),
+            _ => ExprAction::Route(word),
+        })
+    }
+
+    /// `key=value`, `key="value"` or `key=[a, b]` words after action
+    fn options(&mut self) -> Result<Map<String, Value>, ExprError> {
+        let mut res = Map::new();
+        while let (Token::Word(word), column) = self.peek().clone() {
+            let Some((key, value)) = word.split_once('=') else {
+                break;
+            };
+            self.next();
+            if key.is_empty() {
+                return Err(ExprError::new(column, "option name is missing"));
+            }
+
+            let value = match value {
+                "" if self.peek().0 == Token::ListOpen => {
+                    Value::Array(self.values()?.iter().map(|x| option_value(&x.0)).collect())
+                }
+                "" => option_value(&self.word("option value")?.0),
+                x => option_value(x),
+            };
+            if res.insert(key.to_string(), value).is_some() {
+                return Err(ExprError::new(
+                    column,
+                    format!("option '{key}' is set twice"),
+                ));
+            }
+        }
+        Ok(res)
+    }
+
+    /// One value or list of them: `[a, b]`
+    fn values(&mut self) -> Result<Vec<(String, usize)>, ExprError> {
+        if self.peek().0 != Token::ListOpen {
+            return Ok(vec![self.word("value")?]);
+        }
+
+        self.next();
+        let mut values = vec![self.word("value")?];
+        while self.peek().0 == Token::Comma {
+            self.next();
+            values.push(self.word("value")?);
+        }
+        match self.next() {
+            (Token::ListClose, _) => Ok(values),
+            (token, column) => Err(ExprError::new(
+                column,
+                format!("expected ',' or ']', found {token}"),
+            )),
+        }
+    }
+
+    fn or(&mut self) -> Result<Cond, ExprError> {
+        let mut items = vec![self.and()?];
+        while self.is_word("or") {
+            self.next();
+            items.push(self.and()?);
+        }
+        Ok(match items.len() {
+            1 => items.remove(0),
+            _ => Cond::Or(items),
+        })
+    }
+
+    fn and(&mut self) -> Result<Cond, ExprError> {
+        let mut items = vec![self.unary()?];
+        while self.is_word("and") {
+            self.next();
+            items.push(self.unary()?);
+        }
+        Ok(match items.len() {
+            1 => items.remove(0),
+            _ => Cond::And(items),
+        })
+    }
+
+    fn unary(&mut self) -> Result<Cond, ExprError> {
+        if self.is_word("not") {
+            self.next();
+            return Ok(Cond::Not(Box::new(self.unary()?)));
+        }
+        if self.peek().0 == Token::Open {
+            self.next();
+            let res = self.or()?;
+            match self.next() {
+                (Token::Close, _) => return Ok(res),
+                (token, column) => {
+                    return Err(ExprError::new(
+                        column,
+                        format!("expected ')', found {token}"),
+                    ));
+                }
+            }
+        }
+        self.matcher()
+    }
+
+    fn matcher(&mut self) -> Result<Cond, ExprError> {
+        let (name, column) = self.word("rule field")?;
+        let (field, kind) = FIELDS
+            .iter()
+            .find(|x| x.0 == name)
+            .copied()
+            .ok_or_else(|| ExprError::new(column, format!("unknown rule field '{name}'")))?;
+
+        if self.is_word("in") {
+            self.next();
+        }
+
+        let values = match self.peek().0 {
+            // `ip_is_private` alone means true
+            Token::Word(_) | Token::End | Token::Close
+                if kind == Kind::Bool && !self.is_word("true") && !self.is_word("false") =>
+            {
+                vec![("true".to_string(), column)]
+            }
+            _ => self.values()?,
+        };
+
+        if values.len() > 1 && !matches!(kind, Kind::List | Kind::Port | Kind::Uid) {
+            return Err(ExprError::new(column, format!("{field} takes one value")));
+        }
+        for (value, column) in &values {
+            let ok = match kind {
+                Kind::List if matches!(field, "ip_cidr" | "source_ip_cidr") => {
+                    value.parse::<Cidr>().is_ok()
+                }
+                Kind::List | Kind::Single => true,
+                Kind::Port => value.parse::<u16>().is_ok(),
+                Kind::Uid => value.parse::<u32>().is_ok(),
+                Kind::Bool => value.parse::<bool>().is_ok(),
+                Kind::Version => matches!(value.as_str(), "4" | "6"),
+            };
+            if !ok {
+                return Err(ExprError::new(
+                    *column,
+                    format!("'{value}' is not a valid {field}"),
+                ));
+            }
+        }
+
+        Ok(Cond::Match(Matcher {
+            field,
+            kind,
+            values,
+        }))
+    }
+}
+
+impl RuleExpr {
+    /// Parses `<action> [options] [if <condition>]`
+    ///
+    /// ACTION: `proxy`, `direct`, `<outbound tag>`, `route <tag>`, `route-options`,
+    /// `reject [default|drop]`, `hijack-dns`, `sniff [timeout]`, `resolve [server]`
+    ///
+    /// OPTIONS: `key=value` or `key=[a, b]` in sing-box names,
+    /// e.g. `tls_fragment=true`, `override_port=443`, `sniffer=[tls, http]`
+    ///
+    /// CONDITION: `<field> <value>`, `<field> in [<value>, ...]`,
+    /// joined with `and`, `or`, `not` and parentheses
+    ///
+    /// Example: `proxy if domain_suffix in [google.com, youtube.com] and port 443`
+    pub fn parse(input: &str) -> Result<Self, ExprError> {
+        let mut parser = Parser {
+            tokens: tokenize(input)?,
+            pos: 0,
+        };
+
+        let action = parser.action()?;
+        let options = parser.options()?;
+        let cond = match parser.peek().0 {
+            Token::End => None,
+            _ if parser.is_word("if") => {
+                parser.next();
+                Some(parser.or()?)
+            }
+            _ => return Err(parser.unexpected("'if'")),
+        };
+
+        match parser.peek().0 {
+            Token::End => Ok(Self {
+                action,
+                options,
+                cond,
+            }),
+            _ => Err(parser.unexpected("'and', 'or' or end of rule")),
+        }
+    }
+
+    /// Builds route rule with resolved action
+    pub fn to_route_rule(&self, action: RuleAction) -> Result<RouteRule, ExprError> {
+        let mut rule = match &self.cond {
+            Some(x) => build(x)?,
+            None => RouteRule::Default(DefaultRouteRule::new()),
+        };
+        rule.set_action(Some(action));
+        Ok(rule)
+    }
+}
+
+/// Numbers and booleans are kept as json, other values are strings
+fn option_value(value: &str) -> Value {
+    match value.parse::<Value>() {
+        Ok(x @ (Value::Number(_) | Value::Bool(_))) => x,
+        _ => Value::String(value.to_string()),
+    }
+}
+
+fn logical(mode: &str, rules: Vec<RouteRule>) -> RouteRule {
+    RouteRule::Logical(LogicalRouteRule {
+        rules,
+        action: None,
+        ..LogicalRouteRule::new().set_mode(mode.to_string())
+    })
+}
+
+fn build(cond: &Cond) -> Result<RouteRule, ExprError> {
+    match cond {
+        Cond::Match(x) => Ok(RouteRule::Default(merge(&[x])?)),
+        Cond::Not(x) => {
+            let mut rule = build(x)?;
+            rule.toggle_invert();
+            Ok(rule)
+        }
+        Cond::And(items) => {
+            // matchers share one rule while their groups dont repeat
+            let mut buckets: Vec<Vec<&Matcher>> = vec![];
+            let mut rules = vec![];
+            for item in items {
+                let Cond::Match(x) = item else {
+                    rules.push(build(item)?);
+                    continue;
+                };
+                match buckets
+                    .iter_mut()
+                    .find(|b| b.iter().all(|m| group(m.field) != group(x.field)))
+                {
+                    Some(bucket) => bucket.push(x),
+                    None => buckets.push(vec![x]),
+                }
+            }
+
+            let mut res = buckets
+                .iter()
+                .map(|x| merge(x).map(RouteRule::Default))
+                .collect::<Result<Vec<_>, _>>()?;
+            res.extend(rules);
+            Ok(match res.len() {
+                1 => res.remove(0),
+                _ => logical("and", res),
+            })
+        }
+        Cond::Or(items) => {
+            // matchers of one group are already joined with OR
+            let matchers: Vec<&Matcher> = items
+                .iter()
+                .filter_map(|x| match x {
+                    Cond::Match(x) => Some(x),
+                    _ => None,
+                })
+                .collect();
+            if matchers.len() == items.len()
+                && matchers
+                    .iter()
+                    .all(|x| group(x.field) == group(matchers[0].field))
+                && matchers
+                    .iter()
+                    .all(|x| matches!(x.kind, Kind::List | Kind::Port | Kind::Uid))
+            {
+                return merge(&matchers).map(RouteRule::Default);
+            }
+            Ok(logical(
+                "or",
+                items.iter().map(build).collect::<Result<_, _>>()?,
+            ))
+        }
+    }
+}
+
+/// Default rule with all matchers, values of one field are appended.
+/// Value sing-box type rejects is reported at the column of its matcher
+fn merge(matchers: &[&Matcher]) -> Result<DefaultRouteRule, ExprError> {
+    let mut map = serde_json::Map::new();
+    for matcher in matchers {
+        for (value, _) in &matcher.values {
+            let value = match matcher.kind {
+                Kind::List | Kind::Single => serde_json::Value::String(value.clone()),
+                _ => value.parse().unwrap_or(serde_json::Value::Null),
+            };
+            match matcher.kind {
+                Kind::List | Kind::Port | Kind::Uid => {
+                    let list = map
+                        .entry(matcher.field.to_string())
+                        .or_insert_with(|| serde_json::Value::Array(vec![]));
+                    if let serde_json::Value::Array(x) = list {
+                        x.push(value);
+                    }
+                }
+                _ => {
+                    map.insert(matcher.field.to_string(), value);
+                }
+            }
+        }
+    }
+    serde_json::from_value(serde_json::Value::Object(map)).map_err(|e| {
+        let column = matchers
+            .first()
+            .and_then(|x| x.values.first())
+            .map_or(1, |x| x.1);
+        ExprError::new(column, format!("incorrect rule: {e}"))
+    })
+}
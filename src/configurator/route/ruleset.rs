@@ -1,7 +1,21 @@
+use anyhow::{Context, Result, anyhow};
 use macros::auto_skip_none;
-use serde::{Deserialize, Serialize};
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, de};
+use std::io::Read;
+use std::path::Path;
+use std::sync::LazyLock;
 
-use crate::configurator::shared::Network;
+use crate::configurator::shared::{Network, is_logical_rule, rule_error};
+
+pub const FORMATS: &[&str] = &["binary", "source"];
+
+/// First bytes of compiled `.srs` rule set
+const BINARY_MAGIC: &[u8] = b"SRS";
+
+/// Go duration with days as sing-box reads it: `1d`, `12h`, `1h30m`
+static DURATION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^([0-9]+(\.[0-9]+)?(ns|us|µs|ms|s|m|h|d))+$").unwrap());
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(untagged)]
@@ -11,50 +25,159 @@ pub enum NumOrStr {
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
-#[serde(untagged)]
+#[serde(tag = "type", rename_all = "lowercase")]
 pub enum RuleSet {
     Inline(RuleSetInline),
     Local(RuleSetLocal),
     Remote(RuleSetRemote),
 }
 
+impl RuleSet {
+    pub fn get_type(&self) -> &'static str {
+        match self {
+            RuleSet::Inline(_) => "inline",
+            RuleSet::Local(_) => "local",
+            RuleSet::Remote(_) => "remote",
+        }
+    }
+
+    pub fn get_tag(&self) -> Option<String> {
+        match self {
+            RuleSet::Inline(x) => x.tag.clone(),
+            RuleSet::Local(x) => x.tag.clone(),
+            RuleSet::Remote(x) => x.tag.clone(),
+        }
+    }
+
+    /// Path, url or rule count of inline rule set
+    pub fn get_source(&self) -> String {
+        match self {
+            RuleSet::Inline(x) => format!("{} rules", x.rules.as_ref().map_or(0, |x| x.len())),
+            RuleSet::Local(x) => x.path.clone().unwrap_or_default(),
+            RuleSet::Remote(x) => x.url.clone().unwrap_or_default(),
+        }
+    }
+
+    pub fn get_download_detour(&self) -> Option<&str> {
+        match self {
+            RuleSet::Remote(x) => x.download_detour.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+fn check_format(format: &str) -> Result<String> {
+    match FORMATS.contains(&format) {
+        true => Ok(format.to_string()),
+        false => Err(anyhow!("Rule set format must be one of {FORMATS:?}")),
+    }
+}
+
+/// Format by extension: `.srs` -> binary, `.json` -> source
+pub fn format_by_extension(name: &str) -> Option<&'static str> {
+    match Path::new(name).extension().and_then(|x| x.to_str()) {
+        Some("srs") => Some("binary"),
+        Some("json") => Some("source"),
+        _ => None,
+    }
+}
+
+/// Format by extension or by content of existing file
+pub fn detect_local_format(path: &str) -> Result<&'static str> {
+    if let Some(x) = format_by_extension(path) {
+        return Ok(x);
+    }
+
+    let mut magic = [0u8; 3];
+    let mut file =
+        std::fs::File::open(path).with_context(|| format!("Cant open rule set '{path}'"))?;
+    match file.read_exact(&mut magic) {
+        Ok(_) if magic == BINARY_MAGIC => Ok("binary"),
+        _ => Ok("source"),
+    }
+}
+
+/// Format by extension of url path
+pub fn detect_remote_format(url: &str) -> Result<&'static str> {
+    let url = url::Url::parse(url).with_context(|| format!("'{url}' is not an url"))?;
+    format_by_extension(url.path())
+        .with_context(|| format!("Cant detect format of '{url}', set it with --format"))
+}
+
 #[auto_skip_none]
 #[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct RuleSetInline {
-    pub r#type: Option<String>,
     pub tag: Option<String>,
     pub rules: Option<Vec<HeadlessRule>>,
 }
 
 impl RuleSetInline {
-    fn new() -> Self {
+    pub fn new() -> Self {
         Self {
             ..Default::default()
         }
     }
+
+    /// Rules from json object or array of objects in sing-box headless rule form,
+    /// unknown fields and rules without conditions are rejected
+    pub fn with_rules(tag: &str, rules: &str) -> Result<Self> {
+        let value: serde_json::Value =
+            serde_json::from_str(rules).context("Inline rules must be json")?;
+        let values = match value {
+            serde_json::Value::Array(x) => x,
+            x => vec![x],
+        };
+        if let Some(i) = values
+            .iter()
+            .position(|x| x.as_object().is_none_or(|x| x.is_empty()))
+        {
+            return Err(anyhow!("Inline rule {} has no conditions", i + 1));
+        }
+        let rules = serde_json::from_value(serde_json::Value::Array(values))
+            .context("Incorrect inline rules")?;
+        Ok(Self {
+            tag: Some(tag.to_string()),
+            rules: Some(rules),
+        })
+    }
 }
 
 #[auto_skip_none]
 #[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct RuleSetLocal {
-    pub r#type: Option<String>,
     pub tag: Option<String>,
     pub format: Option<String>,
     pub path: Option<String>,
 }
 
 impl RuleSetLocal {
-    fn new() -> Self {
+    pub fn new() -> Self {
         Self {
             ..Default::default()
         }
     }
+
+    /// Format is detected when `format` is None. Relative path is made absolute,
+    /// sing-box reads it from its own working directory
+    pub fn with_path(tag: &str, path: &str, format: Option<&str>) -> Result<Self> {
+        let format = match format {
+            Some(x) => check_format(x)?,
+            None => detect_local_format(path)?.to_string(),
+        };
+        let path = std::fs::canonicalize(path)
+            .or_else(|_| std::path::absolute(path))
+            .with_context(|| format!("Incorrect rule set path '{path}'"))?;
+        Ok(Self {
+            tag: Some(tag.to_string()),
+            format: Some(format),
+            path: Some(path.to_string_lossy().to_string()),
+        })
+    }
 }
 
 #[auto_skip_none]
 #[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct RuleSetRemote {
-    pub r#type: Option<String>,
     pub tag: Option<String>,
     pub format: Option<String>,
     pub url: Option<String>,
@@ -63,22 +186,68 @@ pub struct RuleSetRemote {
 }
 
 impl RuleSetRemote {
-    fn new() -> Self {
+    pub fn new() -> Self {
         Self {
             ..Default::default()
         }
     }
+
+    /// Format is detected when `format` is None
+    pub fn with_url(tag: &str, url: &str, format: Option<&str>) -> Result<Self> {
+        let format = match format {
+            Some(x) => check_format(x)?,
+            None => detect_remote_format(url)?.to_string(),
+        };
+        Ok(Self {
+            tag: Some(tag.to_string()),
+            format: Some(format),
+            url: Some(url.to_string()),
+            ..Default::default()
+        })
+    }
+
+    pub fn set_download_detour(mut self, detour: &str) -> Self {
+        self.download_detour = Some(detour.to_string());
+        self
+    }
+
+    pub fn set_update_interval(mut self, interval: &str) -> Result<Self> {
+        if !DURATION.is_match(interval) {
+            return Err(anyhow!(
+                "Update interval '{interval}' is not a duration like 1d or 12h"
+            ));
+        }
+        self.update_interval = Some(interval.to_string());
+        Ok(self)
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Clone)]
 #[serde(untagged)]
 pub enum HeadlessRule {
-    Default(HeadlessDefaultRule),
     Logical(HeadlessLogicalRule),
+    Default(HeadlessDefaultRule),
+}
+
+/// Variant is picked before deserializing so errors name the field of the rule
+impl<'de> Deserialize<'de> for HeadlessRule {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        match is_logical_rule(&value) {
+            true => HeadlessLogicalRule::deserialize(&value)
+                .map(Self::Logical)
+                .map_err(|e| rule_error::<HeadlessLogicalRule>(&value, e)),
+            false => HeadlessDefaultRule::deserialize(&value)
+                .map(Self::Default)
+                .map_err(|e| rule_error::<HeadlessDefaultRule>(&value, e)),
+        }
+        .map_err(de::Error::custom)
+    }
 }
 
 #[auto_skip_none]
 #[derive(Debug, Serialize, Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct HeadlessDefaultRule {
     pub query_type: Option<Vec<NumOrStr>>,
     pub network: Option<Vec<Network>>,
@@ -96,7 +265,13 @@ pub struct HeadlessDefaultRule {
     pub process_path: Option<Vec<String>>,
     pub process_path_regex: Option<Vec<String>>,
     pub package_name: Option<Vec<String>>,
+    pub network_type: Option<Vec<String>>,
+    pub network_is_expensive: Option<bool>,
+    pub network_is_constrained: Option<bool>,
+    pub network_interface_address: Option<serde_json::Value>,
     pub default_interface_address: Option<Vec<String>>,
+    pub wifi_ssid: Option<Vec<String>>,
+    pub wifi_bssid: Option<Vec<String>>,
     pub invert: Option<bool>,
 }
 
@@ -110,11 +285,12 @@ impl HeadlessDefaultRule {
 
 #[auto_skip_none]
 #[derive(Debug, Serialize, Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct HeadlessLogicalRule {
     #[serde(rename = "type")]
     pub rule_type: Option<String>,
     pub mode: Option<String>,
-    pub rules: Option<Vec<HeadlessRule>>,
+    pub rules: Vec<HeadlessRule>,
     pub invert: Option<bool>,
 }
 
@@ -0,0 +1,68 @@
+use anyhow::{Context, Result, anyhow};
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// Address prefix in `<ip>/<len>` form, plain ip is treated as host prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    pub addr: IpAddr,
+    pub prefix: u8,
+}
+
+impl Cidr {
+    pub fn max_prefix(addr: &IpAddr) -> u8 {
+        match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        }
+    }
+
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(*ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(*ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (addr, prefix) = match s.trim().split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s.trim(), None),
+        };
+
+        let addr: IpAddr = addr
+            .parse()
+            .with_context(|| format!("'{s}' is not an ip address"))?;
+
+        let prefix = match prefix {
+            Some(x) => x
+                .parse::<u8>()
+                .with_context(|| format!("'{s}' has incorrect prefix length"))?,
+            None => Self::max_prefix(&addr),
+        };
+
+        if prefix > Self::max_prefix(&addr) {
+            return Err(anyhow!("'{s}' prefix length is out of range"));
+        }
+
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
@@ -1,10 +1,13 @@
+pub mod cidr;
 pub mod dialfields;
 pub mod listenfields;
 pub mod multiplex;
 pub mod tls;
 pub mod transport;
 
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Network {
@@ -23,3 +26,54 @@ pub enum Listable<T> {
 
 pub type ListableString = Listable<String>;
 pub type ListableU16 = Listable<u16>;
+
+impl<T> Listable<T> {
+    pub fn push(&mut self, value: T) {
+        match self {
+            Listable::Many(x) => x.push(value),
+            Listable::One(_) => {
+                if let Listable::One(first) = std::mem::replace(self, Listable::Many(vec![])) {
+                    *self = Listable::Many(vec![first, value]);
+                }
+            }
+        }
+    }
+
+    pub fn to_vec(&self) -> Vec<&T> {
+        match self {
+            Listable::One(x) => vec![x],
+            Listable::Many(x) => x.iter().collect(),
+        }
+    }
+}
+
+/// Logical rules have `type: logical`, older ones only their nested `rules`
+pub fn is_logical_rule(value: &Value) -> bool {
+    match value.get("type").and_then(|x| x.as_str()) {
+        Some(x) => x == "logical",
+        None => value.get("rules").is_some(),
+    }
+}
+
+/// Error of rule which names the field without which the rule fits `T`
+pub fn rule_error<T: DeserializeOwned>(value: &Value, error: serde_json::Error) -> String {
+    let Some(map) = value.as_object() else {
+        return error.to_string();
+    };
+    for key in map.keys() {
+        let mut rest = map.clone();
+        rest.remove(key);
+        if T::deserialize(Value::Object(rest)).is_ok() {
+            return format!("field '{key}': {error}");
+        }
+    }
+    error.to_string()
+}
+
+/// Appends value to optional listable field
+pub fn push_listable<T>(list: &mut Option<Listable<T>>, value: T) {
+    match list {
+        Some(x) => x.push(value),
+        None => *list = Some(Listable::One(value)),
+    }
+}
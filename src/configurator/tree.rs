@@ -0,0 +1,205 @@
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+
+/// Part of dotted path, numbers index lists
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// `outbounds.0.tls.server_name` into segments, keys with dots are quoted:
+/// `dns.servers.0.predefined."example.com"`
+pub fn parse_path(path: &str) -> Result<Vec<Segment>> {
+    if path.trim().is_empty() {
+        return Err(anyhow!("Path is empty"));
+    }
+    let mut segments = vec![];
+    let mut rest = path;
+    loop {
+        let (segment, tail) = match rest.strip_prefix('"') {
+            Some(x) => {
+                let (key, tail) = x
+                    .split_once('"')
+                    .ok_or_else(|| anyhow!("Unclosed quote in path '{path}'"))?;
+                (Segment::Key(key.to_string()), tail)
+            }
+            None => {
+                let (x, tail) = rest.split_at(rest.find('.').unwrap_or(rest.len()));
+                if x.is_empty() {
+                    return Err(anyhow!("Incorrect path '{path}'"));
+                }
+                let segment = x
+                    .parse::<usize>()
+                    .map_or_else(|_| Segment::Key(x.to_string()), Segment::Index);
+                (segment, tail)
+            }
+        };
+        segments.push(segment);
+        match tail.strip_prefix('.') {
+            Some(x) => rest = x,
+            None if tail.is_empty() => return Ok(segments),
+            None => return Err(anyhow!("Incorrect path '{path}'")),
+        }
+    }
+}
+
+/// Value from command line, json when it parses and plain string otherwise
+pub fn parse_value(input: &str) -> Vec<Value> {
+    let text = Value::String(input.to_string());
+    match serde_json::from_str::<Value>(input) {
+        Ok(x) if x != text => vec![x, text],
+        _ => vec![text],
+    }
+}
+
+pub fn get<'a>(root: &'a Value, path: &str) -> Result<&'a Value> {
+    let mut node = root;
+    for (i, segment) in parse_path(path)?.iter().enumerate() {
+        node = child(node, segment).ok_or_else(|| missing(path, i))?;
+    }
+    Ok(node)
+}
+
+/// Replaces value, missing keys are created and index equal to length appends
+pub fn set(root: &mut Value, path: &str, value: Value) -> Result<()> {
+    let segments = parse_path(path)?;
+    let (last, parent) = parent_mut(root, path, &segments, true)?;
+    match (parent, last) {
+        (Value::Object(x), Segment::Key(key)) => {
+            x.insert(key.clone(), value);
+        }
+        (Value::Object(x), Segment::Index(i)) => {
+            x.insert(i.to_string(), value);
+        }
+        (Value::Array(x), Segment::Index(i)) if *i < x.len() => x[*i] = value,
+        (Value::Array(x), Segment::Index(i)) if *i == x.len() => x.push(value),
+        (Value::Array(_), Segment::Index(_)) => return Err(missing(path, segments.len() - 1)),
+        _ => return Err(anyhow!("'{}' is not an object", prefix(&segments, 1))),
+    }
+    Ok(())
+}
+
+/// Removes key or list item and returns it
+pub fn unset(root: &mut Value, path: &str) -> Result<Value> {
+    let segments = parse_path(path)?;
+    let (last, parent) = parent_mut(root, path, &segments, false)?;
+    let removed = match (parent, last) {
+        (Value::Object(x), Segment::Key(key)) => x.remove(key),
+        (Value::Object(x), Segment::Index(i)) => x.remove(&i.to_string()),
+        (Value::Array(x), Segment::Index(i)) if *i < x.len() => Some(x.remove(*i)),
+        _ => None,
+    };
+    removed.ok_or_else(|| missing(path, segments.len() - 1))
+}
+
+/// Pushes to list, missing list is created
+pub fn append(root: &mut Value, path: &str, value: Value) -> Result<()> {
+    let segments = parse_path(path)?;
+    let (last, parent) = parent_mut(root, path, &segments, true)?;
+    let list = match (parent, last) {
+        (Value::Object(x), Segment::Key(key)) => {
+            x.entry(key.clone()).or_insert_with(|| Value::Array(vec![]))
+        }
+        (Value::Array(x), Segment::Index(i)) if *i < x.len() => &mut x[*i],
+        _ => return Err(missing(path, segments.len() - 1)),
+    };
+    if list.is_null() {
+        *list = Value::Array(vec![]);
+    }
+    match list {
+        Value::Array(x) => x.push(value),
+        _ => return Err(anyhow!("'{path}' is not a list")),
+    }
+    Ok(())
+}
+
+/// Removes list items equal to `value`, empty list is removed
+pub fn remove(root: &mut Value, path: &str, value: &Value) -> Result<()> {
+    let list = match get_mut(root, path)? {
+        Value::Array(x) => x,
+        _ => return Err(anyhow!("'{path}' is not a list")),
+    };
+    let len = list.len();
+    list.retain(|x| x != value);
+    if list.len() == len {
+        return Err(anyhow!("'{path}' has no {value}"));
+    }
+    if list.is_empty() {
+        unset(root, path)?;
+    }
+    Ok(())
+}
+
+fn get_mut<'a>(root: &'a mut Value, path: &str) -> Result<&'a mut Value> {
+    let mut node = root;
+    for (i, segment) in parse_path(path)?.iter().enumerate() {
+        node = child_mut(node, segment).ok_or_else(|| missing(path, i))?;
+    }
+    Ok(node)
+}
+
+/// Parent of the last segment, with `create` missing objects are added on the way
+fn parent_mut<'a, 'b>(
+    root: &'a mut Value,
+    path: &str,
+    segments: &'b [Segment],
+    create: bool,
+) -> Result<(&'b Segment, &'a mut Value)> {
+    let (last, parents) = segments
+        .split_last()
+        .ok_or_else(|| anyhow!("Path is empty"))?;
+    let mut node = root;
+    for (i, segment) in parents.iter().enumerate() {
+        if create && let (Value::Object(x), Segment::Key(key)) = (&mut *node, segment) {
+            let next = x
+                .entry(key.clone())
+                .or_insert_with(|| Value::Object(Default::default()));
+            if next.is_null() {
+                *next = Value::Object(Default::default());
+            }
+        }
+        node = child_mut(node, segment).ok_or_else(|| missing(path, i))?;
+    }
+    Ok((last, node))
+}
+
+fn child<'a>(node: &'a Value, segment: &Segment) -> Option<&'a Value> {
+    match (node, segment) {
+        (Value::Object(x), Segment::Key(key)) => x.get(key),
+        (Value::Object(x), Segment::Index(i)) => x.get(&i.to_string()),
+        (Value::Array(x), Segment::Index(i)) => x.get(*i),
+        _ => None,
+    }
+}
+
+fn child_mut<'a>(node: &'a mut Value, segment: &Segment) -> Option<&'a mut Value> {
+    match (node, segment) {
+        (Value::Object(x), Segment::Key(key)) => x.get_mut(key),
+        (Value::Object(x), Segment::Index(i)) => x.get_mut(&i.to_string()),
+        (Value::Array(x), Segment::Index(i)) => x.get_mut(*i),
+        _ => None,
+    }
+}
+
+/// Path without `skip` last segments
+fn prefix(segments: &[Segment], skip: usize) -> String {
+    segments[..segments.len().saturating_sub(skip)]
+        .iter()
+        .map(|x| match x {
+            Segment::Key(x) if x.contains('.') => format!("\"{x}\""),
+            Segment::Key(x) => x.clone(),
+            Segment::Index(x) => x.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Error for the first missing segment of path
+fn missing(path: &str, at: usize) -> anyhow::Error {
+    let segments = parse_path(path).unwrap_or_default();
+    anyhow!(
+        "'{}' does not exist",
+        prefix(&segments, segments.len().saturating_sub(at + 1))
+    )
+}
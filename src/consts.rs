@@ -10,10 +10,17 @@ pub mod timing {
 
     #[cfg(feature = "daemon")]
     pub const IP_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+    pub const USAGE_SAMPLE: Duration = Duration::from_secs(10);
+}
+
+pub mod history {
+    /// Revisions kept per config
+    pub const REVISIONS: usize = 50;
 }
 
 pub mod net {
     pub const LOCAL_PROXY_ADDR: &str = "127.0.0.1:12334";
+    pub const CLASH_API_PORT: u16 = 9090;
     pub const LOADING_IP: &str = "loading...";
     pub const FALLBACK_IP: &str = "0.0.0.0";
     pub const UNAVAILABLE_IP: &str = "ip unavailable";
@@ -32,6 +39,9 @@ pub mod traffic {
     pub const MIN_WIDTH: u16 = 3;
     pub const MIN_HEIGHT: u16 = 4;
 
+    #[cfg(feature = "tui")]
+    pub const DIRECT_COLOR: ratatui::style::Color = ratatui::style::Color::DarkGray;
+
     pub static BAR_MAP: LazyLock<HashMap<u8, char>> = LazyLock::new(|| {
         HashMap::from([
             (000, ' '),
@@ -118,12 +128,14 @@ pub mod ui {
     pub const ENTER_INDEX: usize = 9;
 
     pub const SETTINGS_FIELDS_COUNT: usize = 10;
+    pub const SETTINGS_HEIGHT: u16 = 18;
     pub const ROUTE_FIELDS_COUNT: usize = 3;
 
     pub const ROUTE_ACTION_CUSTOM_INDEX: usize = 3;
 
     pub const SELECTED_SYMBOL: &str = ">> ";
     pub const RUNNING_SYMBOL: &str = "● ";
+    pub const FAVOURITE_SYMBOL: &str = " ★";
 }
 
 pub mod text {
@@ -132,6 +144,29 @@ pub mod text {
     pub const CONFIGS_TITLE: &str = "Configs";
     pub const LOGS_TITLE: &str = "Logs";
     pub const SETTINGS_TITLE: &str = "Settings";
+    pub const TUN_TITLE: &str = "Tun";
+    pub const NO_TUN: &str = "Config has no tun inbound";
+    pub const LOAD_ERROR_TITLE: &str = "Config failed to load";
+    pub const APPS_TITLE: &str = "Apps";
+    pub const PROCESSES_TITLE: &str = "Processes";
+    pub const NO_APP_RULES: &str = "No app rules";
+    pub const APPS_HELP: &str = "b bypass   p proxy   B/P by uid   x remove";
+    pub const DNS_TITLE: &str = "Dns";
+    pub const DNS_RULES_TITLE: &str = "Dns Rules";
+    pub const NO_DNS_RULES: &str = "No dns rules";
+    pub const ROUTE_RULES_TITLE: &str = "Route rules, numbers of List Management";
+    pub const NO_ROUTE_RULES: &str = "No route rules";
+    pub const DNS_HELP: &str = "n new rule   x remove   J/K move down/up";
+    pub const ENTER_DNS_RULE: &str = "<key>=<value>[,<value>] ... -> <action>";
+    pub const CONNECTIONS_TITLE: &str = "Connections";
+    pub const NO_CONNECTIONS: &str = "No connections";
+    pub const NOT_RUNNING: &str = "Config is not running";
+    pub const CONNECTIONS_HELP: &str = "/ filter   s sort   x close   m mode   o selectors";
+    pub const SELECTORS_TITLE: &str = "Selectors";
+    pub const FILTER_PREFIX: &str = "Filter: ";
+    pub const SEARCH_TITLE: &str = "Search name, tag, region or subscription";
+    pub const CONNECTIONS_HEADER: &[&str] =
+        &["Host", "Destination", "Rule", "Outbound", "Up", "Down", "Time"];
     pub const ERROR_SETTINGS_TITLE: &str = "Data entry error!";
     pub const SELECT_TITLE: &str = "Select";
 
@@ -160,7 +195,7 @@ pub mod text {
     pub const INPUT_PREFIX: &str = "Input: ";
 
     pub const HELP: &str =
-        "↑/↓ navigate   q exit   TAB switch LOG/Settings   a adding config  A adding tun config    d delete config";
+        "↑/↓ navigate   q exit   TAB switch LOG/Settings/Tun/Apps/Dns/Connections   a adding config  A adding tun config    d delete config   / search   g group";
 
     pub const TRAFFIC_TITLE: &str = "Traffic";
 }
@@ -172,6 +207,20 @@ pub mod keys {
     pub const DELETE_CONFIG: char = 'd';
     pub const DOWN_ALT: char = 'j';
     pub const UP_ALT: char = 'k';
+    pub const APP_BYPASS: char = 'b';
+    pub const APP_PROXY: char = 'p';
+    pub const UID_BYPASS: char = 'B';
+    pub const UID_PROXY: char = 'P';
+    pub const REMOVE_RULE: char = 'x';
+    pub const ADD_RULE: char = 'n';
+    pub const MOVE_RULE_DOWN: char = 'J';
+    pub const MOVE_RULE_UP: char = 'K';
+    pub const FILTER: char = '/';
+    pub const SORT: char = 's';
+    pub const CLOSE_CONNECTION: char = 'x';
+    pub const CLASH_MODE: char = 'm';
+    pub const SELECT_MEMBER: char = 'o';
+    pub const GROUP: char = 'g';
 }
 
 pub mod route {
@@ -188,6 +237,7 @@ pub mod route {
         ("domain keyword", "dk"),
         ("domain regex", "dr"),
         ("geosite", "gs"),
+        ("rule set", "rs"),
         ("source geoip", "sg"),
         ("geoip", "gp"),
         ("source ip cidr", "sc"),
@@ -213,7 +263,18 @@ pub mod route {
 }
 
 pub mod DNS {
-    pub const TYPES: &[(&str, &str)] = &[("udp", "up"), ("tcp", "tp")]; 
+    pub const TYPES: &[(&str, &str)] = &[
+        ("udp", "udp"),
+        ("tcp", "tcp"),
+        ("tls", "tls"),
+        ("quic", "quic"),
+        ("https", "https"),
+        ("h3", "h3"),
+        ("dhcp", "dhcp"),
+        ("fakeip", "fakeip"),
+        ("hosts", "hosts"),
+        ("local", "local"),
+    ];
 }
 
 pub mod manage {
@@ -224,7 +285,13 @@ pub mod manage {
         ("Remove dns server", "rd"), 
         ("Move dns server", "md"), 
         ("Final dns server", "fd"), 
-        ("Remove dns server", "rs"), 
-        ("Move dns server", "ms"),
+        ("Remove dns rule", "rs"), 
+        ("Move dns rule", "ms"),
+        ("Group rules", "gr"),
+        ("Nest rule", "nr"),
+        ("Remove rule path", "rp"),
+        ("Extract rule", "er"),
+        ("Invert rule", "ir"),
+        ("Logical mode", "lm"),
     ];
 }
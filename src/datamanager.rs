@@ -1,22 +1,45 @@
 use anyhow::{Context, Result, anyhow};
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
 use std::fs;
 use std::path::PathBuf;
 use toml;
 
+pub mod backup;
+pub mod catalog;
+pub mod history;
+pub mod render;
+
 #[cfg(not(feature = "daemon"))]
 pub mod app;
 
 #[cfg(feature = "daemon")]
 pub mod async_app;
 
+use crate::clashapi::ClashClient;
 use crate::configurator::Configurator;
+use crate::configurator::preset::PresetStore;
+use crate::configurator::route::geo::GeoTemplates;
+use crate::consts;
+use crate::secrets::Encryption;
+use crate::usage::Quota;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
 pub struct Settings {
     current: Option<String>,
     unable_system_proxy: Option<bool>,
+    /// Url template of geosite rule sets, `{name}` is geosite code
+    geosite_url: Option<String>,
+    /// Url template of geoip rule sets, `{name}` is geoip code
+    geoip_url: Option<String>,
+    /// Enable clash api on configs which have no controller, on by default
+    clash_api: Option<bool>,
+    /// First port tried for generated clash api controller
+    clash_api_port: Option<u16>,
+    /// Monthly quotas by config name
+    quotas: Option<BTreeMap<String, Quota>>,
+    /// Credentials in saved configs are encrypted when set
+    encryption: Option<Encryption>,
 }
 
 impl Settings {
@@ -27,6 +50,12 @@ impl Settings {
                 let settings = Self {
                     current: None,
                     unable_system_proxy: None,
+                    geosite_url: None,
+                    geoip_url: None,
+                    clash_api: None,
+                    clash_api_port: None,
+                    quotas: None,
+                    encryption: None,
                 };
 
                 settings.save(setting_file)?;
@@ -50,6 +79,54 @@ impl Settings {
         Ok(())
     }
 
+    pub fn geo_templates(&self) -> GeoTemplates {
+        let default = GeoTemplates::default();
+        GeoTemplates {
+            geosite: self.geosite_url.clone().unwrap_or(default.geosite),
+            geoip: self.geoip_url.clone().unwrap_or(default.geoip),
+        }
+    }
+
+    pub fn clash_api_enabled(&self) -> bool {
+        self.clash_api.unwrap_or(true)
+    }
+
+    pub fn clash_api_port(&self) -> u16 {
+        self.clash_api_port.unwrap_or(consts::net::CLASH_API_PORT)
+    }
+
+    pub fn get_quota(&self, config: &str) -> Option<&Quota> {
+        self.quotas.as_ref()?.get(config)
+    }
+
+    pub fn get_quotas(&self) -> Vec<(&String, &Quota)> {
+        self.quotas.iter().flatten().collect()
+    }
+
+    pub fn set_quota(&mut self, config: &str, quota: Quota) -> &mut Self {
+        self.quotas
+            .get_or_insert_default()
+            .insert(config.to_string(), quota);
+        self
+    }
+
+    pub fn remove_quota(&mut self, config: &str) -> Result<()> {
+        self.quotas
+            .as_mut()
+            .and_then(|x| x.remove(config))
+            .with_context(|| format!("Config '{config}' has no quota"))?;
+        Ok(())
+    }
+
+    pub fn encryption(&self) -> Option<&Encryption> {
+        self.encryption.as_ref()
+    }
+
+    pub fn set_encryption(&mut self, encryption: Option<Encryption>) -> &mut Self {
+        self.encryption = encryption;
+        self
+    }
+
     pub fn read(&mut self, setting_file: PathBuf) -> Result<()> {
         match fs::read_to_string(&setting_file) {
             Ok(content) => {
@@ -3,12 +3,17 @@ use anyhow::{Context, Result, anyhow};
 use directories::ProjectDirs;
 use interprocess::local_socket::tokio::{Stream, prelude::*};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::runtime::Runtime;
 use tokio::time::{Duration, sleep, timeout};
 
+use crate::datamanager::backup::{Backup, RestoreMode, RestoreReport};
+use crate::datamanager::history::History;
+use crate::datamanager::render;
 use crate::datamanager::*;
+use crate::secrets::{self, Key};
+use crate::usage::{self, UsageStore};
 
 pub struct App {
     data_dir: PathBuf,
@@ -45,6 +50,8 @@ impl App {
             runtime,
         };
 
+        app.cfg_handler
+            .set_geo_templates(app.stg_handler.geo_templates());
         app.configs = app.read_configs()?;
         Ok(app)
     }
@@ -107,6 +114,17 @@ impl App {
         Ok(())
     }
 
+    /// Removes config with its quota and history, `remove_config` alone keeps them for rename and rewrite
+    pub fn delete_config(&mut self, name: &str) -> Result<()> {
+        self.remove_config(name)?;
+        History::remove(&self.data_dir, name)?;
+        if self.stg_handler.get_quota(name).is_some() {
+            self.stg_handler.remove_quota(name)?;
+            self.save_settings()?;
+        }
+        Ok(())
+    }
+
     pub fn remove_config_by_number(&mut self, number: usize) -> Result<()> {
         let file_path = self.get_configs_path().join(format!(
             "{}.json",
@@ -127,7 +145,10 @@ impl App {
     }
 
     pub fn add_config(&mut self, name: Option<String>) -> Result<&mut Self> {
-        self.set_log_file();
+        self.cfg_handler
+            .meta_mut()
+            .created
+            .get_or_insert(usage::now());
         let saved_name = self.save_config(name, false)?;
         self.inf_handler
             .set_name(&saved_name)
@@ -180,10 +201,20 @@ impl App {
         }
 
         self.run_app_by_path(file_path, unable_system_proxy)?;
+        if let Some(name) = &self.stg_handler.current {
+            self.mark_used(name);
+        }
         Ok(())
     }
 
+    /// Last start of config, listing does not depend on it so errors are ignored
+    fn mark_used(&self, name: &str) {
+        let path = UsageStore::path(&self.data_dir);
+        let _ = UsageStore::update(&path, |x| x.touch(name, usage::now()));
+    }
+
     fn run_app_by_path(&mut self, file_path: PathBuf, unable_system_proxy: bool) -> Result<()> {
+        self.unlock(&file_path)?;
         self.runtime
             .block_on(async { send_start(file_path.to_str().unwrap().to_string()).await })?;
 
@@ -228,60 +259,97 @@ impl App {
         }
     }
 
+    /// Restarts core when config `name` is running, returns whether it was
+    pub fn reload_config(&mut self, name: &str) -> Result<bool> {
+        // Config is not running when there is no daemon, dont start it
+        let daemon = self
+            .runtime
+            .block_on(async { Ok::<_, anyhow::Error>(Stream::connect(socket_name()?).await?) });
+        if daemon.is_err() {
+            return Ok(false);
+        }
+
+        let path = self.get_configs_path().join(format!("{name}.json"));
+        let path = path.to_string_lossy().to_string();
+        match self.get_status()? {
+            Some(x) if x.file == path => {
+                self.unlock(Path::new(&path))?;
+                self.runtime.block_on(async { send_reload(path).await })?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Gives daemon the key when config has encrypted credentials
+    fn unlock(&mut self, path: &Path) -> Result<()> {
+        if let Some(key) = self.secret_key(path)? {
+            self.runtime
+                .block_on(async { send_unlock(key.to_base64()).await })?;
+        }
+        Ok(())
+    }
+
     pub fn send_quit(&mut self) -> Result<()> {
         self.runtime.block_on(async { send_quit().await })?;
         Ok(())
     }
 
     pub fn rename_config(&mut self, new_name: String) -> Result<()> {
-        self.remove_config(
-            &self
-                .get_selected_config()
-                .context("Config doesnt selected")?,
-        )?;
+        let old_name = self
+            .get_selected_config()
+            .context("Config doesnt selected")?;
+        self.remove_config(&old_name)?;
+        History::rename(&self.data_dir, &old_name, &new_name)?;
         self.add_config(Some(new_name.clone()))?;
         self.inf_handler.set_name(&new_name);
+
+        UsageStore::update(&UsageStore::path(&self.data_dir), |x| {
+            x.rename(&old_name, &new_name)
+        })?;
+        if let Some(quota) = self.stg_handler.get_quota(&old_name).cloned() {
+            self.stg_handler.remove_quota(&old_name)?;
+            self.stg_handler.set_quota(&new_name, quota);
+            self.save_settings()?;
+        }
         Ok(())
     }
 
-    pub fn set_handler_config_by_name(&mut self, name: &str) -> Result<()> {
+    /// Loads config into handler, geo rules migrated on load are saved back
+    fn load_handler(&mut self, name: &str) -> Result<()> {
         self.cfg_handler
             .load_from_file(self.get_configs_path().join(format!("{}.json", name)))?;
 
         self.inf_handler
             .set_name(name)
             .set_inbounds(self.cfg_handler.get_inbounds_ports());
+
+        if self.cfg_handler.migrate_geo() {
+            self.save()?;
+        }
         Ok(())
     }
 
-    pub fn set_handler_config_by_number(&mut self, number: usize) -> Result<()> {
-        self.cfg_handler
-            .load_from_file(self.get_configs_path().join(format!(
-                "{}.json",
-                self.configs.get(number).context("Config doesnt exist")?
-            )))?;
+    pub fn set_handler_config_by_name(&mut self, name: &str) -> Result<()> {
+        self.load_handler(name)
+    }
 
-        self.inf_handler
-            .set_name(&self.configs.get(number).unwrap())
-            .set_inbounds(self.cfg_handler.get_inbounds_ports());
-        Ok(())
+    pub fn set_handler_config_by_number(&mut self, number: usize) -> Result<()> {
+        let name = self
+            .configs
+            .get(number)
+            .context("Config doesnt exist")?
+            .clone();
+        self.load_handler(&name)
     }
 
     pub fn set_handler_config_by_current(&mut self) -> Result<()> {
         if let Some(name) = self.stg_handler.current.clone() {
-            self.cfg_handler
-                .load_from_file(self.get_configs_path().join(format!("{}.json", &name)))?;
-            self.inf_handler
-                .set_name(&name)
-                .set_inbounds(self.cfg_handler.get_inbounds_ports());
+            self.load_handler(&name)?;
         } else {
-            if let Some(name) = self.get_list().first() {
-                self.cfg_handler
-                    .load_from_file(self.get_configs_path().join(format!("{}.json", &name)))?;
-                self.inf_handler
-                    .set_name(&name)
-                    .set_inbounds(self.cfg_handler.get_inbounds_ports());
-                self.stg_handler.current = Some(name.clone());
+            if let Some(name) = self.get_list().first().cloned() {
+                self.load_handler(&name)?;
+                self.stg_handler.current = Some(name);
             } else {
                 return Err(anyhow!("No configs exist"));
             }
@@ -290,6 +358,24 @@ impl App {
         Ok(())
     }
 
+    /// Client of clash api of the current config
+    pub fn clash_client(&mut self) -> Result<ClashClient> {
+        let name = self
+            .stg_handler
+            .current
+            .clone()
+            .context("No config was started")?;
+        self.clash_client_by_name(&name)
+    }
+
+    /// Clash api the running core got on start, without loading config into handler
+    pub fn clash_client_by_name(&self, name: &str) -> Result<ClashClient> {
+        render::clash_api(&self.get_configs_path().join(format!("{}.json", name)))
+            .as_ref()
+            .and_then(ClashClient::from_config)
+            .with_context(|| format!("Config '{name}' is not running with clash api"))
+    }
+
     pub fn save(&mut self) -> Result<()> {
         self.save_config(Some(self.inf_handler.get_name()), true)?;
         Ok(())
@@ -331,27 +417,93 @@ impl App {
             }
         };
 
+        let file_path = self.get_configs_path().join(format!("{free_name}.json"));
+        let mut history = History::open(&self.data_dir, &free_name)?;
+        if let Ok(x) = fs::read_to_string(&file_path) {
+            history.record(&x)?;
+        }
+        if self.cfg_handler.meta_mut().id.is_none() {
+            let taken = catalog::ids(&self.get_configs_path(), &self.configs);
+            self.cfg_handler.meta_mut().id = Some(catalog::free_id(&taken));
+        }
+        self.seal_secrets()?;
         self.cfg_handler
             .save_to_file(self.get_configs_path(), &free_name)?;
+        history.record(&fs::read_to_string(&file_path)?)?;
         Ok(free_name)
     }
 
-    pub fn set_log_file(&mut self) -> &mut Self {
-        self.cfg_handler.set_log(
-            "info".to_string(),
-            Some(self.get_data_path().join("box.log")),
-        );
-        self
+    /// Asks the key of encrypted credentials once, before the terminal UI takes the terminal
+    pub fn unlock_secrets(&self) -> Result<()> {
+        if let Some(x) = self.stg_handler.encryption() {
+            x.key()?;
+        }
+        Ok(())
+    }
+
+    /// Encrypts plain credentials of handler config when encryption is on
+    fn seal_secrets(&mut self) -> Result<()> {
+        let Some(encryption) = self.stg_handler.encryption() else {
+            return Ok(());
+        };
+        let mut tree = self.cfg_handler.to_tree()?;
+        if secrets::has_plain(&tree) {
+            secrets::encrypt_tree(&mut tree, &encryption.key()?)?;
+            self.cfg_handler.set_tree(tree)?;
+        }
+        Ok(())
+    }
+
+    /// Key for config at `path` when it has encrypted credentials
+    fn secret_key(&self, path: &Path) -> Result<Option<Key>> {
+        let tree = serde_json::from_str(&fs::read_to_string(path)?)?;
+        match self.stg_handler.encryption() {
+            Some(x) if secrets::has_encrypted(&tree) => Ok(Some(x.key()?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Restores revision of selected config as a new revision
+    pub fn revert_config(&mut self, rev: u64) -> Result<()> {
+        let name = self.inf_handler.get_name();
+        let content = History::open(&self.data_dir, &name)?.read(rev)?;
+        // Old revisions may predate the id
+        let id = self.cfg_handler.meta_ref().and_then(|x| x.id.clone());
+        self.cfg_handler.set_tree(serde_json::from_str(&content)?)?;
+        if id.is_some() {
+            self.cfg_handler.meta_mut().id = id;
+        }
+        let last = History::open(&self.data_dir, &name)?
+            .latest()
+            .map(|x| x.rev);
+        self.save()?;
+        // Nothing is written when the revision equals the current config
+        let mut history = History::open(&self.data_dir, &name)?;
+        match history.latest().map(|x| x.rev) {
+            Some(x) if Some(x) != last => history.set_restored(x, rev),
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes backup into data dir and reloads configs and settings
+    pub fn restore_backup(&mut self, backup: &Backup, mode: RestoreMode) -> Result<RestoreReport> {
+        let report = backup.restore(&self.data_dir, mode)?;
+        self.stg_handler.read(self.get_settings_path())?;
+        self.cfg_handler
+            .set_geo_templates(self.stg_handler.geo_templates());
+        self.configs = self.read_configs()?;
+        catalog::assign_ids(&self.data_dir, &self.configs)?;
+        Ok(report)
     }
 
     pub fn remove_log_file(&mut self) -> Result<&mut Self> {
-        fs::remove_file(self.get_data_path().join("box.log"))
+        fs::remove_file(self.get_data_path().join(render::LOG_FILE))
             .context("Failed to remove config file")?;
         Ok(self)
     }
 
     pub fn read_logs(&mut self) -> &mut Self {
-        let path = self.get_data_path().join("box.log");
+        let path = self.get_data_path().join(render::LOG_FILE);
 
         self.log_handler.read(path);
 
@@ -386,10 +538,37 @@ impl App {
         self.data_dir.clone().join("config")
     }
 
+    pub fn get_presets_path(&self) -> PathBuf {
+        self.data_dir.clone().join("presets")
+    }
+
+    pub fn presets(&self) -> PresetStore {
+        PresetStore::new(self.get_presets_path())
+    }
+
+    pub fn apply_preset(&mut self, name: &str) -> Result<&mut Self> {
+        let preset = self.presets().load(name)?;
+        self.cfg_handler.clean().apply_preset(preset);
+        self.cfg_handler.meta_mut().preset = Some(name.to_string());
+        Ok(self)
+    }
+
     pub fn get_settings_path(&self) -> PathBuf {
         self.data_dir.clone().join("settings.toml")
     }
 
+    pub fn settings_ref(&self) -> &Settings {
+        &self.stg_handler
+    }
+
+    pub fn settings_mut(&mut self) -> &mut Settings {
+        &mut self.stg_handler
+    }
+
+    pub fn save_settings(&self) -> Result<()> {
+        self.stg_handler.save(self.get_settings_path())
+    }
+
     pub fn get_list(&self) -> Vec<String> {
         self.configs.clone()
     }
@@ -462,6 +641,16 @@ async fn send_stop() -> Result<Response> {
     send_request(Request::stop()).await
 }
 
+#[inline]
+async fn send_reload(config_path: String) -> Result<Response> {
+    send_request(Request::reload(config_path)).await
+}
+
+#[inline]
+async fn send_unlock(key: String) -> Result<Response> {
+    send_request(Request::unlock(key)).await
+}
+
 #[inline]
 async fn send_start(config_path: String) -> Result<Response> {
     send_request(Request::start(config_path)).await
@@ -0,0 +1,449 @@
+use anyhow::{Context, Result, anyhow};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+use super::{Settings, render};
+use crate::configurator::Configurator;
+use crate::secrets::Encryption;
+use crate::usage::{self, UsageStore};
+
+pub const FORMAT: &str = "relation-backup";
+pub const VERSION: u32 = 1;
+
+/// Files of data dir which are not packed, they belong to the running machine
+const SKIPPED: [&str; 1] = [render::LOG_FILE];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: String,
+    pub version: u32,
+    /// Version of relation which made the backup
+    pub app_version: String,
+    /// Unix seconds
+    pub created: u64,
+    /// Data dir of the packed machine, paths inside it are rewritten on restore
+    pub data_dir: String,
+    pub configs: Vec<String>,
+}
+
+/// File content, text stays readable in the archive
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Content {
+    Text(String),
+    Base64(String),
+}
+
+impl Content {
+    fn new(bytes: Vec<u8>) -> Self {
+        match String::from_utf8(bytes) {
+            Ok(x) => Content::Text(x),
+            Err(e) => Content::Base64(STANDARD.encode(e.into_bytes())),
+        }
+    }
+
+    fn bytes(&self) -> Result<Vec<u8>> {
+        match self {
+            Content::Text(x) => Ok(x.clone().into_bytes()),
+            Content::Base64(x) => Ok(STANDARD.decode(x)?),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestoreMode {
+    /// Keep local data, clashing configs get `[n] name`
+    Merge,
+    /// Remove local data first
+    Replace,
+}
+
+/// What restore did with configs
+#[derive(Debug, Default, Serialize)]
+pub struct RestoreReport {
+    /// Restored under the same name
+    pub added: Vec<String>,
+    /// Backup name and the new one
+    pub renamed: Vec<(String, String)>,
+    /// Same as local config
+    pub unchanged: Vec<String>,
+}
+
+/// Configs, settings, presets, history, usage and caches of one data dir
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Backup {
+    pub manifest: Manifest,
+    /// By path relative to data dir with `/` separators
+    files: BTreeMap<String, Content>,
+}
+
+impl Backup {
+    pub fn create(data_dir: &Path) -> Result<Self> {
+        let mut files = BTreeMap::new();
+        collect(data_dir, "", &mut files)?;
+        let configs = files.keys().filter_map(|x| config_name(x)).collect();
+
+        Ok(Self {
+            manifest: Manifest {
+                format: FORMAT.to_string(),
+                version: VERSION,
+                app_version: env!("CARGO_PKG_VERSION").to_string(),
+                created: usage::now(),
+                data_dir: data_dir.to_string_lossy().to_string(),
+                configs,
+            },
+            files,
+        })
+    }
+
+    /// Writes backup readable only by the user, it holds credentials
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options
+            .open(path)
+            .with_context(|| format!("Cant write backup '{}'", path.display()))?;
+        // mode of an existing file is kept by open
+        #[cfg(unix)]
+        file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+        file.write_all(serde_json::to_string_pretty(self)?.as_bytes())
+            .with_context(|| format!("Cant write backup '{}'", path.display()))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Cant read backup '{}'", path.display()))?;
+        let manifest = serde_json::from_str::<Value>(&content)
+            .ok()
+            .and_then(|x| serde_json::from_value::<Manifest>(x.get("manifest")?.clone()).ok())
+            .filter(|x| x.format == FORMAT)
+            .with_context(|| format!("'{}' is not a relation backup", path.display()))?;
+        if manifest.version > VERSION {
+            return Err(anyhow!(
+                "Backup version {} is newer than supported {VERSION}, update relation",
+                manifest.version
+            ));
+        }
+        serde_json::from_str(&content).context("Backup is broken")
+    }
+
+    /// Writes backup into `data_dir`, configs are not loaded by the caller yet
+    pub fn restore(&self, data_dir: &Path, mode: RestoreMode) -> Result<RestoreReport> {
+        if let Some(rel) = self.files.keys().find(|x| !is_safe(x)) {
+            return Err(anyhow!("Backup has unsafe path '{rel}'"));
+        }
+        let encryption = self.encryption(data_dir, mode)?;
+        if mode == RestoreMode::Replace {
+            clear(data_dir)?;
+        }
+
+        let mut report = RestoreReport::default();
+        let mut names = BTreeMap::new();
+        let mut local = local_configs(data_dir)?;
+        for name in self.files.keys().filter_map(|x| config_name(x)) {
+            let content = self.rewrite(&self.file(&format!("config/{name}.json"))?, data_dir)?;
+            let path = |x: &str| data_dir.join("config").join(format!("{x}.json"));
+            let target = match fs::read(path(&name)) {
+                Ok(x) if x == content => {
+                    report.unchanged.push(name);
+                    continue;
+                }
+                Ok(_) => free_name(&local, &name),
+                Err(_) => name.clone(),
+            };
+
+            write(&path(&target), &content)?;
+            match target == name {
+                true => report.added.push(name.clone()),
+                false => report.renamed.push((name.clone(), target.clone())),
+            }
+            local.push(target.clone());
+            names.insert(name, target);
+        }
+
+        for (rel, content) in &self.files {
+            let mut parts = rel.splitn(3, '/');
+            let path = match (parts.next(), parts.next(), parts.next()) {
+                (Some("config"), _, None) => continue,
+                (Some("settings.toml" | "usage.json"), None, None) => continue,
+                // History follows its config, history of unchanged config stays local
+                (Some("history"), Some(name), Some(file)) => match names.get(name) {
+                    Some(to) => data_dir.join("history").join(to).join(file),
+                    None => continue,
+                },
+                (Some("presets"), Some(file), None) => {
+                    let path = data_dir.join("presets").join(file);
+                    match fs::read(&path) {
+                        Ok(x) if x == content.bytes()? => continue,
+                        Ok(_) => free_file(&path),
+                        Err(_) => path,
+                    }
+                }
+                _ => data_dir.join(rel),
+            };
+            if path.exists() {
+                continue;
+            }
+            let bytes = match rel.starts_with("history/") && rel.ends_with(".json") {
+                true => self.rewrite(&content.bytes()?, data_dir)?,
+                false => content.bytes()?,
+            };
+            write(&path, &bytes)?;
+        }
+
+        self.restore_settings(data_dir, &names, encryption)?;
+        self.restore_usage(data_dir, &names)?;
+        Ok(report)
+    }
+
+    /// Encryption of backup which local settings lack. Fails when restored configs could
+    /// not be decrypted here: another local key or a key left in the old machine's keyring
+    fn encryption(&self, data_dir: &Path, mode: RestoreMode) -> Result<Option<Encryption>> {
+        let Some(backup) = self.settings()?.and_then(|x| x.encryption().cloned()) else {
+            return Ok(None);
+        };
+        let local = match mode {
+            RestoreMode::Merge => Settings::new(data_dir.join("settings.toml"))?
+                .encryption()
+                .cloned(),
+            RestoreMode::Replace => None,
+        };
+
+        if let Some(local) = local {
+            if !local.same_key(&backup)? {
+                return Err(anyhow!(
+                    "Configs in backup are encrypted with another key, \
+                     run `relation secrets disable` before making the backup"
+                ));
+            }
+            return Ok(None);
+        }
+        let context = match backup.source.as_str() {
+            "keyring" => {
+                "Key of configs in backup stays in the keyring of the machine which made it, \
+                 run `relation secrets disable` there before making the backup"
+            }
+            _ => "Cant decrypt configs in backup",
+        };
+        backup.key().context(context)?;
+        Ok(Some(backup))
+    }
+
+    fn settings(&self) -> Result<Option<Settings>> {
+        let Ok(content) = self.file("settings.toml") else {
+            return Ok(None);
+        };
+        let settings = toml::from_str(&String::from_utf8(content)?)
+            .context("Settings in backup are broken")?;
+        Ok(Some(settings))
+    }
+
+    /// Local settings win, quotas of restored configs and encryption of backup are added
+    fn restore_settings(
+        &self,
+        data_dir: &Path,
+        names: &BTreeMap<String, String>,
+        encryption: Option<Encryption>,
+    ) -> Result<()> {
+        let Ok(content) = self.file("settings.toml") else {
+            return Ok(());
+        };
+        let path = data_dir.join("settings.toml");
+        if !path.exists() {
+            return write(&path, &content);
+        }
+
+        let backup = self.settings()?.unwrap_or_default();
+        let mut settings = Settings::new(path.clone())?;
+        if encryption.is_some() {
+            settings.set_encryption(encryption);
+        }
+        for (name, quota) in backup.get_quotas() {
+            if let Some(to) = names.get(name)
+                && settings.get_quota(to).is_none()
+            {
+                settings.set_quota(to, quota.clone());
+            }
+        }
+        settings.save(path)
+    }
+
+    fn restore_usage(&self, data_dir: &Path, names: &BTreeMap<String, String>) -> Result<()> {
+        let Ok(content) = self.file("usage.json") else {
+            return Ok(());
+        };
+        let backup: UsageStore =
+            serde_json::from_slice(&content).context("Usage in backup is broken")?;
+        let path = UsageStore::path(data_dir);
+        let mut store = UsageStore::load(&path)?;
+        for (name, x) in backup.configs {
+            if let Some(to) = names.get(&name) {
+                store.configs.entry(to.clone()).or_insert(x);
+            }
+        }
+        store.save(&path)
+    }
+
+    fn file(&self, rel: &str) -> Result<Vec<u8>> {
+        self.files
+            .get(rel)
+            .with_context(|| format!("Backup has no '{rel}'"))?
+            .bytes()
+    }
+
+    /// Points log output and paths inside the old data dir to `data_dir`
+    fn rewrite(&self, content: &[u8], data_dir: &Path) -> Result<Vec<u8>> {
+        let Ok(mut tree) = serde_json::from_slice::<Value>(content) else {
+            return Ok(content.to_vec());
+        };
+        let mut changed = false;
+        rewrite_paths(&mut tree, &self.manifest.data_dir, data_dir, &mut changed);
+        if let Some(output) = tree.pointer_mut("/log/output")
+            && output.is_string()
+        {
+            let local = Value::String(
+                data_dir
+                    .join(render::LOG_FILE)
+                    .to_string_lossy()
+                    .to_string(),
+            );
+            changed |= *output != local;
+            *output = local;
+        }
+
+        if !changed {
+            return Ok(content.to_vec());
+        }
+        // Written like saved configs so unchanged ones compare equal
+        match serde_json::from_value::<Configurator>(tree.clone()) {
+            Ok(x) => Ok(serde_json::to_vec_pretty(&x)?),
+            Err(_) => Ok(serde_json::to_vec_pretty(&tree)?),
+        }
+    }
+}
+
+fn rewrite_paths(node: &mut Value, from: &str, to: &Path, changed: &mut bool) {
+    match node {
+        Value::String(x) => {
+            if let Some(rest) = x.strip_prefix(from)
+                && (rest.is_empty() || rest.starts_with(['/', '\\']))
+            {
+                let path = rest
+                    .split(['/', '\\'])
+                    .filter(|x| !x.is_empty())
+                    .fold(to.to_path_buf(), |path, x| path.join(x));
+                let path = path.to_string_lossy().to_string();
+                *changed |= *x != path;
+                *x = path;
+            }
+        }
+        Value::Array(x) => x
+            .iter_mut()
+            .for_each(|x| rewrite_paths(x, from, to, changed)),
+        Value::Object(x) => x
+            .values_mut()
+            .for_each(|x| rewrite_paths(x, from, to, changed)),
+        _ => {}
+    }
+}
+
+fn collect(dir: &Path, prefix: &str, files: &mut BTreeMap<String, Content>) -> Result<()> {
+    let entries = fs::read_dir(dir).with_context(|| format!("Cant read '{}'", dir.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let rel = format!("{prefix}{name}");
+        if path.is_dir() {
+            collect(&path, &format!("{rel}/"), files)?;
+        } else if !SKIPPED.contains(&rel.as_str()) && !name.ends_with(".tmp") {
+            files.insert(rel, Content::new(fs::read(&path)?));
+        }
+    }
+    Ok(())
+}
+
+/// Everything but skipped files
+fn clear(data_dir: &Path) -> Result<()> {
+    let Ok(entries) = fs::read_dir(data_dir) else {
+        return Ok(());
+    };
+    for entry in entries {
+        let path = entry?.path();
+        if SKIPPED.iter().any(|x| path.ends_with(x)) {
+            continue;
+        }
+        match path.is_dir() {
+            true => fs::remove_dir_all(&path)?,
+            false => fs::remove_file(&path)?,
+        }
+    }
+    Ok(())
+}
+
+fn write(path: &Path, content: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, content).with_context(|| format!("Cant write '{}'", path.display()))
+}
+
+/// Relative path which stays inside data dir: no root, prefix or `..`
+fn is_safe(rel: &str) -> bool {
+    !rel.is_empty()
+        && !rel.contains('\\')
+        && Path::new(rel)
+            .components()
+            .all(|x| matches!(x, Component::Normal(_)))
+}
+
+fn config_name(rel: &str) -> Option<String> {
+    rel.strip_prefix("config/")?
+        .strip_suffix(".json")
+        .filter(|x| is_safe(x) && !x.contains('/'))
+        .map(|x| x.to_string())
+}
+
+fn local_configs(data_dir: &Path) -> Result<Vec<String>> {
+    let Ok(entries) = fs::read_dir(data_dir.join("config")) else {
+        return Ok(vec![]);
+    };
+    let mut res = vec![];
+    for entry in entries {
+        if let Some(x) = config_name(&format!("config/{}", entry?.file_name().to_string_lossy())) {
+            res.push(x);
+        }
+    }
+    Ok(res)
+}
+
+/// First free `[n] name`, same scheme as `exist_config`
+fn free_name(names: &[String], name: &str) -> String {
+    (1..)
+        .map(|x| format!("[{x}] {name}"))
+        .find(|x| !names.contains(x))
+        .unwrap_or_default()
+}
+
+fn free_file(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path
+        .extension()
+        .map(|x| format!(".{}", x.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|x| path.with_file_name(format!("[{x}] {stem}{ext}")))
+        .find(|x| !x.exists())
+        .unwrap_or_default()
+}
@@ -0,0 +1,373 @@
+use anyhow::{Context, Result, anyhow};
+use serde_json::Value;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::iter;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::configurator::Configurator;
+use crate::configurator::profile::ProfileMeta;
+use crate::datamanager::history::History;
+use crate::usage::UsageStore;
+
+/// Connect timeout of latency probe, slower servers are listed as unreachable
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+/// Servers probed at the same time
+pub const PROBE_THREADS: usize = 16;
+/// Group of configs without tag or subscription
+pub const NO_GROUP: &str = "-";
+/// Length of config id, ids start with a letter so they never look like a list number
+pub const ID_LEN: usize = 6;
+const ID_CHARS: &[u8] = b"abcdefghijkmnpqrstuvwxyz23456789";
+
+/// Order of `relation list`, favourites go first in every order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListSort {
+    Name,
+    Latency,
+    LastUsed,
+}
+
+impl ListSort {
+    pub const ALL: &'static [ListSort] = &[ListSort::Name, ListSort::Latency, ListSort::LastUsed];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ListSort::Name => "name",
+            ListSort::Latency => "latency",
+            ListSort::LastUsed => "last-used",
+        }
+    }
+}
+
+impl FromStr for ListSort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.replace('_', "-");
+        ListSort::ALL
+            .iter()
+            .find(|x| x.name() == name)
+            .copied()
+            .ok_or_else(|| {
+                let names: Vec<&str> = ListSort::ALL.iter().map(|x| x.name()).collect();
+                format!("unknown sort, possible: {}", names.join(", "))
+            })
+    }
+}
+
+/// Grouping of configs list in the terminal UI
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Grouping {
+    #[default]
+    None,
+    Tag,
+    Subscription,
+}
+
+impl Grouping {
+    pub fn next(self) -> Self {
+        match self {
+            Grouping::None => Grouping::Tag,
+            Grouping::Tag => Grouping::Subscription,
+            Grouping::Subscription => Grouping::None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Grouping::None => "none",
+            Grouping::Tag => "tag",
+            Grouping::Subscription => "subscription",
+        }
+    }
+}
+
+/// Saved config with its metadata
+#[derive(Debug, Clone)]
+pub struct ConfigInfo {
+    /// Position in `App::get_list`
+    pub index: usize,
+    pub name: String,
+    pub meta: ProfileMeta,
+    /// Unix seconds of the last start
+    pub last_used: Option<u64>,
+    /// Address of the first proxy outbound
+    pub server: Option<(String, u16)>,
+    /// Connect time to `server`, filled by `probe`
+    pub latency: Option<Duration>,
+}
+
+impl ConfigInfo {
+    pub fn new(index: usize, name: &str, config: Option<&Configurator>) -> Self {
+        let server = config.and_then(|x| {
+            let outbounds = x.get_outbound_ref();
+            let kind = outbounds.get_types_except_direct().into_iter().next()?;
+            let outbound = outbounds.get_ref_by_type(&kind)?;
+            Some((outbound.get_addr(), outbound.get_port()?))
+        });
+        Self {
+            index,
+            name: name.to_string(),
+            meta: config
+                .and_then(|x| x.meta_ref())
+                .cloned()
+                .unwrap_or_default(),
+            last_used: None,
+            server,
+            latency: None,
+        }
+    }
+
+    pub fn favourite(&self) -> bool {
+        self.meta.favourite.unwrap_or(false)
+    }
+
+    pub fn tags(&self) -> &[String] {
+        self.meta.tags.as_deref().unwrap_or_default()
+    }
+
+    /// Region from metadata, flag emoji in the name otherwise
+    pub fn region(&self) -> Option<String> {
+        self.meta.region.clone().or_else(|| flag_region(&self.name))
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags().iter().any(|x| x.eq_ignore_ascii_case(tag))
+    }
+
+    /// Case insensitive search in name, tags, region, subscription and notes
+    pub fn matches(&self, text: &str) -> bool {
+        let text = text.to_lowercase();
+        [
+            Some(self.name.clone()),
+            self.region(),
+            self.meta.subscription.clone(),
+            self.meta.notes.clone(),
+        ]
+        .into_iter()
+        .flatten()
+        .chain(self.tags().iter().cloned())
+        .any(|x| x.to_lowercase().contains(&text))
+    }
+
+    /// Group of config, config with several tags is grouped by the first one
+    pub fn group(&self, grouping: Grouping) -> Option<String> {
+        let name = match grouping {
+            Grouping::None => return None,
+            Grouping::Tag => self.tags().first().cloned(),
+            Grouping::Subscription => self.meta.subscription.clone(),
+        };
+        Some(name.unwrap_or_else(|| NO_GROUP.to_string()))
+    }
+}
+
+/// Metadata of `names` in `config_dir`, configs which fail to load have only the name
+pub fn load(config_dir: &Path, names: &[String], usage: &UsageStore) -> Vec<ConfigInfo> {
+    names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let mut config = Configurator::new();
+            let loaded = config
+                .load_from_file(config_dir.join(format!("{name}.json")))
+                .is_ok();
+            let mut info = ConfigInfo::new(i, name, loaded.then_some(&config));
+            info.last_used = usage.get(name).and_then(|x| x.last_used);
+            info
+        })
+        .collect()
+}
+
+/// Random config id like `k3f9xq`
+pub fn new_id() -> String {
+    let mut bytes = [0u8; ID_LEN];
+    let _ = getrandom::getrandom(&mut bytes);
+    bytes
+        .iter()
+        .enumerate()
+        .map(|(i, x)| match i {
+            0 => ID_CHARS[*x as usize % 24] as char,
+            _ => ID_CHARS[*x as usize % ID_CHARS.len()] as char,
+        })
+        .collect()
+}
+
+/// New id which is not in `taken`
+pub fn free_id(taken: &[Option<String>]) -> String {
+    iter::repeat_with(new_id)
+        .find(|x| !taken.iter().any(|y| y.as_ref() == Some(x)))
+        .unwrap_or_default()
+}
+
+/// Ids of `names` in `config_dir`, `None` for configs without one or which fail to parse
+pub fn ids(config_dir: &Path, names: &[String]) -> Vec<Option<String>> {
+    names
+        .iter()
+        .map(|name| {
+            let content = fs::read_to_string(config_dir.join(format!("{name}.json"))).ok()?;
+            let tree: Value = serde_json::from_str(&content).ok()?;
+            Some(tree.pointer("/relation/id")?.as_str()?.to_string())
+        })
+        .collect()
+}
+
+/// Gives an id to configs which have none, like restored ones from old backups.
+/// When configs share an id, the oldest file keeps it. Writes are kept in history
+pub fn assign_ids(data_dir: &Path, names: &[String]) -> Result<()> {
+    let config_dir = &data_dir.join("config");
+    let mut configs: Vec<(&String, Option<String>, Option<SystemTime>)> = names
+        .iter()
+        .zip(ids(config_dir, names))
+        .map(|(name, id)| {
+            let modified = fs::metadata(config_dir.join(format!("{name}.json")))
+                .and_then(|x| x.modified())
+                .ok();
+            (name, id, modified)
+        })
+        .collect();
+    configs.sort_by(|a, b| a.2.cmp(&b.2).then_with(|| a.0.cmp(b.0)));
+
+    let mut taken: HashSet<String> = HashSet::new();
+    for (name, id, _) in configs {
+        if let Some(x) = id
+            && taken.insert(x)
+        {
+            continue;
+        }
+        let path = config_dir.join(format!("{name}.json"));
+        let mut config = Configurator::new();
+        if config.load_from_file(path.clone()).is_err() {
+            continue;
+        }
+        let id = iter::repeat_with(new_id)
+            .find(|x| !taken.contains(x))
+            .unwrap_or_default();
+        config.meta_mut().id = Some(id.clone());
+        let mut history = History::open(data_dir, name)?;
+        history.record(&fs::read_to_string(&path)?)?;
+        config.save_to_file(config_dir.to_path_buf(), name)?;
+        history.record(&fs::read_to_string(&path)?)?;
+        taken.insert(id);
+    }
+    Ok(())
+}
+
+/// Index in `names` of config given by name, id, unique prefix of id or name or 1-based number,
+/// tried in this order
+pub fn resolve(config_dir: &Path, names: &[String], value: &str) -> Result<usize> {
+    if let Some(x) = names.iter().position(|x| x == value) {
+        return Ok(x);
+    }
+    let ids = ids(config_dir, names);
+    if let Some(x) = ids.iter().position(|x| x.as_deref() == Some(value)) {
+        return Ok(x);
+    }
+
+    let matching: Vec<usize> = (0..names.len())
+        .filter(|i| {
+            names[*i].starts_with(value) || ids[*i].as_deref().is_some_and(|x| x.starts_with(value))
+        })
+        .collect();
+    if let [x] = matching[..] {
+        return Ok(x);
+    }
+    if let Ok(n) = value.parse::<usize>() {
+        let index = n.checked_sub(1).context("Configs are numbered from 1")?;
+        return match index < names.len() {
+            true => Ok(index),
+            false => Err(anyhow!(
+                "There is no config number {n}, see `relation list`"
+            )),
+        };
+    }
+    match matching[..] {
+        [] => Err(anyhow!("Config '{value}' does not exist")),
+        _ => Err(anyhow!(
+            "'{value}' matches several configs: {}",
+            matching
+                .iter()
+                .map(|x| names[*x].as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+/// Times tcp connect to servers of `configs`, `PROBE_THREADS` at a time
+pub fn probe(configs: &mut [ConfigInfo]) {
+    let queue = Mutex::new(configs.iter_mut());
+    thread::scope(|s| {
+        for _ in 0..PROBE_THREADS {
+            s.spawn(|| {
+                loop {
+                    let next = queue.lock().map(|mut x| x.next());
+                    let Ok(Some(info)) = next else {
+                        break;
+                    };
+                    info.latency = info.server.as_ref().and_then(|(host, port)| {
+                        let addr = (host.as_str(), *port).to_socket_addrs().ok()?.next()?;
+                        let start = Instant::now();
+                        TcpStream::connect_timeout(&addr, PROBE_TIMEOUT).ok()?;
+                        Some(start.elapsed())
+                    });
+                }
+            });
+        }
+    });
+}
+
+/// Favourites first, then `sort`, unknown latency and last use go last
+pub fn sort(configs: &mut [ConfigInfo], sort: ListSort) {
+    configs.sort_by(|a, b| {
+        b.favourite().cmp(&a.favourite()).then_with(|| match sort {
+            ListSort::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            ListSort::Latency => last_none(a.latency, b.latency),
+            ListSort::LastUsed => last_none(a.last_used.map(Reverse), b.last_used.map(Reverse)),
+        })
+    });
+}
+
+/// Configs by group name keeping their order, ungrouped configs go last
+pub fn grouped<'a>(
+    configs: impl IntoIterator<Item = &'a ConfigInfo>,
+    grouping: Grouping,
+) -> Vec<(String, Vec<&'a ConfigInfo>)> {
+    let mut groups: BTreeMap<String, Vec<&ConfigInfo>> = BTreeMap::new();
+    for info in configs {
+        if let Some(name) = info.group(grouping) {
+            groups.entry(name).or_default().push(info);
+        }
+    }
+    let rest = groups.remove(NO_GROUP);
+    groups
+        .into_iter()
+        .chain(rest.map(|x| (NO_GROUP.to_string(), x)))
+        .collect()
+}
+
+fn last_none<T: Ord>(a: Option<T>, b: Option<T>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (a, b) => a.is_none().cmp(&b.is_none()),
+    }
+}
+
+/// `DE` from the first regional indicator pair in `name`
+fn flag_region(name: &str) -> Option<String> {
+    let letters: Vec<char> = name
+        .chars()
+        .filter_map(|x| {
+            let code = (x as u32).checked_sub(0x1F1E6)?;
+            (code < 26).then(|| (b'A' + code as u8) as char)
+        })
+        .take(2)
+        .collect();
+    (letters.len() == 2).then(|| letters.into_iter().collect())
+}
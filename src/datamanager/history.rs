@@ -0,0 +1,123 @@
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::consts;
+use crate::usage;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Revision {
+    /// Numbered from 1, numbers are not reused after pruning
+    pub rev: u64,
+    /// Unix seconds
+    pub time: u64,
+    /// Revision this one was restored from by `undo` or `revert`
+    pub restored: Option<u64>,
+}
+
+/// Saved states of one config in `<data dir>/history/<name>`
+pub struct History {
+    dir: PathBuf,
+    revisions: Vec<Revision>,
+}
+
+impl History {
+    pub fn open(data_dir: &Path, config: &str) -> Result<Self> {
+        let dir = data_dir.join("history").join(config);
+        let revisions = match fs::read_to_string(dir.join("index.json")) {
+            Ok(x) => serde_json::from_str(&x)
+                .with_context(|| format!("History of '{config}' is broken"))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(anyhow!(e)),
+        };
+        Ok(Self { dir, revisions })
+    }
+
+    pub fn list(&self) -> &[Revision] {
+        &self.revisions
+    }
+
+    pub fn latest(&self) -> Option<&Revision> {
+        self.revisions.last()
+    }
+
+    pub fn read(&self, rev: u64) -> Result<String> {
+        if !self.revisions.iter().any(|x| x.rev == rev) {
+            return Err(anyhow!("Revision {rev} does not exist"));
+        }
+        Ok(fs::read_to_string(self.dir.join(format!("{rev}.json")))?)
+    }
+
+    /// Stores `content` unless it is the latest revision, returns new revision
+    pub fn record(&mut self, content: &str) -> Result<Option<u64>> {
+        if let Some(x) = self.latest()
+            && self.read(x.rev).is_ok_and(|x| x == content)
+        {
+            return Ok(None);
+        }
+
+        fs::create_dir_all(&self.dir)?;
+        let rev = self.latest().map_or(1, |x| x.rev + 1);
+        fs::write(self.dir.join(format!("{rev}.json")), content)?;
+        self.revisions.push(Revision {
+            rev,
+            time: usage::now(),
+            restored: None,
+        });
+
+        let extra = self
+            .revisions
+            .len()
+            .saturating_sub(consts::history::REVISIONS);
+        for x in self.revisions.drain(..extra) {
+            let _ = fs::remove_file(self.dir.join(format!("{}.json", x.rev)));
+        }
+        self.save()?;
+        Ok(Some(rev))
+    }
+
+    /// Marks revision `written` as copy of `rev`
+    pub fn set_restored(&mut self, written: u64, rev: u64) -> Result<()> {
+        if let Some(x) = self.revisions.iter_mut().find(|x| x.rev == written) {
+            x.restored = Some(rev);
+        }
+        self.save()
+    }
+
+    /// Revision before the latest, restored revisions are skipped so undo goes further back
+    pub fn undo_target(&self) -> Option<u64> {
+        let latest = self.latest()?;
+        let base = latest.restored.unwrap_or(latest.rev);
+        self.revisions
+            .iter()
+            .rev()
+            .map(|x| x.rev)
+            .find(|x| *x < base)
+    }
+
+    /// Drops history of removed config
+    pub fn remove(data_dir: &Path, config: &str) -> Result<()> {
+        match fs::remove_dir_all(data_dir.join("history").join(config)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(anyhow!(e)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Keeps history of renamed config
+    pub fn rename(data_dir: &Path, from: &str, to: &str) -> Result<()> {
+        let dir = data_dir.join("history");
+        if dir.join(from).exists() && !dir.join(to).exists() {
+            fs::rename(dir.join(from), dir.join(to))?;
+        }
+        Ok(())
+    }
+
+    fn save(&self) -> Result<()> {
+        fs::write(
+            self.dir.join("index.json"),
+            serde_json::to_string(&self.revisions)?,
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,203 @@
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use super::Settings;
+use crate::clashapi;
+use crate::configurator::experimental::ClashApiConfig;
+use crate::secrets::{self, Key};
+
+/// Log of the core, read by `logs` and the terminal UI
+pub const LOG_FILE: &str = "box.log";
+/// Cache of fakeip, rule sets and dns, kept between runs
+pub const CACHE_FILE: &str = "cache.db";
+/// Subdir of runtime dir with configs opened in editor
+pub const EDIT_DIR: &str = "edit";
+/// Clash api of the running core in runtime dir, kept until the core stops
+pub const CLASH_API_FILE: &str = "clash-api.json";
+
+/// Controller and secret given to the core, they belong to this machine and run
+#[derive(Serialize, Deserialize)]
+struct RunningClashApi {
+    /// Profile the core was started with
+    config: PathBuf,
+    clash_api: ClashApiConfig,
+}
+
+/// Directory of rendered configs, readable only by the user
+pub fn runtime_dir() -> PathBuf {
+    directories::BaseDirs::new()
+        .and_then(|x| x.runtime_dir().map(Path::to_path_buf))
+        .unwrap_or_else(std::env::temp_dir)
+        .join("relation")
+}
+
+/// Writes sing-box config of profile at `<data dir>/config/<name>.json` into runtime dir
+///
+/// Relation metadata is removed, log output and cache file are pointed into data dir
+/// and encrypted credentials are decrypted with `key`. Clash api is enabled on loopback
+/// with a new secret unless settings turn it off or the profile sets a controller
+pub fn render(profile: &Path, key: Option<&Key>) -> Result<PathBuf> {
+    let data_dir = profile
+        .parent()
+        .and_then(Path::parent)
+        .context("Config is outside of data dir")?;
+    let mut tree: Value = serde_json::from_str(
+        &fs::read_to_string(profile)
+            .with_context(|| format!("Cant read config '{}'", profile.display()))?,
+    )?;
+    if secrets::has_encrypted(&tree) {
+        let key = key.context("Config has encrypted credentials and key is not given")?;
+        secrets::decrypt_tree(&mut tree, key)?;
+    }
+
+    let mut settings = Settings::default();
+    settings.read(data_dir.join("settings.toml"))?;
+    let local_api = match tree
+        .pointer("/experimental/clash_api/external_controller")
+        .is_none()
+        && settings.clash_api_enabled()
+    {
+        true => Some(ClashApiConfig::local(
+            clashapi::free_port(settings.clash_api_port()),
+            clashapi::generate_secret()?,
+        )),
+        false => None,
+    };
+
+    let root = tree.as_object_mut().context("Config is not an object")?;
+    root.remove("relation");
+
+    if let Some(x) = local_api {
+        let clash_api = object(object(root, "experimental"), "clash_api");
+        if let Value::Object(x) = serde_json::to_value(x)? {
+            clash_api.extend(x);
+        }
+    }
+
+    let log = object(root, "log");
+    log.entry("level").or_insert_with(|| "info".into());
+    log.insert("output".to_string(), path_value(&data_dir.join(LOG_FILE)));
+
+    if let Some(Value::Object(cache)) = root
+        .get_mut("experimental")
+        .and_then(|x| x.get_mut("cache_file"))
+    {
+        let local = cache
+            .get("path")
+            .and_then(Value::as_str)
+            .is_some_and(|x| Path::new(x).is_absolute());
+        if !local {
+            cache.insert("path".to_string(), path_value(&data_dir.join(CACHE_FILE)));
+        }
+    }
+
+    clear()?;
+    remove_clash_api()?;
+    if let Some(x) = tree.pointer("/experimental/clash_api") {
+        let running = RunningClashApi {
+            config: profile.to_path_buf(),
+            clash_api: serde_json::from_value(x.clone())?,
+        };
+        create_private(&private_dir()?.join(CLASH_API_FILE))?
+            .write_all(&serde_json::to_vec(&running)?)?;
+    }
+
+    let path = private_dir()?.join(
+        profile
+            .file_name()
+            .context("Config path has no file name")?,
+    );
+    create_private(&path)?.write_all(&serde_json::to_vec(&tree)?)?;
+    Ok(path)
+}
+
+/// Clash api given to the core when it was started with `profile`
+pub fn clash_api(profile: &Path) -> Option<ClashApiConfig> {
+    let content = fs::read_to_string(runtime_dir().join(CLASH_API_FILE)).ok()?;
+    let running: RunningClashApi = serde_json::from_str(&content).ok()?;
+    (running.config == profile).then_some(running.clash_api)
+}
+
+/// Forgets clash api of the stopped core
+pub fn remove_clash_api() -> Result<()> {
+    match fs::remove_file(runtime_dir().join(CLASH_API_FILE)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Runtime dir, created with `0700`. Fails when it is a symlink, belongs to another user
+/// or is open to others, as it is in shared `/tmp` without `XDG_RUNTIME_DIR`
+pub fn private_dir() -> Result<PathBuf> {
+    let dir = runtime_dir();
+    ensure_private(&dir)?;
+    Ok(dir)
+}
+
+/// Private dir of configs opened in editor, `clear` leaves it alone
+pub fn edit_dir() -> Result<PathBuf> {
+    let dir = private_dir()?.join(EDIT_DIR);
+    ensure_private(&dir)?;
+    Ok(dir)
+}
+
+fn ensure_private(dir: &Path) -> Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(dir)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let meta = fs::symlink_metadata(dir)?;
+        let uid = unsafe { libc::geteuid() };
+        if !meta.is_dir() || meta.uid() != uid || meta.mode() & 0o777 != 0o700 {
+            return Err(anyhow!(
+                "'{}' must be a directory of uid {uid} with mode 0700",
+                dir.display()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// New file readable only by the user, an existing file or symlink is an error
+pub fn create_private(path: &Path) -> Result<fs::File> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(path)
+        .with_context(|| format!("Cant create '{}'", path.display()))
+}
+
+/// Removes rendered configs, the core reads them only on start
+pub fn clear() -> Result<()> {
+    for entry in fs::read_dir(runtime_dir()).into_iter().flatten() {
+        let entry = entry?;
+        if entry.file_type()?.is_file() && entry.file_name() != CLASH_API_FILE {
+            fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// Object under `key`, created when missing
+fn object<'a>(root: &'a mut Map<String, Value>, key: &str) -> &'a mut Map<String, Value> {
+    let value = root.entry(key).or_insert_with(|| Value::Object(Map::new()));
+    if !value.is_object() {
+        *value = Value::Object(Map::new());
+    }
+    value.as_object_mut().unwrap()
+}
+
+fn path_value(path: &Path) -> Value {
+    Value::String(path.to_string_lossy().to_string())
+}
@@ -1,11 +1,14 @@
 #![cfg_attr(not(debug_assertions), allow(warnings))]
 
 pub mod bridge;
+pub mod clashapi;
 pub mod configurator;
 pub mod consts;
 pub mod datamanager;
 pub mod minireq;
+pub mod secrets;
 pub mod ui;
+pub mod usage;
 
 use macros::auto_skip_none;
 use std::process::{Command as StdCommand, Stdio};
@@ -41,6 +44,20 @@ impl Request {
         }
     }
 
+    /// Restarts core with changed config
+    pub fn reload(config_path: String) -> Self {
+        Request {
+            command: Command::Reload(config_path),
+        }
+    }
+
+    /// Key of encrypted credentials, kept by daemon until it exits
+    pub fn unlock(key: String) -> Self {
+        Request {
+            command: Command::Unlock(key),
+        }
+    }
+
     pub fn stop() -> Self {
         Request {
             command: Command::Stop,
@@ -71,6 +88,8 @@ impl Request {
 pub enum Command {
     EnableSysProxy((String, u16, bool)),
     Start(String),
+    Reload(String),
+    Unlock(String),
     Status,
     Stop,
     DisableSysProxy,
@@ -96,6 +115,9 @@ pub struct DaemonStatus {
     pub ping: Option<u128>,
     #[serde(rename = "Ip")]
     pub ip: Option<String>,
+    /// Passed data quota
+    #[serde(rename = "Warning", default, skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
 }
 
 impl DaemonStatus {
@@ -109,6 +131,7 @@ impl DaemonStatus {
             sys_proxy: false,
             ping: None,
             ip: None,
+            warning: None,
         }
     }
 }
@@ -0,0 +1,381 @@
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use anyhow::{Context, Result, anyhow};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::io::IsTerminal;
+use std::path::Path;
+use std::sync::Mutex;
+use zeroize::Zeroize;
+
+use crate::configurator::Configurator;
+
+/// Start of encrypted value in saved config
+pub const PREFIX: &str = "enc:v1:";
+/// Inbound and outbound fields which hold credentials
+pub const FIELDS: [&str; 6] = [
+    "uuid",
+    "password",
+    "public_key",
+    "private_key",
+    "pre_shared_key",
+    "short_id",
+];
+/// Passphrase for scripts, asked on terminal otherwise
+pub const PASSPHRASE_ENV: &str = "RELATION_PASSPHRASE";
+/// Config sections with credentials and their fields, users of inbounds included
+const SECTIONS: [(&str, &[&str]); 3] = [
+    ("/outbounds", &FIELDS),
+    ("/inbounds", &FIELDS),
+    ("/experimental/clash_api", &["secret"]),
+];
+pub const SOURCES: [&str; 2] = ["passphrase", "keyring"];
+
+#[cfg(feature = "keyring")]
+const KEYRING_SERVICE: &str = "relation";
+#[cfg(feature = "keyring")]
+const KEYRING_USER: &str = "config-key";
+const CHECK_TEXT: &str = "relation";
+const NONCE_LEN: usize = 12;
+/// scrypt cost, 2^15 rounds take about 100ms
+const SCRYPT_LOG_N: u8 = 15;
+
+/// Key is asked once per process
+static CACHED: Mutex<Option<Key>> = Mutex::new(None);
+
+/// Encryption settings of data dir
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Encryption {
+    /// `passphrase` or `keyring`
+    pub source: String,
+    /// Salt of passphrase key, base64
+    pub salt: Option<String>,
+    /// Known text encrypted with the key, tells wrong passphrase from broken data
+    pub check: String,
+}
+
+impl Encryption {
+    /// New key from `source`, keyring key is stored right away
+    pub fn create(source: &str) -> Result<(Self, Key)> {
+        let (key, salt) = match source {
+            "passphrase" => {
+                let salt = random::<16>()?;
+                let passphrase = read_passphrase(true)?;
+                (
+                    Key::derive(&passphrase, &salt)?,
+                    Some(STANDARD.encode(salt)),
+                )
+            }
+            "keyring" => {
+                let key = Key(random()?);
+                keyring_store(&key)?;
+                (key, None)
+            }
+            _ => return Err(anyhow!("Key source must be one of {SOURCES:?}")),
+        };
+
+        let encryption = Self {
+            source: source.to_string(),
+            salt,
+            check: key.encrypt(CHECK_TEXT)?,
+        };
+        *CACHED.lock().unwrap() = Some(key.clone());
+        Ok((encryption, key))
+    }
+
+    /// Key from passphrase or keyring, checked against `check`
+    pub fn key(&self) -> Result<Key> {
+        if let Some(x) = CACHED.lock().unwrap().as_ref() {
+            return Ok(x.clone());
+        }
+
+        let key = match self.source.as_str() {
+            "keyring" => keyring_load()?,
+            _ => {
+                let salt = STANDARD.decode(self.salt.as_deref().unwrap_or_default())?;
+                Key::derive(&read_passphrase(false)?, &salt)?
+            }
+        };
+        if key.decrypt(&self.check).ok().as_deref() != Some(CHECK_TEXT) {
+            return Err(anyhow!("Wrong passphrase"));
+        }
+
+        *CACHED.lock().unwrap() = Some(key.clone());
+        Ok(key)
+    }
+
+    /// Whether `other` uses the same key, the key is asked only when settings differ
+    pub fn same_key(&self, other: &Encryption) -> Result<bool> {
+        if self.check == other.check {
+            return Ok(true);
+        }
+        Ok(self.key()?.decrypt(&other.check).ok().as_deref() == Some(CHECK_TEXT))
+    }
+
+    /// Forgets keyring key after secrets are decrypted
+    pub fn remove(&self) -> Result<()> {
+        match self.source.as_str() {
+            "keyring" => keyring_remove(),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// AES-256-GCM key, wiped on drop
+#[derive(Clone)]
+pub struct Key([u8; 32]);
+
+impl Key {
+    fn derive(passphrase: &str, salt: &[u8]) -> Result<Self> {
+        let params = scrypt::Params::new(SCRYPT_LOG_N, 8, 1, 32).map_err(|e| anyhow!("{e}"))?;
+        let mut key = [0u8; 32];
+        scrypt::scrypt(passphrase.as_bytes(), salt, &params, &mut key)
+            .map_err(|e| anyhow!("{e}"))?;
+        Ok(Self(key))
+    }
+
+    pub fn from_base64(value: &str) -> Result<Self> {
+        let bytes = STANDARD.decode(value).context("Key is not base64")?;
+        Ok(Self(
+            bytes
+                .try_into()
+                .map_err(|_| anyhow!("Key must be 32 bytes"))?,
+        ))
+    }
+
+    pub fn to_base64(&self) -> String {
+        STANDARD.encode(self.0)
+    }
+
+    /// `enc:v1:` and base64 of nonce with ciphertext
+    pub fn encrypt(&self, plain: &str) -> Result<String> {
+        let nonce = random::<NONCE_LEN>()?;
+        let mut data = self
+            .cipher()
+            .encrypt(Nonce::from_slice(&nonce), plain.as_bytes())
+            .map_err(|_| anyhow!("Failed to encrypt"))?;
+        data.splice(0..0, nonce);
+        Ok(format!("{PREFIX}{}", STANDARD.encode(data)))
+    }
+
+    pub fn decrypt(&self, value: &str) -> Result<String> {
+        let data = value
+            .strip_prefix(PREFIX)
+            .and_then(|x| STANDARD.decode(x).ok())
+            .filter(|x| x.len() > NONCE_LEN)
+            .context("Value is not encrypted")?;
+        let (nonce, data) = data.split_at(NONCE_LEN);
+        let plain = self
+            .cipher()
+            .decrypt(Nonce::from_slice(nonce), data)
+            .map_err(|_| anyhow!("Failed to decrypt, wrong key or broken value"))?;
+        Ok(String::from_utf8(plain)?)
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new((&self.0).into())
+    }
+}
+
+impl Drop for Key {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(PREFIX)
+}
+
+/// Encrypts plain credentials, returns whether any was found
+pub fn encrypt_tree(tree: &mut Value, key: &Key) -> Result<bool> {
+    walk(tree, &mut |x| match is_encrypted(x) {
+        true => Ok(None),
+        false => key.encrypt(x).map(Some),
+    })
+}
+
+/// Decrypts credentials, returns whether any was found
+pub fn decrypt_tree(tree: &mut Value, key: &Key) -> Result<bool> {
+    walk(tree, &mut |x| match is_encrypted(x) {
+        true => key.decrypt(x).map(Some),
+        false => Ok(None),
+    })
+}
+
+/// Whether config has credentials in plain text
+pub fn has_plain(tree: &Value) -> bool {
+    count(tree, |x| !is_encrypted(x)) > 0
+}
+
+/// Whether config has encrypted credentials
+pub fn has_encrypted(tree: &Value) -> bool {
+    count(tree, is_encrypted) > 0
+}
+
+fn count(tree: &Value, filter: impl Fn(&str) -> bool) -> usize {
+    let mut res = 0;
+    let mut tree = tree.clone();
+    let _ = walk(&mut tree, &mut |x| {
+        res += filter(x) as usize;
+        Ok(None)
+    });
+    res
+}
+
+/// Calls `f` on credential fields of `SECTIONS`, `Some` replaces the value
+fn walk(tree: &mut Value, f: &mut impl FnMut(&str) -> Result<Option<String>>) -> Result<bool> {
+    fn visit(
+        node: &mut Value,
+        fields: &[&str],
+        f: &mut impl FnMut(&str) -> Result<Option<String>>,
+        found: &mut bool,
+    ) -> Result<()> {
+        match node {
+            Value::Object(x) => {
+                for (key, value) in x.iter_mut() {
+                    match value {
+                        Value::String(text) if fields.contains(&key.as_str()) => {
+                            if let Some(new) = f(text)? {
+                                *text = new;
+                                *found = true;
+                            }
+                        }
+                        _ => visit(value, fields, f, found)?,
+                    }
+                }
+            }
+            Value::Array(x) => {
+                for value in x {
+                    visit(value, fields, f, found)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    let mut found = false;
+    for (section, fields) in SECTIONS {
+        if let Some(x) = tree.pointer_mut(section) {
+            visit(x, fields, f, &mut found)?;
+        }
+    }
+    Ok(found)
+}
+
+/// Applies `f` to saved configs and their revisions, files are written only when `f` changes them
+pub fn rewrite_configs(
+    data_dir: &Path,
+    mut f: impl FnMut(&mut Value) -> Result<bool>,
+) -> Result<usize> {
+    let mut files = vec![];
+    for dir in [data_dir.join("config")].into_iter().chain(
+        fs::read_dir(data_dir.join("history"))
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|x| x.path()),
+    ) {
+        for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+            let path = entry.path();
+            if path.extension().unwrap_or_default() == "json"
+                && path.file_stem().unwrap_or_default() != "index"
+            {
+                files.push(path);
+            }
+        }
+    }
+
+    let mut res = 0;
+    for path in files {
+        let mut tree: Value = serde_json::from_str(&fs::read_to_string(&path)?)
+            .with_context(|| format!("'{}' is broken", path.display()))?;
+        if !f(&mut tree)? {
+            continue;
+        }
+        // Written like saved configs
+        let content = match serde_json::from_value::<Configurator>(tree.clone()) {
+            Ok(x) => serde_json::to_string_pretty(&x)?,
+            Err(_) => serde_json::to_string_pretty(&tree)?,
+        };
+        fs::write(&path, content)?;
+        res += 1;
+    }
+    Ok(res)
+}
+
+fn read_passphrase(confirm: bool) -> Result<String> {
+    if let Ok(x) = std::env::var(PASSPHRASE_ENV) {
+        return Ok(x);
+    }
+    if !std::io::stdin().is_terminal() {
+        return Err(anyhow!("Passphrase is needed, set {PASSPHRASE_ENV}"));
+    }
+    // prompt would be drawn over the terminal UI and raw mode never ends the line
+    #[cfg(feature = "tui")]
+    if crossterm::terminal::is_raw_mode_enabled().unwrap_or(false) {
+        return Err(anyhow!("Passphrase is needed, set {PASSPHRASE_ENV}"));
+    }
+
+    let passphrase = rpassword::prompt_password("Passphrase: ")?;
+    if passphrase.is_empty() {
+        return Err(anyhow!("Passphrase is empty"));
+    }
+    if confirm && rpassword::prompt_password("Repeat passphrase: ")? != passphrase {
+        return Err(anyhow!("Passphrases do not match"));
+    }
+    Ok(passphrase)
+}
+
+fn random<const N: usize>() -> Result<[u8; N]> {
+    let mut res = [0u8; N];
+    getrandom::getrandom(&mut res).map_err(|e| anyhow!("No random source: {e}"))?;
+    Ok(res)
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_entry() -> Result<keyring::Entry> {
+    Ok(keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)?)
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_store(key: &Key) -> Result<()> {
+    keyring_entry()?
+        .set_password(&key.to_base64())
+        .context("Cant store key in keyring")
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_load() -> Result<Key> {
+    let value = keyring_entry()?
+        .get_password()
+        .context("Cant read key from keyring")?;
+    Key::from_base64(&value)
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_remove() -> Result<()> {
+    match keyring_entry()?.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(anyhow!(e)),
+    }
+}
+
+#[cfg(not(feature = "keyring"))]
+fn keyring_store(_key: &Key) -> Result<()> {
+    Err(anyhow!("relation is built without keyring support"))
+}
+
+#[cfg(not(feature = "keyring"))]
+fn keyring_load() -> Result<Key> {
+    Err(anyhow!("relation is built without keyring support"))
+}
+
+#[cfg(not(feature = "keyring"))]
+fn keyring_remove() -> Result<()> {
+    Ok(())
+}
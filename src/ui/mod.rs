@@ -1,10 +1,17 @@
 #![warn(clippy::never_loop)]
 
+mod output;
 #[cfg(feature = "tui")]
 mod tui;
 
 use anyhow::{Context, Result, anyhow};
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::net::IpAddr;
+use std::path::Path;
 use std::str::FromStr;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, OnceLock};
@@ -15,12 +22,291 @@ use crate::datamanager::app::App;
 #[cfg(feature = "daemon")]
 use crate::datamanager::async_app::App;
 
+use crate::clashapi;
+use crate::configurator::Configurator;
+use crate::configurator::apps::{AppPolicy, AppTarget};
+use crate::configurator::diff;
+use crate::configurator::dns::dnsrule::DnsDefaultRule;
+use crate::configurator::dns::dnsruleaction::{
+    DnsPredefinedAction, DnsRejectAction, DnsRouteAction, DnsRuleAction,
+};
+use crate::configurator::inbound::tun::TunField;
+use crate::configurator::preset::Preset;
+use crate::configurator::route::explain::{self, Metadata, Verdict};
+use crate::configurator::route::parse_rule_path;
+use crate::configurator::route::routerule::RouteRule;
+use crate::configurator::route::ruleset::{RuleSet, RuleSetInline, RuleSetLocal, RuleSetRemote};
+use crate::configurator::tree;
+use crate::datamanager::backup::{Backup, RestoreMode};
+use crate::datamanager::catalog::{self, ConfigInfo, ListSort};
+use crate::datamanager::history::History;
+use crate::datamanager::render;
+use crate::secrets::{self, Encryption};
+use crate::usage::{self, Quota, UsageStore};
+use output::*;
+
+pub use output::OutputFormat;
+
 use signal_hook::consts::SIGINT;
 // use signal_hook::iterator::Signals;
 use signal_hook::flag;
 
 static SHUTDOWN: OnceLock<Arc<AtomicBool>> = OnceLock::new();
 
+/// Loads config into handler, current config is used when `value` is None
+fn select_config(manager: &mut App, value: &Option<ConfigEn>) -> Result<()> {
+    match value {
+        Some(x) => manager.set_handler_config_by_name(&config_name(manager, x)?),
+        None => manager.set_handler_config_by_current(),
+    }
+}
+
+/// Name of saved config, numbers and ids resolve the same way in every command
+fn config_name(manager: &App, value: &ConfigEn) -> Result<String> {
+    let names = manager.get_list();
+    let index = catalog::resolve(&manager.get_configs_path(), &names, &value.0)?;
+    Ok(names[index].clone())
+}
+
+/// Edits config tree with each reading of value, the first one fitting typed config is saved
+fn edit_config(
+    manager: &mut App,
+    path: &str,
+    values: Vec<Value>,
+    edit: impl Fn(&mut Value, Value) -> Result<()>,
+) -> Result<()> {
+    let tree = manager.handler_ref().to_tree()?;
+    let mut first_error = None;
+    for value in values {
+        let keep = !value.is_null();
+        let mut edited = tree.clone();
+        let res = edit(&mut edited, value).and_then(|_| {
+            manager
+                .handler_mut()
+                .set_tree(edited)
+                .map_err(|e| anyhow!("'{path}': {e}"))?;
+            // Unknown fields are dropped by typed config
+            if keep && tree::get(&manager.handler_ref().to_tree()?, path).is_err() {
+                manager.handler_mut().set_tree(tree.clone())?;
+                return Err(anyhow!("'{path}' is not a known field"));
+            }
+            Ok(())
+        });
+        match res {
+            Ok(_) => return manager.save(),
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    Err(first_error.unwrap_or_else(|| anyhow!("No value for '{path}'")))
+}
+
+/// Config of revision, current config when `rev` is None
+fn load_revision(manager: &App, history: &History, rev: Option<u64>) -> Result<Configurator> {
+    let tree = match rev {
+        Some(x) => serde_json::from_str(&history.read(x)?)?,
+        None => manager.handler_ref().to_tree()?,
+    };
+    let mut config = Configurator::new();
+    config.set_tree(tree)?;
+    Ok(config)
+}
+
+/// `2024-01-02 03:04` in UTC
+fn format_time(secs: u64) -> String {
+    format!(
+        "{} {:02}:{:02}",
+        usage::day_key(secs),
+        secs % 86400 / 3600,
+        secs % 3600 / 60
+    )
+}
+
+/// `[ 3]: k3f9xq  * name  #tag  DE  120ms  used 2024-01-02 03:04`, number and id are accepted by commands
+fn list_line(info: &ConfigInfo, latency: bool) -> String {
+    let mut parts = vec![format!(
+        "[{:2}]: {:width$}  {}{}",
+        info.index + 1,
+        info.meta.id.as_deref().unwrap_or_default(),
+        if info.favourite() { "* " } else { "" },
+        info.name,
+        width = catalog::ID_LEN
+    )];
+    if !info.tags().is_empty() {
+        parts.push(
+            info.tags()
+                .iter()
+                .map(|x| format!("#{x}"))
+                .collect::<Vec<_>>()
+                .join(" "),
+        );
+    }
+    parts.extend(info.region());
+    if latency {
+        parts.push(match info.latency {
+            Some(x) => format!("{}ms", x.as_millis()),
+            None => "unreachable".to_string(),
+        });
+    }
+    if let Some(x) = info.last_used {
+        parts.push(format!("used {}", format_time(x)));
+    }
+    parts.join("  ")
+}
+
+fn print_usage(label: &str, bytes: &usage::Bytes) {
+    println!(
+        "  {label:12}up {:>10}  down {:>10}",
+        clashapi::format_bytes(bytes.up),
+        clashapi::format_bytes(bytes.down)
+    );
+}
+
+fn print_route_rules(rules: &[RouteRule], prefix: &str) {
+    for (i, rule) in rules.iter().enumerate() {
+        let number = format!("{prefix}{}", i + 1);
+        println!("[{number:>2}]: {rule}");
+        if let RouteRule::Logical(x) = rule {
+            print_route_rules(&x.rules, &format!("{number}."));
+        }
+    }
+}
+
+fn open_in_editor(path: &Path) -> Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+
+    let status = std::process::Command::new(&editor)
+        .arg(path)
+        .status()
+        .with_context(|| format!("Cant run editor '{editor}'"))?;
+
+    if !status.success() {
+        return Err(anyhow!("Editor '{editor}' exited with {status}"));
+    }
+    Ok(())
+}
+
+/// Edits handler config in temp file until it is valid or discarded, returns whether it was saved
+fn edit_in_editor(manager: &mut App, yaml: bool) -> Result<bool> {
+    let tree = manager.handler_ref().to_tree()?;
+    let (original, extension) = match yaml {
+        true => (yaml_serde::to_string(&tree)?, "yaml"),
+        false => (serde_json::to_string_pretty(&tree)? + "\n", "json"),
+    };
+    // Config has credentials, it is edited in the private runtime dir
+    let path = render::edit_dir()?.join(format!(
+        "edit-{}-{}.{extension}",
+        manager.get_inf_ref().get_name(),
+        std::process::id()
+    ));
+    render::create_private(&path)?.write_all(original.as_bytes())?;
+
+    let res = edit_loop(manager, &path, &original, yaml);
+    if res.as_ref().is_ok_and(|saved| !saved) {
+        manager.handler_mut().set_tree(tree)?;
+    }
+    let _ = fs::remove_file(&path);
+    res
+}
+
+fn edit_loop(manager: &mut App, path: &Path, original: &str, yaml: bool) -> Result<bool> {
+    loop {
+        open_in_editor(path)?;
+        let content = fs::read_to_string(path)?;
+        if content == original {
+            println!("No changes");
+            return Ok(false);
+        }
+
+        let tree: Result<Value> = match yaml {
+            true => yaml_serde::from_str(&content).map_err(|e| anyhow!(e)),
+            false => serde_json::from_str(&content).map_err(|e| anyhow!(e)),
+        };
+        let res = tree
+            .and_then(|x| manager.handler_mut().set_tree(x).map(|_| ()))
+            .and_then(|_| manager.handler_ref().validate());
+        match res {
+            Ok(_) => {
+                manager.save()?;
+                return Ok(true);
+            }
+            Err(e) => {
+                eprintln!("Config is not valid:\n{e}");
+                if !ask_edit_again()? {
+                    println!("Changes discarded");
+                    return Ok(false);
+                }
+            }
+        }
+    }
+}
+
+/// Edits preset in temp file and writes it to the presets dir only when it is valid
+fn edit_preset(manager: &App, name: &str) -> Result<()> {
+    let presets = manager.presets();
+    let target = presets.file_path(name);
+    let original = presets.source(name)?;
+    let extension = target
+        .extension()
+        .and_then(|x| x.to_str())
+        .unwrap_or("toml");
+    let path =
+        render::edit_dir()?.join(format!("preset-{name}-{}.{extension}", std::process::id()));
+    render::create_private(&path)?.write_all(original.as_bytes())?;
+
+    let res = edit_preset_loop(&path, &target, &original);
+    let _ = fs::remove_file(&path);
+    res
+}
+
+fn edit_preset_loop(path: &Path, target: &Path, original: &str) -> Result<()> {
+    loop {
+        open_in_editor(path)?;
+        let content = fs::read_to_string(path)?;
+        if content == original {
+            println!("No changes");
+            return Ok(());
+        }
+
+        match Preset::parse(&content, path) {
+            Ok(_) => {
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(target, content)?;
+                return Ok(());
+            }
+            Err(e) => {
+                eprintln!("Preset is not valid:\n{e}");
+                if !ask_edit_again()? {
+                    println!("Changes discarded");
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+fn ask_edit_again() -> Result<bool> {
+    if !io::stdin().is_terminal() {
+        return Ok(false);
+    }
+    loop {
+        eprint!("Edit again? [Y/n] ");
+        io::stderr().flush()?;
+        let mut answer = String::new();
+        io::stdin().lock().read_line(&mut answer)?;
+        match answer.trim().to_lowercase().as_str() {
+            "" | "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => {}
+        }
+    }
+}
+
 fn setup_signal_handler() {
     let shutdown = SHUTDOWN
         .get_or_init(|| Arc::new(AtomicBool::new(false)))
@@ -32,6 +318,10 @@ fn setup_signal_handler() {
 #[command(version, about, long_about = None)]
 #[command(propagate_version = true)]
 pub struct Cli {
+    /// Output format, json and yaml have stable schemas for scripts
+    #[arg(long, short, global = true, value_enum, default_value_t)]
+    output: OutputFormat,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -44,114 +334,885 @@ enum Commands {
         #[arg(short, long)]
         url: Option<String>,
 
-        /// Set dns servers
+        /// Add dns servers [udp|tcp|tls|quic|https|h3://<addr>[?detour=&resolver=&tag=], dhcp://<iface>, fakeip, hosts:<path>]
+        #[arg(long)]
+        dns: Option<Vec<String>>,
+
+        /// Set route rules [<action> if <condition>, e.g. `proxy if domain_suffix in [a.com, b.com] and port 443`, or <action>:<type>:<value>]
+        #[arg(long)]
+        route: Option<Vec<String>>,
+
+        /// Manage route rules [<action>:<value>:<value>]
+        #[arg(long)]
+        manage: Option<Vec<String>>,
+
+        /// Set as tunnel (also name as VPN), same as `--preset tun`
+        #[arg(short, long)]
+        tun: bool,
+
+        #[command(flatten)]
+        fakeip: FakeIpArgs,
+
+        /// Preset with dns, inbounds and route skeleton [default: default]
+        #[arg(short, long, conflicts_with = "tun")]
+        preset: Option<String>,
+
+        /// Replace file if exist
+        #[arg(short, long)]
+        rewrite: bool,
+
+        /// Set a custom name of config
         #[arg(long)]
+        name: Option<String>,
+
+        /// Subscription the config came from, configs are grouped by it in the TUI
+        #[arg(long)]
+        subscription: Option<String>,
+    },
+    /// Manage settings of config
+    Manage {
+        /// Config endentifier
+        value: Option<ConfigEn>,
+
+        /// Add dns servers [udp|tcp|tls|quic|https|h3://<addr>[?detour=&resolver=&tag=], dhcp://<iface>, fakeip, hosts:<path>]
+        #[arg(long, short)]
         dns: Option<Vec<String>>,
 
-        /// Set route rules [<action>:<type>:<value>]
-        #[arg(long)]
-        route: Option<Vec<String>>,
+        /// Set route rules [<action> if <condition>, e.g. `proxy if domain_suffix in [a.com, b.com] and port 443`, or <action>:<type>:<value>]
+        #[arg(long, short)]
+        route: Option<Vec<String>>,
+
+        /// Manage route rules [<action>:<value>:<value>]
+        #[arg(long, short)]
+        manage: Option<Vec<String>>,
+
+        /// Set a custom name of config
+        #[arg(long)]
+        name: Option<String>,
+
+        #[command(flatten)]
+        fakeip: FakeIpArgs,
+
+        #[arg(long)]
+        print: bool,
+    },
+
+    /// Open config in $EDITOR, changes are validated before saving
+    Edit {
+        /// Config endentifier
+        value: Option<ConfigEn>,
+
+        /// Edit as yaml instead of json
+        #[arg(long)]
+        yaml: bool,
+    },
+
+    /// Tune tun inbound of config
+    Tun {
+        #[command(subcommand)]
+        command: TunCommands,
+    },
+
+    /// Route applications directly or through proxy
+    Apps {
+        #[command(subcommand)]
+        command: AppsCommands,
+    },
+
+    /// Manage dns rules
+    Dns {
+        #[command(subcommand)]
+        command: DnsCommands,
+    },
+
+    /// Manage route rules
+    Route {
+        #[command(subcommand)]
+        command: RouteCommands,
+    },
+
+    /// Manage rule sets used by route and dns rules
+    Ruleset {
+        #[command(subcommand)]
+        command: RulesetCommands,
+    },
+
+    /// Inspect and control running config through clash api
+    Clash {
+        #[command(subcommand)]
+        command: ClashCommands,
+    },
+
+    /// Print traffic recorded by relationd
+    Usage {
+        /// Config endentifier, all configs when omitted
+        #[arg(short, long)]
+        config: Option<ConfigEn>,
+
+        /// Print days of the current month and its quota
+        #[arg(long)]
+        month: bool,
+    },
+
+    /// Manage monthly traffic quotas of configs
+    Quota {
+        #[command(subcommand)]
+        command: QuotaCommands,
+    },
+
+    /// Encrypt credentials of saved configs
+    Secrets {
+        #[command(subcommand)]
+        command: SecretsCommands,
+    },
+
+    /// Dispay list of possible configs
+    List {
+        /// Only configs with the tag
+        #[arg(long)]
+        tag: Option<String>,
+
+        /// Order of configs [name, latency, last-used], favourites go first
+        #[arg(long)]
+        sort: Option<ListSort>,
+
+        /// Only configs with the text in name, tags, region, subscription or notes
+        #[arg(long)]
+        filter: Option<String>,
+    },
+
+    /// Add user tags to config
+    Tag {
+        /// Config endentifier
+        value: ConfigEn,
+
+        #[arg(required = true)]
+        tags: Vec<String>,
+
+        /// Remove the tags instead
+        #[arg(short, long)]
+        remove: bool,
+    },
+
+    /// Mark config as favourite, favourites are listed first
+    Favourite {
+        /// Config endentifier
+        value: ConfigEn,
+
+        /// Unmark config
+        #[arg(long)]
+        off: bool,
+    },
+
+    /// Print saved revisions of config
+    History {
+        /// Config endentifier
+        value: Option<ConfigEn>,
+    },
+
+    /// Print changes of route, dns and outbounds between revisions
+    Diff {
+        /// Config endentifier
+        value: ConfigEn,
+
+        /// Older revision [default: the one before the latest]
+        from: Option<u64>,
+
+        /// Newer revision [default: current config]
+        to: Option<u64>,
+    },
+
+    /// Restore the revision before the last change, repeat to go further back
+    Undo {
+        /// Config endentifier
+        value: Option<ConfigEn>,
+    },
+
+    /// Restore revision as a new revision
+    Revert {
+        /// Config endentifier
+        value: ConfigEn,
+        rev: u64,
+    },
+
+    /// Pack configs, settings, presets, history, usage and caches into one file
+    Backup { file: String },
+
+    /// Unpack backup, clashing configs are added as `[n] name`
+    Restore {
+        file: String,
+
+        /// Remove local data instead of merging
+        #[arg(long)]
+        replace: bool,
+    },
+
+    /// Get or change any config field by dotted path, like `outbounds.0.tls.server_name`,
+    /// keys with dots are quoted: `dns.servers.0.predefined."example.com"`
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+
+    /// Manage presets used by `add`
+    Preset {
+        #[command(subcommand)]
+        command: PresetCommands,
+    },
+
+    /// Get daemon status
+    #[cfg(feature = "daemon")]
+    Status,
+
+    /// Run terminal user interface
+    #[cfg(feature = "tui")]
+    Tui,
+
+    /// Remove config
+    Remove {
+        /// Config endentifier
+        value: Option<ConfigEn>,
+    },
+
+    /// Run application with selected config
+    Run {
+        /// Config endentifier
+        value: Option<ConfigEn>,
+
+        #[arg(long, short)]
+        unable_system_proxy: bool,
+
+        #[cfg(feature = "daemon")]
+        #[arg(long, short)]
+        quiet: bool,
+    },
+
+    ///Stop proxy
+    #[cfg(feature = "daemon")]
+    Stop,
+
+    ///Stop and shutdown proxy
+    #[cfg(feature = "daemon")]
+    Quit,
+}
+
+#[derive(Args)]
+struct FakeIpArgs {
+    /// Answer A/AAAA queries with fake addresses, needs tun inbound
+    #[arg(long)]
+    fakeip: bool,
+
+    /// Fakeip ipv4 range [default: 198.18.0.0/15]
+    #[arg(long, requires = "fakeip")]
+    fakeip_inet4: Option<String>,
+
+    /// Fakeip ipv6 range [default: fc00::/18]
+    #[arg(long, requires = "fakeip")]
+    fakeip_inet6: Option<String>,
+}
+
+impl FakeIpArgs {
+    fn apply(&self, manager: &mut App) -> Result<()> {
+        if self.fakeip {
+            manager
+                .handler_mut()
+                .enable_fakeip(self.fakeip_inet4.as_deref(), self.fakeip_inet6.as_deref())?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Print field, whole config without path
+    Get {
+        /// Config endentifier
+        config: ConfigEn,
+        path: Option<String>,
+    },
+
+    /// Set field, value is json or plain string
+    Set {
+        /// Config endentifier
+        config: ConfigEn,
+        path: String,
+        value: String,
+    },
+
+    /// Remove field or list item
+    Unset {
+        /// Config endentifier
+        config: ConfigEn,
+        path: String,
+    },
+
+    /// Add value to list
+    Append {
+        /// Config endentifier
+        config: ConfigEn,
+        path: String,
+        value: String,
+    },
+
+    /// Remove value from list
+    Remove {
+        /// Config endentifier
+        config: ConfigEn,
+        path: String,
+        value: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum PresetCommands {
+    /// Display builtin and user presets
+    List,
+
+    /// Print preset content
+    Show {
+        /// Preset name
+        name: String,
+    },
+
+    /// Open preset in $EDITOR, it is saved to data dir when valid
+    Edit {
+        /// Preset name
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum TunCommands {
+    /// Print tun settings
+    Show {
+        /// Config endentifier
+        value: Option<ConfigEn>,
+    },
+
+    /// Set tun settings, lists replace previous values
+    Set(Box<TunSetArgs>),
+
+    /// Remove tun setting
+    Unset {
+        /// Field name, for example `route_exclude_address`
+        field: TunField,
+
+        /// Config endentifier [default: current config]
+        #[arg(short, long)]
+        config: Option<ConfigEn>,
+    },
+}
+
+#[derive(Args)]
+struct TunSetArgs {
+    /// Config endentifier
+    value: Option<ConfigEn>,
+
+    /// Network stack [system, gvisor, mixed]
+    #[arg(long)]
+    stack: Option<String>,
+
+    #[arg(long)]
+    mtu: Option<String>,
+
+    /// Tun interface addresses in CIDR form
+    #[arg(long)]
+    address: Option<Vec<String>>,
+
+    /// Tun interface name
+    #[arg(long)]
+    interface_name: Option<String>,
+
+    #[arg(long)]
+    auto_route: Option<String>,
+
+    #[arg(long)]
+    strict_route: Option<String>,
+
+    /// Only route traffic of these uids
+    #[arg(long)]
+    include_uid: Option<Vec<String>>,
+
+    /// Never route traffic of these uids
+    #[arg(long)]
+    exclude_uid: Option<Vec<String>>,
+
+    /// Route only these CIDRs into tun
+    #[arg(long)]
+    route_address: Option<Vec<String>>,
+
+    /// Do not route these CIDRs into tun
+    #[arg(long)]
+    route_exclude_address: Option<Vec<String>>,
+}
+
+#[derive(Subcommand)]
+enum AppsCommands {
+    /// Print app rules and tun uid filters
+    List {
+        /// Config endentifier
+        value: Option<ConfigEn>,
+    },
+
+    /// Send application traffic to direct outbound
+    Bypass(AppArgs),
+
+    /// Send application traffic to proxy outbound
+    Proxy(AppArgs),
+
+    /// Remove app rule
+    Remove(AppArgs),
+}
+
+#[derive(Subcommand)]
+enum DnsCommands {
+    /// Print dns servers and rules
+    List {
+        /// Config endentifier
+        value: Option<ConfigEn>,
+    },
+
+    /// Add dns rule
+    Add(Box<DnsRuleArgs>),
+
+    /// Remove dns rule by number
+    Remove {
+        /// Rule number from `dns list`
+        index: usize,
+
+        /// Config endentifier [default: current config]
+        #[arg(short, long)]
+        config: Option<ConfigEn>,
+    },
+
+    /// Move dns rule
+    Move {
+        /// Rule number from `dns list`
+        from: usize,
+
+        /// New rule number
+        to: usize,
+
+        /// Config endentifier [default: current config]
+        #[arg(short, long)]
+        config: Option<ConfigEn>,
+    },
+}
+
+#[derive(Subcommand)]
+enum RouteCommands {
+    /// Print route rules, nested rules of logical rules too
+    List {
+        /// Config endentifier
+        value: Option<ConfigEn>,
+    },
+
+    /// Add route rule, e.g. `proxy if domain_suffix in [a.com, b.com] or port 443`
+    Add {
+        /// Rule expression
+        rule: String,
+
+        /// Config endentifier [default: current config]
+        #[arg(short, long)]
+        config: Option<ConfigEn>,
+    },
+
+    /// Remove rule or nested rule
+    Remove {
+        /// Rule number from `route list`, nested rules as `2.1`
+        rule: String,
+
+        /// Config endentifier [default: current config]
+        #[arg(short, long)]
+        config: Option<ConfigEn>,
+    },
+
+    /// Join rules with the same action into one logical rule
+    Group {
+        /// Rule numbers from `route list`
+        #[arg(required = true, num_args = 1..)]
+        rules: Vec<usize>,
+
+        /// Logical mode [and, or]
+        #[arg(short, long, default_value = "and")]
+        mode: String,
+
+        /// Invert logical rule
+        #[arg(short, long)]
+        invert: bool,
+
+        /// Config endentifier [default: current config]
+        #[arg(short, long)]
+        config: Option<ConfigEn>,
+    },
+
+    /// Move rule into logical rule, its action is dropped
+    Nest {
+        /// Rule number from `route list`
+        rule: usize,
+
+        /// Logical rule, nested ones as `2.1`
+        into: String,
+
+        /// Config endentifier [default: current config]
+        #[arg(short, long)]
+        config: Option<ConfigEn>,
+    },
+
+    /// Move nested rule out of logical rule, it gets action of the top level rule
+    Extract {
+        /// Nested rule as `2.1`
+        rule: String,
+
+        /// Config endentifier [default: current config]
+        #[arg(short, long)]
+        config: Option<ConfigEn>,
+    },
+
+    /// Toggle invert of rule or nested rule
+    Invert {
+        /// Rule number from `route list`, nested rules as `2.1`
+        rule: String,
+
+        /// Config endentifier [default: current config]
+        #[arg(short, long)]
+        config: Option<ConfigEn>,
+    },
+
+    /// Set mode of logical rule
+    Mode {
+        /// Rule number from `route list`, nested rules as `2.1`
+        rule: String,
+
+        /// Logical mode [and, or]
+        mode: String,
+
+        /// Config endentifier [default: current config]
+        #[arg(short, long)]
+        config: Option<ConfigEn>,
+    },
+
+    /// Show which rule a connection would hit
+    Explain {
+        /// Destination domain or ip
+        #[arg(short, long)]
+        domain: Option<String>,
+
+        /// Destination ip, for example resolved address of the domain
+        #[arg(long)]
+        ip: Option<IpAddr>,
+
+        /// Destination port
+        #[arg(short, long)]
+        port: Option<u16>,
+
+        /// Network [tcp, udp]
+        #[arg(short, long, value_parser = ["tcp", "udp"])]
+        network: Option<String>,
+
+        /// Process name or full path
+        #[arg(long)]
+        process: Option<String>,
+
+        /// Sniffed protocol, for example `tls` or `dns`
+        #[arg(long)]
+        protocol: Option<String>,
+
+        /// Inbound tag
+        #[arg(long)]
+        inbound: Option<String>,
+
+        /// Source ip
+        #[arg(long)]
+        source_ip: Option<IpAddr>,
+
+        /// Source port
+        #[arg(long)]
+        source_port: Option<u16>,
+
+        /// Config endentifier [default: current config]
+        #[arg(short, long)]
+        config: Option<ConfigEn>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ClashCommands {
+    /// Print active connections
+    Connections,
+
+    /// Close connection by id
+    Close {
+        #[arg(required_unless_present = "all")]
+        id: Option<String>,
+
+        /// Close all connections
+        #[arg(long, short, conflicts_with = "id")]
+        all: bool,
+    },
+
+    /// Print current upload and download rates
+    Traffic,
+
+    /// Print selectors and their members
+    Proxies,
+
+    /// Switch selector to another member
+    Select { selector: String, member: String },
+
+    /// Print or change clash mode [rule, global, direct]
+    Mode { mode: Option<String> },
+}
+
+#[derive(Subcommand)]
+enum QuotaCommands {
+    /// Print quotas
+    List,
+
+    /// Set monthly quota of config
+    Set {
+        /// Config endentifier
+        config: ConfigEn,
+
+        /// Monthly limit like `50GB` or `500MiB`
+        limit: String,
+
+        /// What relationd does when quota is passed [warn, stop, switch]
+        #[arg(long, short)]
+        action: Option<String>,
+
+        /// Config started instead for `switch`
+        #[arg(long)]
+        to: Option<ConfigEn>,
+    },
+
+    /// Remove quota of config
+    Remove {
+        /// Config endentifier
+        config: ConfigEn,
+    },
+}
+
+#[derive(Subcommand)]
+enum SecretsCommands {
+    /// Print whether credentials are encrypted
+    Status,
+
+    /// Encrypt credentials of configs and their history with a passphrase
+    Enable {
+        /// Keep random key in system keyring instead of asking passphrase
+        #[arg(long)]
+        keyring: bool,
+    },
+
+    /// Store credentials in plain text again
+    Disable,
+}
+
+#[derive(Subcommand)]
+enum RulesetCommands {
+    /// Print rule sets
+    List {
+        /// Config endentifier
+        value: Option<ConfigEn>,
+    },
+
+    /// Add local, remote or inline rule set
+    Add {
+        /// Rule set tag used by `rule_set` of route and dns rules
+        tag: String,
+
+        /// Remote rule set url
+        #[arg(long, group = "source")]
+        url: Option<String>,
+
+        /// Local rule set file
+        #[arg(long, group = "source")]
+        path: Option<String>,
+
+        /// Headless rules in json, for example `{"domain_suffix":["ads.com"]}`
+        #[arg(long, group = "source")]
+        inline: Option<String>,
+
+        /// Rule set format [binary, source], detected by extension or file content by default
+        #[arg(long, conflicts_with = "inline")]
+        format: Option<String>,
+
+        /// Update interval of remote rule set, for example `1d`
+        #[arg(long, requires = "url")]
+        update_interval: Option<String>,
+
+        /// Outbound tag used to download remote rule set
+        #[arg(long, requires = "url")]
+        download_detour: Option<String>,
+
+        /// Config endentifier [default: current config]
+        #[arg(short, long)]
+        config: Option<ConfigEn>,
+    },
+
+    /// Remove rule set which is not used by rules
+    Remove {
+        tag: String,
+
+        /// Config endentifier [default: current config]
+        #[arg(short, long)]
+        config: Option<ConfigEn>,
+    },
+}
+
+#[derive(Args)]
+struct DnsRuleArgs {
+    /// Rule in form `<key>=<value>[,<value>] ... -> <action>`,
+    /// for example `domain_suffix=corp.local -> dns-corp`
+    #[arg(conflicts_with_all = ["domain", "suffix", "keyword", "rule_set", "query_type", "outbound"])]
+    rule: Option<String>,
 
-        /// Manage route rules [<action>:<value>:<value>]
-        #[arg(long)]
-        manage: Option<Vec<String>>,
+    #[arg(long)]
+    domain: Vec<String>,
 
-        /// Set as tunnel (also name as VPN)
-        #[arg(short, long)]
-        tun: bool,
+    /// Domain suffix, `corp.local` also matches `a.corp.local`
+    #[arg(long)]
+    suffix: Vec<String>,
 
-        /// Replace file if exist
-        #[arg(short, long)]
-        rewrite: bool,
+    #[arg(long)]
+    keyword: Vec<String>,
 
-        /// Set a custom name of config
-        #[arg(long)]
-        name: Option<String>,
-    },
-    /// Manage settings of config
-    Manage {
-        /// Config endentifier
-        value: Option<ConfigEn>,
+    /// Rule set tag
+    #[arg(long)]
+    rule_set: Vec<String>,
 
-        /// Set dns servers
-        #[arg(long, short)]
-        dns: Option<Vec<String>>,
+    /// Query type name or number, for example `A` or `28`
+    #[arg(long)]
+    query_type: Vec<String>,
 
-        /// Set route rules [<action>:<type>:<value>]
-        #[arg(long, short)]
-        route: Option<Vec<String>>,
+    /// Outbound tag the query comes from
+    #[arg(long)]
+    outbound: Vec<String>,
 
-        /// Manage route rules [<action>:<value>:<value>]
-        #[arg(long, short)]
-        manage: Option<Vec<String>>,
+    /// Resolve matched queries with dns server tag
+    #[arg(long, conflicts_with_all = ["reject", "predefined"])]
+    server: Option<String>,
 
-        /// Set a custom name of config
-        #[arg(long)]
-        name: Option<String>,
+    /// Reject matched queries [default, drop]
+    #[arg(long, num_args = 0..=1, default_missing_value = "default", conflicts_with = "predefined")]
+    reject: Option<String>,
 
-        #[arg(long)]
-        print: bool,
-    },
+    /// Answer matched queries with rcode, for example `NXDOMAIN`
+    #[arg(long)]
+    predefined: Option<String>,
 
-    /// Dispay list of possible configs
-    List,
+    /// Answer record of predefined action, for example `example.com. IN A 10.0.0.1`
+    #[arg(long, requires = "predefined")]
+    answer: Vec<String>,
 
-    /// Get daemon status
-    #[cfg(feature = "daemon")]
-    Status,
+    /// Config endentifier [default: current config]
+    #[arg(short, long)]
+    config: Option<ConfigEn>,
+}
 
-    /// Run terminal user interface
-    #[cfg(feature = "tui")]
-    Tui,
+impl DnsRuleArgs {
+    fn rule(&self) -> Result<DnsDefaultRule> {
+        if let Some(x) = &self.rule {
+            if self.server.is_some() || self.reject.is_some() || self.predefined.is_some() {
+                return Err(anyhow!("Action is already set by the rule"));
+            }
+            return DnsDefaultRule::from_spec(x);
+        }
 
-    /// Remove config
-    Remove {
-        /// Config endentifier
-        value: Option<ConfigEn>,
-    },
+        let conditions = [
+            ("domain", &self.domain),
+            ("domain_suffix", &self.suffix),
+            ("domain_keyword", &self.keyword),
+            ("rule_set", &self.rule_set),
+            ("query_type", &self.query_type),
+            ("outbound", &self.outbound),
+        ];
+        let mut rule = DnsDefaultRule::new();
+        for (key, values) in conditions {
+            for value in values {
+                rule = rule.add_condition(key, value)?;
+            }
+        }
 
-    /// Run application with selected config
-    Run {
-        /// Config endentifier
-        value: Option<ConfigEn>,
+        let action = match (&self.server, &self.reject, &self.predefined) {
+            (Some(server), _, _) => DnsRuleAction::Route(DnsRouteAction::with_server(server)),
+            (_, Some(method), _) => {
+                DnsRuleAction::Reject(DnsRejectAction::new().set_method(method))
+            }
+            (_, _, Some(rcode)) => {
+                let mut action = DnsPredefinedAction::new().set_rcode(rcode);
+                for answer in &self.answer {
+                    action = action.add_answer(answer);
+                }
+                DnsRuleAction::Predefined(action)
+            }
+            _ => {
+                return Err(anyhow!(
+                    "Use --server, --reject or --predefined to set action"
+                ));
+            }
+        };
+        Ok(rule.set_action(action))
+    }
+}
 
-        #[arg(long, short)]
-        unable_system_proxy: bool,
+#[derive(Args)]
+struct AppArgs {
+    /// Process name or absolute path of executable
+    #[arg(required_unless_present = "uid")]
+    app: Option<String>,
 
-        #[cfg(feature = "daemon")]
-        #[arg(long, short)]
-        quiet: bool,
-    },
+    /// Match by user id instead of process
+    #[arg(long, conflicts_with = "app")]
+    uid: Option<u32>,
 
-    ///Stop proxy
-    #[cfg(feature = "daemon")]
-    Stop,
+    /// Config endentifier [default: current config]
+    #[arg(short, long)]
+    config: Option<ConfigEn>,
+}
 
-    ///Stop and shutdown proxy
-    #[cfg(feature = "daemon")]
-    Quit,
+impl AppArgs {
+    fn target(&self) -> Result<AppTarget> {
+        match (&self.app, self.uid) {
+            (_, Some(uid)) => Ok(AppTarget::Uid(uid)),
+            (Some(app), None) => app.parse(),
+            (None, None) => Err(anyhow!("Application or uid is required")),
+        }
+    }
 }
 
+/// Config name, id, unique prefix of id or name or 1-based number from `list`, in this order
 #[derive(Debug, Clone)]
-enum ConfigEn {
-    Number(u16),
-    Text(String),
-}
+struct ConfigEn(String);
 
 impl FromStr for ConfigEn {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if let Ok(n) = s.parse::<u16>() {
-            Ok(ConfigEn::Number(n))
-        } else {
-            Ok(ConfigEn::Text(s.to_string()))
-        }
+        Ok(ConfigEn(s.to_string()))
     }
 }
 
 impl Cli {
     pub fn run(&mut self, manager: &mut App) -> Result<()> {
+        let res = self.run_command(manager);
+        for line in manager.handler_mut().take_geo_report() {
+            match self.output.structured() {
+                true => eprintln!("migrated {line}"),
+                false => println!("migrated {line}"),
+            }
+        }
+        res
+    }
+
+    /// Prints error body for json and yaml, table output leaves error to the caller
+    pub fn print_error(&self, error: &anyhow::Error) -> bool {
+        self.output.structured() && self.output.emit(&ErrorOutput::from(error)).is_ok()
+    }
+
+    fn run_command(&mut self, manager: &mut App) -> Result<()> {
+        let output = self.output;
         match &self.command {
             Commands::Add {
                 url,
@@ -159,15 +1220,19 @@ impl Cli {
                 route,
                 manage,
                 tun,
+                fakeip,
+                preset,
                 rewrite,
                 name,
+                subscription,
             } => {
                 if let Some(value) = url {
-                    if !tun {
-                        manager.handler_mut().default();
-                    } else {
-                        manager.handler_mut().default_tun();
-                    }
+                    let preset = match (tun, preset) {
+                        (true, _) => "tun",
+                        (false, Some(x)) => x.as_str(),
+                        (false, None) => "default",
+                    };
+                    manager.apply_preset(preset)?;
                     manager.handler_mut().set_outbound_from_url(value)?;
                     if let Some(value) = dns {
                         manager.handler_mut().add_dns_servers(value)?;
@@ -178,6 +1243,10 @@ impl Cli {
                     if let Some(value) = manage {
                         manager.handler_mut().manage(value)?;
                     }
+                    fakeip.apply(manager)?;
+                    if let Some(x) = subscription {
+                        manager.handler_mut().meta_mut().subscription = Some(x.clone());
+                    }
                     if *rewrite
                         && let Some(value) = name
                         && manager.exist_config(value) > 0
@@ -197,23 +1266,20 @@ impl Cli {
                 route,
                 manage,
                 name,
+                fakeip,
                 print,
             } => {
-                let rr = match value {
-                    Some(x) => match x {
-                        ConfigEn::Text(t) => manager.set_handler_config_by_name(&t),
-                        ConfigEn::Number(n) => {
-                            manager.set_handler_config_by_number(*n as usize - 1)
-                        }
-                    },
-                    None => manager.set_handler_config_by_current(),
-                };
-
-                if let Err(x) = rr {
-                    return Err(anyhow!(x));
-                }
+                select_config(manager, value)?;
 
-                if *print {
+                if *print && output.structured() {
+                    output.emit(&ConfigDump {
+                        name: manager.get_inf_ref().get_name(),
+                        dns: manager.handler_ref().get_dns_ref(),
+                        inbounds: manager.handler_ref().get_inbound_ref(),
+                        outbounds: manager.handler_ref().get_outbound_ref(),
+                        route: manager.handler_ref().get_route_ref(),
+                    })?;
+                } else if *print {
                     println!(
                         "DNS:\n{}",
                         yaml_serde::to_string(manager.handler_ref().get_dns_ref())?
@@ -241,30 +1307,948 @@ impl Cli {
                 if let Some(value) = manage {
                     manager.handler_mut().manage(value)?;
                 }
+                fakeip.apply(manager)?;
                 if let Some(value) = name {
                     manager.rename_config(value.clone())?;
                 }
 
                 manager.save()?;
             }
-            Commands::List => {
-                if manager.get_list().is_empty() {
-                    println!("There are no configurations");
-                } else {
-                    for i in manager.get_list().iter().enumerate() {
-                        println!("[{:2}]: {}", i.0 + 1, i.1);
+            Commands::List { tag, sort, filter } => {
+                let usage = UsageStore::load(&UsageStore::path(&manager.get_data_path()))?;
+                let mut configs =
+                    catalog::load(&manager.get_configs_path(), &manager.get_list(), &usage);
+                configs.retain(|x| {
+                    tag.as_ref().is_none_or(|t| x.has_tag(t))
+                        && filter.as_ref().is_none_or(|t| x.matches(t))
+                });
+                match sort {
+                    Some(x) => {
+                        if *x == ListSort::Latency {
+                            catalog::probe(&mut configs);
+                        }
+                        catalog::sort(&mut configs, *x);
+                    }
+                    None => configs.sort_by_key(|x| !x.favourite()),
+                }
+
+                if output.structured() {
+                    let entries: Vec<ConfigEntry> = configs
+                        .iter()
+                        .map(|info| {
+                            let mut config = Configurator::new();
+                            let loaded = config
+                                .load_from_file(
+                                    manager
+                                        .get_configs_path()
+                                        .join(format!("{}.json", info.name)),
+                                )
+                                .is_ok();
+                            ConfigEntry::new(info, loaded.then_some(&config))
+                        })
+                        .collect();
+                    return output.emit(&entries);
+                }
+                if configs.is_empty() {
+                    match manager.get_list().is_empty() {
+                        true => println!("There are no configurations"),
+                        false => println!("No configurations match"),
+                    }
+                }
+                for info in &configs {
+                    println!("{}", list_line(info, *sort == Some(ListSort::Latency)));
+                }
+            }
+            Commands::Tag {
+                value,
+                tags,
+                remove,
+            } => {
+                select_config(manager, &Some(value.clone()))?;
+                let meta = manager.handler_mut().meta_mut();
+                let list = meta.tags.get_or_insert_default();
+                for tag in tags {
+                    if *remove {
+                        list.retain(|x| !x.eq_ignore_ascii_case(tag));
+                    } else if !list.iter().any(|x| x.eq_ignore_ascii_case(tag)) {
+                        list.push(tag.clone());
+                    }
+                }
+                if list.is_empty() {
+                    meta.tags = None;
+                }
+                manager.save()?;
+            }
+            Commands::Favourite { value, off } => {
+                select_config(manager, &Some(value.clone()))?;
+                manager.handler_mut().meta_mut().favourite = (!off).then_some(true);
+                manager.save()?;
+            }
+            Commands::Tun { command } => match command {
+                TunCommands::Show { value } => {
+                    select_config(manager, value)?;
+                    let tun = manager
+                        .handler_ref()
+                        .get_tun_ref()
+                        .context("Config has no tun inbound")?;
+                    if output.structured() {
+                        return output.emit(tun);
+                    }
+                    for field in TunField::ALL {
+                        println!(
+                            "{:22}{}",
+                            field.name(),
+                            tun.get_field(*field).unwrap_or_default()
+                        );
+                    }
+                }
+                TunCommands::Set(args) => {
+                    let TunSetArgs {
+                        value,
+                        stack,
+                        mtu,
+                        address,
+                        interface_name,
+                        auto_route,
+                        strict_route,
+                        include_uid,
+                        exclude_uid,
+                        route_address,
+                        route_exclude_address,
+                    } = args.as_ref();
+                    select_config(manager, value)?;
+                    let fields = [
+                        (TunField::Stack, stack.clone()),
+                        (TunField::Mtu, mtu.clone()),
+                        (TunField::Address, address.as_ref().map(|x| x.join(","))),
+                        (TunField::InterfaceName, interface_name.clone()),
+                        (TunField::AutoRoute, auto_route.clone()),
+                        (TunField::StrictRoute, strict_route.clone()),
+                        (
+                            TunField::IncludeUid,
+                            include_uid.as_ref().map(|x| x.join(",")),
+                        ),
+                        (
+                            TunField::ExcludeUid,
+                            exclude_uid.as_ref().map(|x| x.join(",")),
+                        ),
+                        (
+                            TunField::RouteAddress,
+                            route_address.as_ref().map(|x| x.join(",")),
+                        ),
+                        (
+                            TunField::RouteExcludeAddress,
+                            route_exclude_address.as_ref().map(|x| x.join(",")),
+                        ),
+                    ];
+
+                    let tun = manager.handler_mut().get_tun_mut()?;
+                    for (field, value) in fields {
+                        if let Some(x) = value {
+                            tun.set_field(field, &x)
+                                .with_context(|| format!("Incorrect {}", field.name()))?;
+                        }
+                    }
+                    manager.save()?;
+                }
+                TunCommands::Unset { field, config } => {
+                    select_config(manager, config)?;
+                    manager.handler_mut().get_tun_mut()?.unset_field(*field)?;
+                    manager.save()?;
+                }
+            },
+            Commands::Apps { command } => match command {
+                AppsCommands::List { value } => {
+                    select_config(manager, value)?;
+                    let rules = manager.handler_ref().get_app_rules();
+                    if output.structured() {
+                        let tun = manager.handler_ref().get_tun_ref();
+                        return output.emit(&AppsDump {
+                            rules: AppEntry::list(&rules),
+                            include_uid: tun.and_then(|x| x.include_uid.as_deref()),
+                            exclude_uid: tun.and_then(|x| x.exclude_uid.as_deref()),
+                        });
+                    }
+                    if rules.is_empty() {
+                        println!("There are no app rules");
+                    }
+                    for (i, rule) in rules.iter().enumerate() {
+                        println!("[{:2}]: {}", i + 1, rule);
+                    }
+                    if let Some(tun) = manager.handler_ref().get_tun_ref() {
+                        for field in [TunField::IncludeUid, TunField::ExcludeUid] {
+                            if let Some(x) = tun.get_field(field) {
+                                println!("tun {}: {}", field.name(), x);
+                            }
+                        }
+                    }
+                }
+                AppsCommands::Bypass(args) | AppsCommands::Proxy(args) => {
+                    let policy = match command {
+                        AppsCommands::Bypass(_) => AppPolicy::Bypass,
+                        _ => AppPolicy::Proxy,
+                    };
+                    select_config(manager, &args.config)?;
+                    manager.handler_mut().add_app_rule(args.target()?, policy)?;
+                    manager.save()?;
+                }
+                AppsCommands::Remove(args) => {
+                    select_config(manager, &args.config)?;
+                    manager.handler_mut().remove_app_rule(&args.target()?)?;
+                    manager.save()?;
+                }
+            },
+            Commands::Dns { command } => match command {
+                DnsCommands::List { value } => {
+                    select_config(manager, value)?;
+                    let dns = manager.handler_ref().get_dns_ref();
+                    if output.structured() {
+                        return output.emit(&DnsDump {
+                            servers: dns.get_tags(),
+                            default: dns.default.clone(),
+                            rules: RuleEntry::list(dns.get_rules()),
+                        });
+                    }
+                    for tag in dns.get_tags() {
+                        println!("server: {tag}");
+                    }
+                    if let Some(x) = &dns.default {
+                        println!("final: {x}");
+                    }
+                    let rules = dns.get_rules();
+                    if rules.is_empty() {
+                        println!("There are no dns rules");
+                    }
+                    for (i, rule) in rules.iter().enumerate() {
+                        println!("[{:2}]: {}", i + 1, rule);
+                    }
+                }
+                DnsCommands::Add(args) => {
+                    select_config(manager, &args.config)?;
+                    manager.handler_mut().add_dns_rule(args.rule()?)?;
+                    manager.save()?;
+                }
+                DnsCommands::Remove { index, config } => {
+                    select_config(manager, config)?;
+                    manager.handler_mut().remove_dns_rule(
+                        index.checked_sub(1).context("Rules are numbered from 1")?,
+                    )?;
+                    manager.save()?;
+                }
+                DnsCommands::Move { from, to, config } => {
+                    select_config(manager, config)?;
+                    manager.handler_mut().move_dns_rule(
+                        from.checked_sub(1).context("Rules are numbered from 1")?,
+                        to.checked_sub(1).context("Rules are numbered from 1")?,
+                    )?;
+                    manager.save()?;
+                }
+            },
+            Commands::Route { command } => match command {
+                RouteCommands::List { value } => {
+                    select_config(manager, value)?;
+                    let route = manager.handler_ref().get_route_ref();
+                    if output.structured() {
+                        return output.emit(&RouteDump {
+                            default: route.default.clone(),
+                            rules: RuleEntry::list(&route.rules),
+                        });
+                    }
+                    if let Some(x) = &route.default {
+                        println!("final: {x}");
+                    }
+                    if route.rules.is_empty() {
+                        println!("There are no route rules");
+                    }
+                    print_route_rules(&route.rules, "");
+                }
+                RouteCommands::Add { rule, config } => {
+                    select_config(manager, config)?;
+                    manager.handler_mut().add_route_expr(rule)?;
+                    manager.save()?;
+                }
+                RouteCommands::Remove { rule, config } => {
+                    select_config(manager, config)?;
+                    manager
+                        .handler_mut()
+                        .get_route_mut()
+                        .remove_rule_by_path(&parse_rule_path(rule)?)?;
+                    manager.save()?;
+                }
+                RouteCommands::Group {
+                    rules,
+                    mode,
+                    invert,
+                    config,
+                } => {
+                    select_config(manager, config)?;
+                    let indices = rules
+                        .iter()
+                        .map(|x| x.checked_sub(1).context("Rules are numbered from 1"))
+                        .collect::<Result<Vec<usize>>>()?;
+                    let route = manager.handler_mut().get_route_mut();
+                    route.group_rules(&indices, mode)?;
+                    if *invert && let Some(first) = indices.iter().min() {
+                        route.invert_rule(&[*first])?;
+                    }
+                    manager.save()?;
+                }
+                RouteCommands::Nest { rule, into, config } => {
+                    select_config(manager, config)?;
+                    manager.handler_mut().get_route_mut().nest_rule(
+                        rule.checked_sub(1).context("Rules are numbered from 1")?,
+                        &parse_rule_path(into)?,
+                    )?;
+                    manager.save()?;
+                }
+                RouteCommands::Extract { rule, config } => {
+                    select_config(manager, config)?;
+                    manager
+                        .handler_mut()
+                        .get_route_mut()
+                        .extract_rule(&parse_rule_path(rule)?)?;
+                    manager.save()?;
+                }
+                RouteCommands::Invert { rule, config } => {
+                    select_config(manager, config)?;
+                    manager
+                        .handler_mut()
+                        .get_route_mut()
+                        .invert_rule(&parse_rule_path(rule)?)?;
+                    manager.save()?;
+                }
+                RouteCommands::Explain {
+                    domain,
+                    ip,
+                    port,
+                    network,
+                    process,
+                    protocol,
+                    inbound,
+                    source_ip,
+                    source_port,
+                    config,
+                } => {
+                    select_config(manager, config)?;
+                    let meta = Metadata {
+                        inbound: inbound.clone(),
+                        network: network.clone(),
+                        protocol: protocol.clone(),
+                        domain: domain.clone(),
+                        ip: *ip,
+                        port: *port,
+                        source_ip: *source_ip,
+                        source_port: *source_port,
+                        process: process.clone(),
+                    };
+                    let route = manager.handler_ref().get_route_ref();
+                    let res = explain::explain(route, &meta);
+                    let unsure = res
+                        .steps
+                        .iter()
+                        .any(|x| matches!(x.verdict, Verdict::Unknown(_)));
+
+                    if output.structured() {
+                        return output.emit(&ExplainOutput {
+                            steps: res
+                                .steps
+                                .iter()
+                                .map(|x| ExplainStep {
+                                    index: x.index + 1,
+                                    rule: route.rules[x.index].to_string(),
+                                    verdict: match &x.verdict {
+                                        Verdict::Unknown(_) => "unknown",
+                                        _ => "match",
+                                    }
+                                    .to_string(),
+                                    unknown: match &x.verdict {
+                                        Verdict::Unknown(x) => x.clone(),
+                                        _ => vec![],
+                                    },
+                                })
+                                .collect(),
+                            matched: res.matched.map(|x| x + 1),
+                            action: res
+                                .matched
+                                .and_then(|x| route.rules[x].get_action())
+                                .map(|x| x.to_string()),
+                            default: match res.matched {
+                                Some(_) => None,
+                                None => route.default.clone(),
+                            },
+                            unsure,
+                        });
+                    }
+
+                    for step in &res.steps {
+                        let rule = &route.rules[step.index];
+                        match &step.verdict {
+                            Verdict::Unknown(x) => println!(
+                                "[{:2}]: may match: {rule}\n      unknown: {}",
+                                step.index + 1,
+                                x.join(", ")
+                            ),
+                            _ => println!("[{:2}]: match: {rule}", step.index + 1),
+                        }
+                    }
+                    let result = match res.matched {
+                        Some(i) => match route.rules[i].get_action() {
+                            Some(x) => format!("rule {} -> {x}", i + 1),
+                            None => format!("rule {}", i + 1),
+                        },
+                        None => format!(
+                            "final -> {}",
+                            route.default.as_deref().unwrap_or("first outbound")
+                        ),
+                    };
+                    match unsure {
+                        true => println!("result: {result}, unless a rule above matches"),
+                        false => println!("result: {result}"),
+                    }
+                }
+                RouteCommands::Mode { rule, mode, config } => {
+                    select_config(manager, config)?;
+                    manager
+                        .handler_mut()
+                        .get_route_mut()
+                        .set_rule_mode(&parse_rule_path(rule)?, mode)?;
+                    manager.save()?;
+                }
+            },
+            Commands::Clash { command } => {
+                let client = manager.clash_client()?;
+                match command {
+                    ClashCommands::Connections => {
+                        let list = client.connections()?;
+                        if output.structured() {
+                            return output.emit(&list);
+                        }
+                        let connections = list.connections.unwrap_or_default();
+                        if connections.is_empty() {
+                            println!("There are no connections");
+                        }
+                        for x in &connections {
+                            println!(
+                                "{:10}{:5}{:32}{:16}{:>10}{:>10}  {}",
+                                x.id.get(..8).unwrap_or(&x.id),
+                                x.metadata.network,
+                                x.destination(),
+                                x.outbound(),
+                                clashapi::format_bytes(x.upload),
+                                clashapi::format_bytes(x.download),
+                                x.rule
+                            );
+                        }
+                        println!(
+                            "total: up {}, down {}",
+                            clashapi::format_bytes(list.upload_total),
+                            clashapi::format_bytes(list.download_total)
+                        );
+                    }
+                    ClashCommands::Close { id, all } => match (id, all) {
+                        (_, true) => client.close_all_connections()?,
+                        (Some(id), false) => {
+                            let connections = client.connections()?.connections.unwrap_or_default();
+                            let found: Vec<_> = connections
+                                .iter()
+                                .filter(|x| x.id.starts_with(id.as_str()))
+                                .collect();
+                            match found.as_slice() {
+                                [x] => client.close_connection(&x.id)?,
+                                [] => return Err(anyhow!("Connection '{id}' does not exist")),
+                                _ => return Err(anyhow!("Connection id '{id}' is ambiguous")),
+                            }
+                        }
+                        (None, false) => unreachable!(),
+                    },
+                    ClashCommands::Traffic => {
+                        let x = client.traffic()?;
+                        if output.structured() {
+                            return output.emit(&x);
+                        }
+                        println!(
+                            "up {}/s, down {}/s",
+                            clashapi::format_bytes(x.up),
+                            clashapi::format_bytes(x.down)
+                        );
+                    }
+                    ClashCommands::Proxies => {
+                        let proxies = client.proxies()?;
+                        if output.structured() {
+                            return output.emit(&proxies);
+                        }
+                        for x in proxies.values() {
+                            let Some(all) = &x.all else {
+                                continue;
+                            };
+                            println!("{} ({})", x.name, x.proxy_type);
+                            for member in all {
+                                let mark = match x.now.as_deref() == Some(member.as_str()) {
+                                    true => "*",
+                                    false => " ",
+                                };
+                                println!("  {mark} {member}");
+                            }
+                        }
+                    }
+                    ClashCommands::Select { selector, member } => {
+                        client.select(selector, member)?;
+                    }
+                    ClashCommands::Mode { mode } => match mode {
+                        Some(x) => {
+                            manager.set_handler_config_by_current()?;
+                            let modes = manager.handler_ref().clash_modes();
+                            let mode = modes
+                                .iter()
+                                .find(|m| m.eq_ignore_ascii_case(x))
+                                .ok_or_else(|| anyhow!("Clash mode must be one of {modes:?}"))?;
+                            client.set_mode(mode)?
+                        }
+                        None if output.structured() => output.emit(&ModeOutput {
+                            mode: client.mode()?,
+                        })?,
+                        None => println!("{}", client.mode()?),
+                    },
+                }
+            }
+            Commands::Usage { config, month } => {
+                let names = match config {
+                    Some(_) => {
+                        select_config(manager, config)?;
+                        vec![manager.get_inf_ref().get_name()]
+                    }
+                    None => manager.get_list(),
+                };
+                let store = UsageStore::load(&UsageStore::path(&manager.get_data_path()))?;
+                let now = usage::now();
+                let entries: Vec<UsageEntry> = names
+                    .iter()
+                    .map(|name| {
+                        UsageEntry::new(
+                            name,
+                            store.get(name),
+                            *month,
+                            now,
+                            manager.settings_ref().get_quota(name),
+                        )
+                    })
+                    .collect();
+                if output.structured() {
+                    return output.emit(&entries);
+                }
+
+                for x in entries {
+                    if store.get(&x.config).is_none() {
+                        println!("{}: no traffic recorded", x.config);
+                        continue;
+                    }
+
+                    if let Some(total) = x.total {
+                        println!("{} ({})", x.config, usage::month_key(now));
+                        for (day, bytes) in &x.daily {
+                            print_usage(day, bytes);
+                        }
+                        print_usage("total", &total);
+                        if let Some(quota) = x.quota {
+                            println!(
+                                "quota: used {} of {} ({})",
+                                clashapi::format_bytes(total.total()),
+                                quota.monthly,
+                                quota.action()
+                            );
+                        }
+                    } else {
+                        println!("{} (today, UTC hours)", x.config);
+                        for (hour, bytes) in &x.hourly {
+                            print_usage(&hour[11..], bytes);
+                        }
+                        println!("{} (last 7 days)", x.config);
+                        for (day, bytes) in &x.daily {
+                            print_usage(day, bytes);
+                        }
+                    }
+                }
+            }
+            Commands::Secrets { command } => match command {
+                SecretsCommands::Status => {
+                    let mut status = SecretsStatus {
+                        source: manager
+                            .settings_ref()
+                            .encryption()
+                            .map(|x| x.source.clone()),
+                        encrypted: vec![],
+                        plain: vec![],
+                    };
+                    for name in manager.get_list() {
+                        let path = manager.get_configs_path().join(format!("{name}.json"));
+                        let tree = fs::read_to_string(path)
+                            .ok()
+                            .and_then(|x| serde_json::from_str::<Value>(&x).ok());
+                        let Some(tree) = tree else {
+                            continue;
+                        };
+                        if secrets::has_encrypted(&tree) {
+                            status.encrypted.push(name.clone());
+                        }
+                        if secrets::has_plain(&tree) {
+                            status.plain.push(name);
+                        }
+                    }
+                    if output.structured() {
+                        return output.emit(&status);
+                    }
+
+                    match &status.source {
+                        Some(x) => println!("Credentials are encrypted, key from {x}"),
+                        None => println!("Credentials are stored in plain text"),
                     }
+                    for x in &status.plain {
+                        println!("'{x}' has plain credentials");
+                    }
+                }
+                SecretsCommands::Enable { keyring } => {
+                    if manager.settings_ref().encryption().is_some() {
+                        return Err(anyhow!("Credentials are already encrypted"));
+                    }
+                    let source = match keyring {
+                        true => "keyring",
+                        false => "passphrase",
+                    };
+                    let (encryption, key) = Encryption::create(source)?;
+                    // Settings go first, files encrypted before a failure stay readable
+                    manager.settings_mut().set_encryption(Some(encryption));
+                    manager.save_settings()?;
+                    let count = secrets::rewrite_configs(&manager.get_data_path(), |x| {
+                        secrets::encrypt_tree(x, &key)
+                    })?;
+                    println!("Encrypted credentials in {count} files");
+                }
+                SecretsCommands::Disable => {
+                    let encryption = manager
+                        .settings_ref()
+                        .encryption()
+                        .cloned()
+                        .context("Credentials are not encrypted")?;
+                    let key = encryption.key()?;
+                    let count = secrets::rewrite_configs(&manager.get_data_path(), |x| {
+                        secrets::decrypt_tree(x, &key)
+                    })?;
+                    manager.settings_mut().set_encryption(None);
+                    manager.save_settings()?;
+                    encryption.remove()?;
+                    println!("Decrypted credentials in {count} files");
+                }
+            },
+            Commands::Quota { command } => match command {
+                QuotaCommands::List => {
+                    let quotas = manager.settings_ref().get_quotas();
+                    if output.structured() {
+                        return output.emit(&quotas.into_iter().collect::<BTreeMap<_, _>>());
+                    }
+                    if quotas.is_empty() {
+                        println!("There are no quotas");
+                    }
+                    for (name, x) in quotas {
+                        match &x.switch_to {
+                            Some(to) => println!("{name:16}{:10}{} {to}", x.monthly, x.action()),
+                            None => println!("{name:16}{:10}{}", x.monthly, x.action()),
+                        }
+                    }
+                }
+                QuotaCommands::Set {
+                    config,
+                    limit,
+                    action,
+                    to,
+                } => {
+                    select_config(manager, &Some(config.clone()))?;
+                    let name = manager.get_inf_ref().get_name();
+                    let to = to.as_ref().map(|x| config_name(manager, x)).transpose()?;
+                    let quota = Quota::new(limit, action.as_deref(), to.as_deref())?;
+                    manager.settings_mut().set_quota(&name, quota);
+                    manager.save_settings()?;
+                }
+                QuotaCommands::Remove { config } => {
+                    select_config(manager, &Some(config.clone()))?;
+                    let name = manager.get_inf_ref().get_name();
+                    manager.settings_mut().remove_quota(&name)?;
+                    manager.save_settings()?;
+                }
+            },
+            Commands::Ruleset { command } => match command {
+                RulesetCommands::List { value } => {
+                    select_config(manager, value)?;
+                    let rule_sets = manager.handler_ref().get_route_ref().get_rule_sets();
+                    if output.structured() {
+                        let entries: Vec<RuleSetEntry> = rule_sets
+                            .iter()
+                            .map(|x| RuleSetEntry {
+                                tag: x.get_tag().unwrap_or_default(),
+                                kind: x.get_type().to_string(),
+                                source: x.get_source(),
+                            })
+                            .collect();
+                        return output.emit(&entries);
+                    }
+                    if rule_sets.is_empty() {
+                        println!("There are no rule sets");
+                    }
+                    for x in rule_sets {
+                        println!(
+                            "{:16}{:8}{}",
+                            x.get_tag().unwrap_or_default(),
+                            x.get_type(),
+                            x.get_source()
+                        );
+                    }
+                }
+                RulesetCommands::Add {
+                    tag,
+                    url,
+                    path,
+                    inline,
+                    format,
+                    update_interval,
+                    download_detour,
+                    config,
+                } => {
+                    select_config(manager, config)?;
+                    let format = format.as_deref();
+                    let rule_set = match (url, path, inline) {
+                        (Some(url), _, _) => {
+                            let mut x = RuleSetRemote::with_url(tag, url, format)?;
+                            if let Some(interval) = update_interval {
+                                x = x.set_update_interval(interval)?;
+                            }
+                            if let Some(detour) = download_detour {
+                                x = x.set_download_detour(detour);
+                            }
+                            RuleSet::Remote(x)
+                        }
+                        (_, Some(path), _) => {
+                            RuleSet::Local(RuleSetLocal::with_path(tag, path, format)?)
+                        }
+                        (_, _, Some(rules)) => {
+                            RuleSet::Inline(RuleSetInline::with_rules(tag, rules)?)
+                        }
+                        _ => return Err(anyhow!("Use --url, --path or --inline")),
+                    };
+                    manager.handler_mut().add_rule_set(rule_set)?;
+                    manager.save()?;
+                }
+                RulesetCommands::Remove { tag, config } => {
+                    select_config(manager, config)?;
+                    manager.handler_mut().remove_rule_set(tag)?;
+                    manager.save()?;
+                }
+            },
+            Commands::History { value } => {
+                select_config(manager, value)?;
+                let history =
+                    History::open(&manager.get_data_path(), &manager.get_inf_ref().get_name())?;
+                let mut entries = vec![];
+                let mut prev: Option<Configurator> = None;
+                for x in history.list() {
+                    let config = load_revision(manager, &history, Some(x.rev))?;
+                    entries.push(RevisionEntry {
+                        rev: x.rev,
+                        time: x.time,
+                        restored: x.restored,
+                        changes: prev.as_ref().map_or(0, |p| diff::diff(p, &config).len()),
+                    });
+                    prev = Some(config);
+                }
+                if output.structured() {
+                    return output.emit(&entries);
+                }
+
+                if entries.is_empty() {
+                    println!("There are no revisions");
+                }
+                for x in entries.iter().rev() {
+                    let note = match (x.restored, x.rev == entries[0].rev) {
+                        (Some(from), _) => format!("restored from {from}"),
+                        (None, true) => "oldest".to_string(),
+                        (None, false) => format!("{} changes", x.changes),
+                    };
+                    println!("[{:3}]: {}  {note}", x.rev, format_time(x.time));
+                }
+            }
+            Commands::Diff { value, from, to } => {
+                select_config(manager, &Some(value.clone()))?;
+                let history =
+                    History::open(&manager.get_data_path(), &manager.get_inf_ref().get_name())?;
+                let from = match from {
+                    Some(x) => *x,
+                    None => {
+                        let list = history.list();
+                        list.len()
+                            .checked_sub(2)
+                            .map(|i| list[i].rev)
+                            .context("There is no previous revision")?
+                    }
+                };
+                let changes = diff::diff(
+                    &load_revision(manager, &history, Some(from))?,
+                    &load_revision(manager, &history, *to)?,
+                );
+                if output.structured() {
+                    return output.emit(&changes);
+                }
+
+                if changes.is_empty() {
+                    println!("No changes");
+                }
+                for x in changes {
+                    println!("{x}");
+                }
+            }
+            Commands::Undo { value } => {
+                select_config(manager, value)?;
+                let rev =
+                    History::open(&manager.get_data_path(), &manager.get_inf_ref().get_name())?
+                        .undo_target()
+                        .context("Nothing to undo")?;
+                manager.revert_config(rev)?;
+                println!("Restored revision {rev}");
+            }
+            Commands::Revert { value, rev } => {
+                select_config(manager, &Some(value.clone()))?;
+                manager.revert_config(*rev)?;
+            }
+            Commands::Backup { file } => {
+                let backup = Backup::create(&manager.get_data_path())?;
+                backup.save(Path::new(file))?;
+                if output.structured() {
+                    return output.emit(&backup.manifest);
+                }
+                println!(
+                    "Saved {} configs into '{file}'",
+                    backup.manifest.configs.len()
+                );
+            }
+            Commands::Restore { file, replace } => {
+                let backup = Backup::load(Path::new(file))?;
+                let mode = match replace {
+                    true => RestoreMode::Replace,
+                    false => RestoreMode::Merge,
+                };
+                let report = manager.restore_backup(&backup, mode)?;
+                if output.structured() {
+                    return output.emit(&report);
+                }
+
+                for x in &report.added {
+                    println!("Added '{x}'");
+                }
+                for (from, to) in &report.renamed {
+                    println!("Added '{from}' as '{to}'");
+                }
+                for x in &report.unchanged {
+                    println!("Skipped '{x}', same as local");
+                }
+            }
+            Commands::Edit { value, yaml } => {
+                select_config(manager, value)?;
+                let name = manager.get_inf_ref().get_name();
+                if edit_in_editor(manager, *yaml)? && manager.reload_config(&name)? {
+                    println!("Running config reloaded");
                 }
             }
+            Commands::Config { command } => match command {
+                ConfigCommands::Get { config, path } => {
+                    select_config(manager, &Some(config.clone()))?;
+                    let root = manager.handler_ref().to_tree()?;
+                    let value = match path {
+                        Some(x) => tree::get(&root, x)?,
+                        None => &root,
+                    };
+                    match value {
+                        _ if output.structured() => output.emit(value)?,
+                        Value::String(x) => println!("{x}"),
+                        x => println!("{}", serde_json::to_string_pretty(x)?),
+                    }
+                }
+                ConfigCommands::Set {
+                    config,
+                    path,
+                    value,
+                } => {
+                    select_config(manager, &Some(config.clone()))?;
+                    edit_config(manager, path, tree::parse_value(value), |root, x| {
+                        tree::set(root, path, x)
+                    })?;
+                }
+                ConfigCommands::Unset { config, path } => {
+                    select_config(manager, &Some(config.clone()))?;
+                    edit_config(manager, path, vec![Value::Null], |root, _| {
+                        tree::unset(root, path).map(|_| ())
+                    })?;
+                }
+                ConfigCommands::Append {
+                    config,
+                    path,
+                    value,
+                } => {
+                    select_config(manager, &Some(config.clone()))?;
+                    edit_config(manager, path, tree::parse_value(value), |root, x| {
+                        tree::append(root, path, x)
+                    })?;
+                }
+                ConfigCommands::Remove {
+                    config,
+                    path,
+                    value,
+                } => {
+                    select_config(manager, &Some(config.clone()))?;
+                    edit_config(manager, path, tree::parse_value(value), |root, x| {
+                        tree::remove(root, path, &x)
+                    })?;
+                }
+            },
+            Commands::Preset { command } => match command {
+                PresetCommands::List if output.structured() => {
+                    let entries: Vec<PresetEntry> = manager
+                        .presets()
+                        .get_list()?
+                        .into_iter()
+                        .map(|(name, custom)| PresetEntry {
+                            builtin: Preset::is_builtin(&name),
+                            name,
+                            custom,
+                        })
+                        .collect();
+                    output.emit(&entries)?;
+                }
+                PresetCommands::List => {
+                    for (name, custom) in manager.presets().get_list()? {
+                        if custom && Preset::is_builtin(&name) {
+                            println!("{name} (builtin, modified)");
+                        } else if custom {
+                            println!("{name}");
+                        } else {
+                            println!("{name} (builtin)");
+                        }
+                    }
+                }
+                PresetCommands::Show { name } => {
+                    let preset = manager.presets().load(name)?;
+                    match output.structured() {
+                        true => output.emit(&preset)?,
+                        false => print!("{}", toml::to_string_pretty(&preset)?),
+                    }
+                }
+                PresetCommands::Edit { name } => edit_preset(manager, name)?,
+            },
             Commands::Remove { value } => {
                 let rr = match value {
-                    Some(x) => match x {
-                        ConfigEn::Text(t) => manager.remove_config(t),
-                        ConfigEn::Number(n) => manager.remove_config_by_number(*n as usize),
-                    },
+                    Some(x) => {
+                        let name = config_name(manager, x)?;
+                        manager.delete_config(&name)
+                    }
                     None => {
                         for i in manager.get_list() {
-                            manager.remove_config(&i)?;
+                            manager.delete_config(&i)?;
                         }
                         Ok(())
                     }
@@ -289,12 +2273,10 @@ impl Cli {
                 setup_signal_handler();
 
                 let rr = match value {
-                    Some(x) => match x {
-                        ConfigEn::Text(t) => manager.run_app(Some(&t), None, *unable_system_proxy),
-                        ConfigEn::Number(n) => {
-                            manager.run_app(None, Some(*n as usize - 1), *unable_system_proxy)
-                        }
-                    },
+                    Some(x) => {
+                        let name = config_name(manager, x)?;
+                        manager.run_app(Some(&name), None, *unable_system_proxy)
+                    }
                     None => manager.run_app(None, None, *unable_system_proxy),
                 };
 
@@ -351,7 +2333,9 @@ impl Cli {
             #[cfg(feature = "daemon")]
             Commands::Status => {
                 let a = manager.get_status()?;
-                if a.is_none() {
+                if output.structured() {
+                    output.emit(&StatusOutput::from(a.as_ref()))?;
+                } else if a.is_none() {
                     println!("Not started");
                 } else {
                     print!("{}", yaml_serde::to_string(a.as_ref().unwrap())?);
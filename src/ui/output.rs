@@ -0,0 +1,388 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::configurator::Configurator;
+use crate::configurator::apps::AppRule;
+use crate::configurator::dns::DnsConfig;
+use crate::configurator::dns::dnsrule::DnsRule;
+use crate::configurator::inbound::InboundConfig;
+use crate::configurator::outbound::OutboundConfig;
+use crate::configurator::route::RouteConfig;
+use crate::configurator::route::routerule::{DefaultRouteRule, RouteRule};
+use crate::datamanager::InboundMod;
+use crate::datamanager::catalog::ConfigInfo;
+use crate::usage::{self, Bytes, ConfigUsage, Quota};
+
+#[cfg(feature = "daemon")]
+use crate::DaemonStatus;
+
+/// Format of command output, `table` is for people, others have stable schemas
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Table,
+    Json,
+    Yaml,
+}
+
+impl OutputFormat {
+    pub fn structured(self) -> bool {
+        self != OutputFormat::Table
+    }
+
+    /// Prints `value` as json or yaml
+    pub fn emit<T: Serialize>(self, value: &T) -> Result<()> {
+        match self {
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(value)?),
+            _ => print!("{}", yaml_serde::to_string(value)?),
+        }
+        Ok(())
+    }
+}
+
+/// Body printed instead of human message when command fails
+#[derive(Debug, Serialize)]
+pub struct ErrorOutput {
+    pub error: ErrorBody,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorBody {
+    pub message: String,
+    /// Underlying errors from the outermost one
+    pub causes: Vec<String>,
+}
+
+impl From<&anyhow::Error> for ErrorOutput {
+    fn from(value: &anyhow::Error) -> Self {
+        Self {
+            error: ErrorBody {
+                message: value.to_string(),
+                causes: value.chain().skip(1).map(|x| x.to_string()).collect(),
+            },
+        }
+    }
+}
+
+/// Item of `relation list`
+#[derive(Debug, Serialize)]
+pub struct ConfigEntry {
+    /// 1-based number accepted by commands instead of name
+    pub index: usize,
+    /// Persistent short id, accepted by commands too
+    pub id: Option<String>,
+    pub name: String,
+    pub outbound_type: Option<String>,
+    pub server: Option<String>,
+    pub server_port: Option<u16>,
+    pub inbounds: Vec<InboundEntry>,
+    pub tags: Vec<String>,
+    pub favourite: bool,
+    pub region: Option<String>,
+    pub subscription: Option<String>,
+    /// Unix seconds of the last start
+    pub last_used: Option<u64>,
+    /// Tcp connect time to server, only with `--sort latency`
+    pub latency_ms: Option<u128>,
+}
+
+impl ConfigEntry {
+    /// Entry of config which could not be loaded has only index and metadata
+    pub fn new(info: &ConfigInfo, config: Option<&Configurator>) -> Self {
+        let outbound = config.and_then(|x| {
+            let outbounds = x.get_outbound_ref();
+            let kind = outbounds.get_types_except_direct().into_iter().next()?;
+            outbounds.get_ref_by_type(&kind)
+        });
+
+        Self {
+            index: info.index + 1,
+            id: info.meta.id.clone(),
+            name: info.name.clone(),
+            outbound_type: outbound.map(|x| x.get_type().to_string()),
+            server: outbound.map(|x| x.get_addr()),
+            server_port: outbound.and_then(|x| x.get_port()),
+            inbounds: config
+                .map(|x| x.get_inbounds_ports())
+                .unwrap_or_default()
+                .into_iter()
+                .map(InboundEntry::from)
+                .collect(),
+            tags: info.tags().to_vec(),
+            favourite: info.favourite(),
+            region: info.region(),
+            subscription: info.meta.subscription.clone(),
+            last_used: info.last_used,
+            latency_ms: info.latency.map(|x| x.as_millis()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct InboundEntry {
+    /// `mixed`, `http`, `socks5` or `tun`
+    pub mode: String,
+    pub port: Option<u16>,
+}
+
+impl From<InboundMod> for InboundEntry {
+    fn from(value: InboundMod) -> Self {
+        let (mode, port) = match value {
+            InboundMod::Mixed(x) => ("mixed", Some(x)),
+            InboundMod::Http(x) => ("http", Some(x)),
+            InboundMod::Socks5(x) => ("socks5", Some(x)),
+            InboundMod::Tun => ("tun", None),
+        };
+        Self {
+            mode: mode.to_string(),
+            port,
+        }
+    }
+}
+
+/// `relation status`
+#[cfg(feature = "daemon")]
+#[derive(Debug, Serialize)]
+pub struct StatusOutput {
+    pub running: bool,
+    /// Name of the running config
+    pub config: Option<String>,
+    pub file: Option<String>,
+    pub system_proxy: bool,
+    pub ping_ms: Option<u128>,
+    pub ip: Option<String>,
+    pub warning: Option<String>,
+}
+
+#[cfg(feature = "daemon")]
+impl From<Option<&DaemonStatus>> for StatusOutput {
+    fn from(value: Option<&DaemonStatus>) -> Self {
+        let Some(x) = value.filter(|x| x.running()) else {
+            return Self {
+                running: false,
+                config: None,
+                file: None,
+                system_proxy: false,
+                ping_ms: None,
+                ip: None,
+                warning: value.and_then(|x| x.warning.clone()),
+            };
+        };
+
+        Self {
+            running: true,
+            config: std::path::Path::new(&x.file)
+                .file_stem()
+                .map(|x| x.to_string_lossy().to_string()),
+            file: Some(x.file.clone()),
+            system_proxy: x.sys_proxy,
+            ping_ms: x.ping,
+            ip: x.ip.clone(),
+            warning: x.warning.clone(),
+        }
+    }
+}
+
+/// `relation manage --print`, sections in sing-box format
+#[derive(Serialize)]
+pub struct ConfigDump<'a> {
+    pub name: String,
+    pub dns: &'a DnsConfig,
+    pub inbounds: &'a InboundConfig,
+    pub outbounds: &'a OutboundConfig,
+    pub route: &'a RouteConfig,
+}
+
+/// `relation route list`
+#[derive(Debug, Serialize)]
+pub struct RouteDump<'a> {
+    #[serde(rename = "final")]
+    pub default: Option<String>,
+    pub rules: Vec<RuleEntry<'a, RouteRule>>,
+}
+
+/// Rule in sing-box format, nested rules of logical rule are in its `rules`
+#[derive(Debug, Serialize)]
+pub struct RuleEntry<'a, T> {
+    /// 1-based position, nested rules are addressed as `<index>.<n>` by rule commands
+    pub index: usize,
+    pub rule: &'a T,
+}
+
+impl<'a, T> RuleEntry<'a, T> {
+    pub fn list(rules: &'a [T]) -> Vec<Self> {
+        rules
+            .iter()
+            .enumerate()
+            .map(|(i, rule)| Self { index: i + 1, rule })
+            .collect()
+    }
+}
+
+/// `relation dns list`
+#[derive(Debug, Serialize)]
+pub struct DnsDump<'a> {
+    pub servers: Vec<String>,
+    #[serde(rename = "final")]
+    pub default: Option<String>,
+    pub rules: Vec<RuleEntry<'a, DnsRule>>,
+}
+
+/// `relation route explain`
+#[derive(Debug, Serialize)]
+pub struct ExplainOutput {
+    pub steps: Vec<ExplainStep>,
+    /// 1-based rule with final action, `None` when `final` outbound is used
+    pub matched: Option<usize>,
+    pub action: Option<String>,
+    #[serde(rename = "final")]
+    pub default: Option<String>,
+    /// Some steps may match, their fields are not known from the query
+    pub unsure: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExplainStep {
+    /// 1-based rule
+    pub index: usize,
+    pub rule: String,
+    /// `match` or `unknown`
+    pub verdict: String,
+    pub unknown: Vec<String>,
+}
+
+/// Item of `relation history`
+#[derive(Debug, Serialize)]
+pub struct RevisionEntry {
+    pub rev: u64,
+    /// Unix seconds
+    pub time: u64,
+    pub restored: Option<u64>,
+    /// Changes from the previous revision
+    pub changes: usize,
+}
+
+/// `relation apps list`, uids are from tun inbound
+#[derive(Debug, Serialize)]
+pub struct AppsDump<'a> {
+    pub rules: Vec<AppEntry>,
+    pub include_uid: Option<&'a [u32]>,
+    pub exclude_uid: Option<&'a [u32]>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AppEntry {
+    /// 1-based position among app rules
+    pub index: usize,
+    /// `bypass` or `proxy`
+    pub policy: &'static str,
+    /// Route rule in sing-box format
+    pub rule: DefaultRouteRule,
+}
+
+impl AppEntry {
+    pub fn list(rules: &[AppRule]) -> Vec<Self> {
+        rules
+            .iter()
+            .enumerate()
+            .map(|(i, x)| Self {
+                index: i + 1,
+                policy: x.policy.name(),
+                rule: x.target.to_route_rule(x.outbound.clone()),
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RuleSetEntry {
+    pub tag: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub source: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PresetEntry {
+    pub name: String,
+    pub builtin: bool,
+    pub custom: bool,
+}
+
+/// `relation usage`, month report has only daily buckets and total
+#[derive(Debug, Serialize)]
+pub struct UsageEntry<'a> {
+    pub config: String,
+    /// UTC hours of today
+    pub hourly: BTreeMap<&'a str, Bytes>,
+    /// UTC days of the last week or of the current month
+    pub daily: BTreeMap<&'a str, Bytes>,
+    pub total: Option<Bytes>,
+    pub quota: Option<&'a Quota>,
+}
+
+impl<'a> UsageEntry<'a> {
+    pub fn new(
+        config: &str,
+        usage: Option<&'a ConfigUsage>,
+        month: bool,
+        now: u64,
+        quota: Option<&'a Quota>,
+    ) -> Self {
+        let mut res = Self {
+            config: config.to_string(),
+            hourly: BTreeMap::new(),
+            daily: BTreeMap::new(),
+            total: None,
+            quota,
+        };
+        let Some(x) = usage else {
+            return res;
+        };
+
+        if month {
+            let month = usage::month_key(now);
+            res.daily = x
+                .daily
+                .iter()
+                .filter(|x| x.0.starts_with(&month))
+                .map(|(k, v)| (k.as_str(), *v))
+                .collect();
+            res.total = Some(x.total(&month));
+        } else {
+            let today = usage::day_key(now);
+            let oldest = usage::day_key(now.saturating_sub(6 * 86400));
+            res.hourly = x
+                .hourly
+                .iter()
+                .filter(|x| x.0.starts_with(&today))
+                .map(|(k, v)| (k.as_str(), *v))
+                .collect();
+            res.daily = x
+                .daily
+                .range(oldest..)
+                .map(|(k, v)| (k.as_str(), *v))
+                .collect();
+        }
+        res
+    }
+}
+
+/// `relation secrets status`
+#[derive(Debug, Serialize)]
+pub struct SecretsStatus {
+    /// `passphrase` or `keyring`, `None` when credentials are stored in plain text
+    pub source: Option<String>,
+    /// Configs with encrypted credentials
+    pub encrypted: Vec<String>,
+    /// Configs with credentials in plain text
+    pub plain: Vec<String>,
+}
+
+/// `relation clash mode`
+#[derive(Debug, Serialize)]
+pub struct ModeOutput {
+    pub mode: String,
+}
@@ -0,0 +1,311 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::AddAssign;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::clashapi::{ClashClient, Connection, Connections};
+use crate::consts::timing;
+
+/// Connections of the running config, filled by `spawn_feed`
+#[derive(Default)]
+pub struct ConnectionFeed {
+    pub client: Option<ClashClient>,
+    pub connections: Vec<Connection>,
+    pub error: Option<String>,
+    pub stats: TrafficStats,
+}
+
+impl ConnectionFeed {
+    pub fn reset(&mut self, client: Option<ClashClient>, error: Option<String>) {
+        *self = Self {
+            client,
+            error,
+            ..Default::default()
+        };
+    }
+}
+
+/// Bytes or bytes per second
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Counters {
+    pub up: u64,
+    pub down: u64,
+}
+
+impl AddAssign for Counters {
+    fn add_assign(&mut self, rhs: Self) {
+        self.up += rhs.up;
+        self.down += rhs.down;
+    }
+}
+
+impl Counters {
+    pub fn total(&self) -> u64 {
+        self.up + self.down
+    }
+
+    fn per_second(&self, secs: f64) -> Self {
+        Self {
+            up: (self.up as f64 / secs) as u64,
+            down: (self.down as f64 / secs) as u64,
+        }
+    }
+}
+
+/// One point of traffic graph
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TrafficSample {
+    pub proxy: Counters,
+    pub direct: Counters,
+}
+
+/// Traffic of the running config split by outbound
+#[derive(Debug, Default)]
+pub struct TrafficStats {
+    /// Tags of direct outbounds, `None` until read from clash api
+    pub direct_tags: Option<HashSet<String>>,
+    pub rate: TrafficSample,
+    /// Bytes since the config was picked up
+    pub session: Counters,
+    pub outbounds: BTreeMap<String, Counters>,
+    /// Outbound and bytes of open connections at the previous sample
+    seen: HashMap<String, (String, Counters)>,
+    totals: Option<Counters>,
+    sampled: Option<Instant>,
+}
+
+impl TrafficStats {
+    /// Core totals include closed connections. Bytes missing from open connections are
+    /// split between outbounds of connections closed since the previous sample by their size,
+    /// connections opened and closed between two samples are counted as proxy
+    pub fn update(&mut self, list: &Connections) {
+        let now = Instant::now();
+        let totals = Counters {
+            up: list.upload_total,
+            down: list.download_total,
+        };
+        let first = self.totals.is_none();
+
+        let mut direct = Counters::default();
+        let mut open = Counters::default();
+        let mut seen = HashMap::new();
+        for x in list.connections.as_deref().unwrap_or_default() {
+            let current = Counters {
+                up: x.upload,
+                down: x.download,
+            };
+            let prev = match self.seen.get(&x.id) {
+                Some((_, prev)) => *prev,
+                None if first => current,
+                None => Counters::default(),
+            };
+            let delta = Counters {
+                up: current.up.saturating_sub(prev.up),
+                down: current.down.saturating_sub(prev.down),
+            };
+
+            open += delta;
+            if self.add_outbound(x.outbound(), delta) {
+                direct += delta;
+            }
+            seen.insert(x.id.clone(), (x.outbound().to_string(), current));
+        }
+
+        let total = match self.totals {
+            Some(prev) => Counters {
+                up: totals.up.saturating_sub(prev.up),
+                down: totals.down.saturating_sub(prev.down),
+            },
+            None => Counters::default(),
+        };
+        let unseen = Counters {
+            up: total.up.saturating_sub(open.up),
+            down: total.down.saturating_sub(open.down),
+        };
+        let mut closed: HashMap<String, u64> = HashMap::new();
+        for (id, (outbound, bytes)) in &self.seen {
+            if !seen.contains_key(id) {
+                *closed.entry(outbound.clone()).or_default() += bytes.total().max(1);
+            }
+        }
+        let weight: u64 = closed.values().sum();
+        for (outbound, part) in closed {
+            let share = Counters {
+                up: (unseen.up as u128 * part as u128 / weight as u128) as u64,
+                down: (unseen.down as u128 * part as u128 / weight as u128) as u64,
+            };
+            if self.add_outbound(&outbound, share) {
+                direct += share;
+            }
+        }
+        let proxy = Counters {
+            up: total.up.saturating_sub(direct.up),
+            down: total.down.saturating_sub(direct.down),
+        };
+        self.session += total;
+
+        let secs = self
+            .sampled
+            .map_or(1.0, |x| (now - x).as_secs_f64().max(0.001));
+        self.rate = TrafficSample {
+            proxy: proxy.per_second(secs),
+            direct: direct.per_second(secs),
+        };
+
+        self.seen = seen;
+        self.totals = Some(totals);
+        self.sampled = Some(now);
+    }
+
+    /// Adds bytes to outbound, returns whether it is direct
+    fn add_outbound(&mut self, outbound: &str, bytes: Counters) -> bool {
+        *self.outbounds.entry(outbound.to_string()).or_default() += bytes;
+        self.direct_tags
+            .as_ref()
+            .is_some_and(|tags| tags.contains(outbound))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionSort {
+    Duration,
+    Download,
+    Upload,
+    Host,
+}
+
+impl ConnectionSort {
+    pub fn next(self) -> Self {
+        match self {
+            ConnectionSort::Duration => ConnectionSort::Download,
+            ConnectionSort::Download => ConnectionSort::Upload,
+            ConnectionSort::Upload => ConnectionSort::Host,
+            ConnectionSort::Host => ConnectionSort::Duration,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ConnectionSort::Duration => "time",
+            ConnectionSort::Download => "down",
+            ConnectionSort::Upload => "up",
+            ConnectionSort::Host => "host",
+        }
+    }
+}
+
+/// Polls clash api of `feed.client` on `TRAFFIC_REFRESH` cadence
+pub fn spawn_feed(feed: Arc<Mutex<ConnectionFeed>>) {
+    thread::spawn(move || {
+        loop {
+            let client = feed.lock().ok().and_then(|x| x.client.clone());
+            if let Some(client) = client {
+                let direct_tags = match feed.lock().map(|x| x.stats.direct_tags.is_none()) {
+                    Ok(true) => client.proxies().ok().map(|x| {
+                        x.into_values()
+                            .filter(|x| x.proxy_type.eq_ignore_ascii_case("direct"))
+                            .map(|x| x.name)
+                            .collect()
+                    }),
+                    _ => None,
+                };
+                let result = client.connections();
+                if let Ok(mut x) = feed.lock() {
+                    if direct_tags.is_some() {
+                        x.stats.direct_tags = direct_tags;
+                    }
+                    match result {
+                        Ok(list) => {
+                            x.stats.update(&list);
+                            x.connections = list.connections.unwrap_or_default();
+                            x.error = None;
+                        }
+                        Err(e) => {
+                            x.connections.clear();
+                            x.stats.rate = TrafficSample::default();
+                            x.error = Some(e.to_string());
+                        }
+                    }
+                }
+            }
+            thread::sleep(timing::TRAFFIC_REFRESH);
+        }
+    });
+}
+
+/// Connections matching `filter`, ordered by `sort`
+pub fn visible(connections: &[Connection], filter: &str, sort: ConnectionSort) -> Vec<Connection> {
+    let filter = filter.to_lowercase();
+    let mut res: Vec<Connection> = connections
+        .iter()
+        .filter(|x| {
+            filter.is_empty()
+                || [
+                    x.metadata.host.as_str(),
+                    &x.destination(),
+                    &x.rule,
+                    x.outbound(),
+                    &x.metadata.network,
+                    &x.metadata.process_path,
+                ]
+                .iter()
+                .any(|field| field.to_lowercase().contains(&filter))
+        })
+        .cloned()
+        .collect();
+
+    match sort {
+        ConnectionSort::Duration => res.sort_by_key(|x| parse_rfc3339(&x.start)),
+        ConnectionSort::Download => res.sort_by_key(|x| Reverse(x.download)),
+        ConnectionSort::Upload => res.sort_by_key(|x| Reverse(x.upload)),
+        ConnectionSort::Host => res.sort_by_key(|x| x.destination()),
+    }
+    res
+}
+
+/// Time since connection start, `1h02m`, `3m04s` or `12s`
+pub fn format_duration(start: &str) -> String {
+    let Some(start) = parse_rfc3339(start) else {
+        return "-".to_string();
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |x| x.as_secs() as i64);
+    let secs = (now - start).max(0);
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+/// Unix seconds of `2024-01-02T03:04:05.678+03:00`
+fn parse_rfc3339(value: &str) -> Option<i64> {
+    let (date, time) = value.split_once('T')?;
+    let mut date = date.splitn(3, '-').map(|x| x.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+
+    let offset_at = time.rfind(['Z', '+', '-'])?;
+    let (clock, offset) = time.split_at(offset_at);
+    let mut clock = clock.split('.').next()?.splitn(3, ':');
+    let seconds = clock.next()?.parse::<i64>().ok()? * 3600
+        + clock.next()?.parse::<i64>().ok()? * 60
+        + clock.next()?.parse::<i64>().ok()?;
+    let offset = match offset.split_at(1) {
+        ("Z", _) => 0,
+        (sign, x) => {
+            let (h, m) = x.split_once(':')?;
+            let x = h.parse::<i64>().ok()? * 3600 + m.parse::<i64>().ok()? * 60;
+            if sign == "-" { -x } else { x }
+        }
+    };
+
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let days = era * 146097 + yoe * 365 + yoe / 4 - yoe / 100 + doy - 719468;
+    Some(days * 86400 + seconds - offset)
+}
@@ -1,5 +1,9 @@
 use std::sync::{Arc, Mutex};
 
+use crate::configurator::apps::{AppPolicy, AppTarget};
+use crate::configurator::dns::dnsrule::DnsDefaultRule;
+use crate::configurator::dns::dnsurl::build_dns_url;
+use crate::configurator::inbound::tun::TunField;
 use crate::consts::DNS;
 use crate::consts::manage;
 #[cfg(not(feature = "daemon"))]
@@ -12,6 +16,7 @@ use crate::datamanager::async_app::App;
 
 use crate::ui::tui::state::InputMode;
 
+use super::procs::list_processes;
 use super::state::{Focus, InputAction, RightPanel, TuiState};
 use crate::consts::{keys, route, timing, ui};
 
@@ -44,14 +49,12 @@ pub fn handle_add_config_input(
         }
         KeyCode::Enter => {
             if !state.input.buffer.is_empty() {
-                let cfg = app.handler_mut().clean();
-                let result = if tun_mode {
-                    cfg.default_tun()
+                let preset = if tun_mode { "tun" } else { "default" };
+                let result = app.apply_preset(preset).and_then(|app| {
+                    app.handler_mut()
                         .set_outbound_from_url(&state.input.buffer.clone())
-                } else {
-                    cfg.default()
-                        .set_outbound_from_url(&state.input.buffer.clone())
-                };
+                        .map(|_| ())
+                });
 
                 match result {
                     Ok(_) => {
@@ -127,6 +130,319 @@ pub fn handle_route_value_input(state: &mut TuiState, key: KeyCode) {
     }
 }
 
+pub fn handle_tun_input(app: &mut App, state: &mut TuiState, key: KeyCode) -> Result<()> {
+    match key {
+        KeyCode::Esc => {
+            state.input.mode = InputMode::Normal;
+            state.input.error = false;
+            state.input.buffer.clear();
+        }
+        KeyCode::Enter => {
+            let field = TunField::ALL[state.ui.tun_selected];
+            let value = state.input.buffer.clone();
+
+            let result = app
+                .set_handler_config_by_number(state.app.selected_index)
+                .and_then(|_| {
+                    let tun = app.handler_mut().get_tun_mut()?;
+                    if value.trim().is_empty() {
+                        tun.unset_field(field)?;
+                    } else {
+                        tun.set_field(field, &value)?;
+                    }
+                    app.save()
+                });
+
+            match result {
+                Ok(_) => {
+                    state.input.error = false;
+                    state.input.mode = InputMode::Normal;
+                    state.input.buffer.clear();
+                }
+                Err(_) => state.input.error = true,
+            }
+        }
+        KeyCode::Backspace => {
+            state.input.buffer.pop();
+        }
+        KeyCode::Char(c) => {
+            state.input.error = false;
+            state.input.buffer.push(c);
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+pub fn handle_dns_rule_input(app: &mut App, state: &mut TuiState, key: KeyCode) -> Result<()> {
+    match key {
+        KeyCode::Esc => {
+            state.input.mode = InputMode::Normal;
+            state.input.error = false;
+            state.input.buffer.clear();
+        }
+        KeyCode::Enter => {
+            let result = app
+                .set_handler_config_by_number(state.app.selected_index)
+                .and_then(|_| {
+                    let rule = DnsDefaultRule::from_spec(&state.input.buffer)?;
+                    app.handler_mut().add_dns_rule(rule)?;
+                    app.save()
+                });
+
+            match result {
+                Ok(_) => {
+                    state.ui.dns_selected = app.handler_ref().get_dns_ref().get_rules().len() - 1;
+                    state.input.error = false;
+                    state.input.mode = InputMode::Normal;
+                    state.input.buffer.clear();
+                }
+                Err(_) => state.input.error = true,
+            }
+        }
+        KeyCode::Backspace => {
+            state.input.buffer.pop();
+        }
+        KeyCode::Char(c) => {
+            state.input.error = false;
+            state.input.buffer.push(c);
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+pub fn handle_connection_filter_input(state: &mut TuiState, key: KeyCode) {
+    match key {
+        KeyCode::Esc => {
+            state.input.mode = InputMode::Normal;
+            state.connections.filter.clear();
+        }
+        KeyCode::Enter => {
+            state.input.mode = InputMode::Normal;
+        }
+        KeyCode::Backspace => {
+            state.connections.filter.pop();
+        }
+        KeyCode::Char(c) => {
+            state.connections.filter.push(c);
+        }
+        _ => {}
+    }
+    state.connections.selected = 0;
+}
+
+/// Incremental search of configs, selection follows the first match
+pub fn handle_config_search_input(app: &mut App, state: &mut TuiState, key: KeyCode) -> Result<()> {
+    match key {
+        KeyCode::Esc => {
+            state.input.mode = InputMode::Normal;
+            state.app.search.clear();
+        }
+        KeyCode::Enter => {
+            state.input.mode = InputMode::Normal;
+        }
+        KeyCode::Backspace => {
+            state.app.search.pop();
+        }
+        KeyCode::Char(c) => {
+            state.app.search.push(c);
+        }
+        _ => {}
+    }
+    if state.app.step(0)
+        && matches!(
+            state.ui.right_panel,
+            RightPanel::Settings | RightPanel::Tun | RightPanel::Apps | RightPanel::Dns
+        )
+    {
+        load_panel_config(app, state);
+        state.ui.dns_selected = 0;
+    }
+    Ok(())
+}
+
+/// Loads selected config for Settings, Tun, Apps and Dns panels, failure is shown on the panel
+fn load_panel_config(app: &mut App, state: &mut TuiState) -> bool {
+    let result = app.set_handler_config_by_number(state.app.selected_index);
+    state.ui.load_error = result.as_ref().err().map(|e| format!("{e:#}"));
+    result.is_ok()
+}
+
+/// Closes the connection selected on Connections panel
+fn close_connection(state: &mut TuiState) {
+    let (Some(client), Some(connection)) = (
+        state.connections.client.as_ref(),
+        state.connections.list.get(state.connections.selected),
+    ) else {
+        return;
+    };
+    state.input.error = client.close_connection(&connection.id).is_err();
+}
+
+/// Switches the running config to the clash mode after the current one
+fn next_clash_mode(state: &mut TuiState) {
+    let Some(client) = state.connections.client.as_ref() else {
+        return;
+    };
+    let modes = &state.connections.modes;
+    let result = client.mode().and_then(|current| {
+        let next = modes
+            .iter()
+            .position(|x| x.eq_ignore_ascii_case(&current))
+            .map_or(0, |x| (x + 1) % modes.len());
+        let mode = modes.get(next).cloned().unwrap_or(current);
+        client.set_mode(&mode).map(|_| mode)
+    });
+    match result {
+        Ok(x) => {
+            state.connections.mode = Some(x);
+            state.input.error = false;
+        }
+        Err(_) => state.input.error = true,
+    }
+}
+
+/// Opens selector popup of the running config
+fn open_selectors(state: &mut TuiState) {
+    if load_members(state) {
+        state.input.mode = InputMode::SelectorSelect;
+    }
+}
+
+/// Members of selectors of the running config for selector popup
+fn load_members(state: &mut TuiState) -> bool {
+    let Some(client) = state.connections.client.as_ref() else {
+        return false;
+    };
+    let Ok(proxies) = client.proxies() else {
+        state.input.error = true;
+        return false;
+    };
+    state.connections.members = proxies
+        .values()
+        .filter(|x| x.proxy_type == "Selector")
+        .flat_map(|x| {
+            x.all.iter().flatten().map(|member| {
+                let now = x.now.as_deref() == Some(member.as_str());
+                (x.name.clone(), member.clone(), now)
+            })
+        })
+        .collect();
+    state.connections.member_selected = state
+        .connections
+        .member_selected
+        .min(state.connections.members.len().saturating_sub(1));
+    state.input.error = false;
+    true
+}
+
+pub fn handle_selector_input(state: &mut TuiState, key: KeyCode) {
+    let len = state.connections.members.len();
+    match key {
+        KeyCode::Esc => {
+            state.input.mode = InputMode::Normal;
+        }
+        KeyCode::Down | KeyCode::Char(keys::DOWN_ALT) if len > 0 => {
+            state.connections.member_selected = (state.connections.member_selected + 1) % len;
+        }
+        KeyCode::Up | KeyCode::Char(keys::UP_ALT) if len > 0 => {
+            state.connections.member_selected = (state.connections.member_selected + len - 1) % len;
+        }
+        KeyCode::Enter => {
+            let (Some(client), Some((selector, member, _))) = (
+                state.connections.client.as_ref(),
+                state
+                    .connections
+                    .members
+                    .get(state.connections.member_selected),
+            ) else {
+                state.input.mode = InputMode::Normal;
+                return;
+            };
+            state.input.error = client.select(selector, member).is_err();
+            if !state.input.error {
+                state.input.mode = InputMode::Normal;
+                load_members(state);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Removes or moves the rule selected on Dns panel
+fn apply_dns_key(app: &mut App, state: &mut TuiState, key: char) -> Result<()> {
+    if !load_panel_config(app, state) {
+        return Ok(());
+    }
+    let len = app.handler_ref().get_dns_ref().get_rules().len();
+    let selected = state.ui.dns_selected;
+    if selected >= len {
+        return Ok(());
+    }
+
+    let handler = app.handler_mut();
+    let result = match key {
+        keys::MOVE_RULE_DOWN if selected + 1 < len => handler
+            .move_dns_rule(selected, selected + 1)
+            .map(|_| selected + 1),
+        keys::MOVE_RULE_UP if selected > 0 => handler
+            .move_dns_rule(selected, selected - 1)
+            .map(|_| selected - 1),
+        keys::REMOVE_RULE => handler
+            .remove_dns_rule(selected)
+            .map(|_| selected.min(len.saturating_sub(2))),
+        _ => return Ok(()),
+    };
+
+    match result {
+        Ok(x) => {
+            app.save()?;
+            state.ui.dns_selected = x;
+            state.input.error = false;
+        }
+        Err(_) => state.input.error = true,
+    }
+    Ok(())
+}
+
+/// Applies app rule for the process selected on Apps panel
+fn apply_app_key(app: &mut App, state: &mut TuiState, key: char) -> Result<()> {
+    let Some(process) = state.ui.processes.get(state.ui.apps_selected).cloned() else {
+        return Ok(());
+    };
+
+    let target = match key {
+        keys::UID_BYPASS | keys::UID_PROXY => AppTarget::Uid(process.uid),
+        _ => AppTarget::Name(process.name.clone()),
+    };
+
+    if !load_panel_config(app, state) {
+        return Ok(());
+    }
+    let handler = app.handler_mut();
+    let result = match key {
+        keys::APP_BYPASS | keys::UID_BYPASS => handler.add_app_rule(target, AppPolicy::Bypass),
+        keys::APP_PROXY | keys::UID_PROXY => handler.add_app_rule(target, AppPolicy::Proxy),
+        _ => match handler.remove_app_rule(&target) {
+            Ok(x) => Ok(x),
+            Err(_) => handler.remove_app_rule(&AppTarget::Uid(process.uid)),
+        },
+    }
+    .map(|_| ());
+
+    match result {
+        Ok(_) => {
+            app.save()?;
+            state.input.error = false;
+        }
+        Err(_) => state.input.error = true,
+    }
+    Ok(())
+}
+
 pub fn handle_normal_input(
     app: &mut App,
     state: &mut TuiState,
@@ -178,7 +494,7 @@ pub fn handle_normal_input(
         KeyCode::Char(keys::DELETE_CONFIG) => {
             if state.app.len > 0 {
                 let name = app.get_list()[state.app.selected_index].clone();
-                app.remove_config_by_number(state.app.selected_index)?;
+                app.delete_config(&name)?;
 
                 if state.app.running.as_deref() == Some(name.as_str()) {
                     app.stop_app()?;
@@ -196,10 +512,109 @@ pub fn handle_normal_input(
 
         KeyCode::Tab => {
             state.ui.context_menu = false;
+            state.input.error = false;
             state.ui.right_panel = match state.ui.right_panel {
                 RightPanel::Logs => RightPanel::Settings,
-                RightPanel::Settings => RightPanel::Logs,
+                RightPanel::Settings => RightPanel::Tun,
+                RightPanel::Tun => RightPanel::Apps,
+                RightPanel::Apps => RightPanel::Dns,
+                RightPanel::Dns => RightPanel::Connections,
+                RightPanel::Connections => RightPanel::Logs,
             };
+            if state.ui.right_panel == RightPanel::Apps {
+                state.ui.processes = list_processes();
+                state.ui.apps_selected = 0;
+            }
+            if state.ui.right_panel == RightPanel::Dns {
+                state.ui.dns_selected = 0;
+            }
+            if state.ui.right_panel == RightPanel::Connections {
+                state.connections.selected = 0;
+            }
+            if matches!(
+                state.ui.right_panel,
+                RightPanel::Settings | RightPanel::Tun | RightPanel::Apps | RightPanel::Dns
+            ) && state.app.len > 0
+            {
+                load_panel_config(app, state);
+            }
+        }
+
+        KeyCode::Char(
+            c @ (keys::APP_BYPASS
+            | keys::APP_PROXY
+            | keys::UID_BYPASS
+            | keys::UID_PROXY
+            | keys::REMOVE_RULE),
+        ) if state.ui.focus == Focus::RightPanel
+            && state.ui.right_panel == RightPanel::Apps
+            && state.app.len > 0 =>
+        {
+            apply_app_key(app, state, c)?;
+        }
+
+        KeyCode::Char(keys::ADD_RULE)
+            if state.ui.focus == Focus::RightPanel
+                && state.ui.right_panel == RightPanel::Dns
+                && state.app.len > 0 =>
+        {
+            state.input.mode = InputMode::DnsRuleInput;
+            state.input.buffer.clear();
+            state.input.error = false;
+        }
+
+        KeyCode::Char(c @ (keys::REMOVE_RULE | keys::MOVE_RULE_DOWN | keys::MOVE_RULE_UP))
+            if state.ui.focus == Focus::RightPanel
+                && state.ui.right_panel == RightPanel::Dns
+                && state.app.len > 0 =>
+        {
+            apply_dns_key(app, state, c)?;
+        }
+
+        KeyCode::Char(keys::FILTER) if state.ui.focus == Focus::Configs => {
+            state.input.mode = InputMode::ConfigSearch;
+            state.input.error = false;
+        }
+
+        KeyCode::Char(keys::GROUP) if state.ui.focus == Focus::Configs => {
+            state.app.grouping = state.app.grouping.next();
+        }
+
+        KeyCode::Char(keys::FILTER)
+            if state.ui.focus == Focus::RightPanel
+                && state.ui.right_panel == RightPanel::Connections =>
+        {
+            state.input.mode = InputMode::ConnectionFilter;
+            state.input.error = false;
+        }
+
+        KeyCode::Char(keys::SORT)
+            if state.ui.focus == Focus::RightPanel
+                && state.ui.right_panel == RightPanel::Connections =>
+        {
+            state.connections.sort = state.connections.sort.next();
+            state.connections.selected = 0;
+        }
+
+        KeyCode::Char(keys::CLOSE_CONNECTION)
+            if state.ui.focus == Focus::RightPanel
+                && state.ui.right_panel == RightPanel::Connections =>
+        {
+            close_connection(state);
+        }
+
+        KeyCode::Char(keys::CLASH_MODE)
+            if state.ui.focus == Focus::RightPanel
+                && state.ui.right_panel == RightPanel::Connections =>
+        {
+            next_clash_mode(state);
+        }
+
+        KeyCode::Char(keys::SELECT_MEMBER)
+            if state.ui.focus == Focus::RightPanel
+                && state.ui.right_panel == RightPanel::Connections =>
+        {
+            open_selectors(state);
         }
 
         KeyCode::Char(c) => {
@@ -240,18 +655,27 @@ pub fn handle_normal_input(
                     state.settings.route_value = None;
                 }
 
-                let dns_rule = build_rule(&[
-                    state.settings.dns_type.as_deref(),
-                    state.settings.dns_address.as_deref(),
-                    state.settings.dns_port.as_deref(),
-                ]);
+                let dns_url = state.settings.dns_type.as_deref().map(|x| {
+                    build_dns_url(
+                        x,
+                        state.settings.dns_address.as_deref(),
+                        state.settings.dns_port.as_deref(),
+                    )
+                });
 
-                if let Some(dns_rules) = dns_rule {
-                    app.handler_mut().add_dns_servers(&dns_rules)?;
-                    app.save()?;
-                    state.settings.dns_type = None;
-                    state.settings.dns_address = None;
-                    state.settings.dns_port = None;
+                if let Some(dns_url) = dns_url {
+                    match app.handler_mut().add_dns_servers(&vec![dns_url]) {
+                        Ok(_) => {
+                            app.save()?;
+                            state.input.error = false;
+                            state.settings.dns_type = None;
+                            state.settings.dns_address = None;
+                            state.settings.dns_port = None;
+                        }
+                        Err(_) => {
+                            state.input.error = true;
+                        }
+                    }
                 }
 
                 let manage_rule = build_rule(&[
@@ -317,6 +741,15 @@ pub fn handle_normal_input(
                     state.ui.context_menu = false;
                     state.ui.popup_selected = 0;
                 }
+            } else if state.ui.focus == Focus::RightPanel && state.ui.right_panel == RightPanel::Tun
+            {
+                if let Some(tun) = app.handler_ref().get_tun_ref() {
+                    state.input.buffer = tun
+                        .get_field(TunField::ALL[state.ui.tun_selected])
+                        .unwrap_or_default();
+                    state.input.error = false;
+                    state.input.mode = InputMode::TunInput;
+                }
             } else if state.ui.focus == Focus::RightPanel
                 && state.ui.right_panel == RightPanel::Settings
             {
@@ -343,7 +776,6 @@ pub fn handle_normal_input(
                 if len > 0 && !state.app.enter_mode {
                     let number = state.app.selected_index as u16 + 1;
                     state.app.running = Some(app.get_list()[state.app.selected_index].clone());
-                    app.set_log_file();
                     app.run_app(None, Some(number as usize - 1), false)?;
                     state.ui.right_panel = RightPanel::Logs;
                     state.ui.focus = Focus::Configs;
@@ -358,7 +790,6 @@ pub fn handle_normal_input(
                         app.stop_app()?;
                         std::thread::sleep(timing::RESTART_DELAY);
                         state.app.running = Some(name.clone());
-                        app.set_log_file();
                         app.run_app(None, Some(state.app.selected_index), false)?;
                         state.ui.right_panel = RightPanel::Logs;
                         state.ui.focus = Focus::Configs;
@@ -377,7 +808,9 @@ pub fn handle_normal_input(
         }
 
         KeyCode::Left => {
-            if state.ui.settings_selected == 0 && state.ui.focus == Focus::RightPanel {
+            if state.ui.focus == Focus::RightPanel
+                && (state.ui.settings_selected == 0 || state.ui.right_panel != RightPanel::Settings)
+            {
                 state.ui.focus = Focus::Configs;
             } else if state.ui.focus == Focus::RightPanel
                 && state.ui.right_panel == RightPanel::Settings
@@ -390,7 +823,35 @@ pub fn handle_normal_input(
 
         KeyCode::Down | KeyCode::Char(keys::DOWN_ALT) => {
             if state.ui.focus == Focus::Configs && state.app.len > 0 {
-                state.app.selected_index = (state.app.selected_index + 1) % state.app.len;
+                if state.app.step(1)
+                    && matches!(
+                        state.ui.right_panel,
+                        RightPanel::Settings | RightPanel::Tun | RightPanel::Apps | RightPanel::Dns
+                    )
+                {
+                    load_panel_config(app, state);
+                    state.ui.dns_selected = 0;
+                }
+            } else if state.ui.focus == Focus::RightPanel && state.ui.right_panel == RightPanel::Tun
+            {
+                state.ui.tun_selected = (state.ui.tun_selected + 1) % TunField::ALL.len();
+            } else if state.ui.focus == Focus::RightPanel
+                && state.ui.right_panel == RightPanel::Apps
+                && !state.ui.processes.is_empty()
+            {
+                state.ui.apps_selected = (state.ui.apps_selected + 1) % state.ui.processes.len();
+            } else if state.ui.focus == Focus::RightPanel && state.ui.right_panel == RightPanel::Dns
+            {
+                let len = app.handler_ref().get_dns_ref().get_rules().len();
+                if len > 0 {
+                    state.ui.dns_selected = (state.ui.dns_selected + 1) % len;
+                }
+            } else if state.ui.focus == Focus::RightPanel
+                && state.ui.right_panel == RightPanel::Connections
+                && !state.connections.list.is_empty()
+            {
+                state.connections.selected =
+                    (state.connections.selected + 1) % state.connections.list.len();
             } else if state.ui.context_menu {
                 let context_len = if state.ui.settings_selected == ui::ROUTE_ACTION_INDEX {
                     route::ACTIONS.len() + 1
@@ -424,8 +885,37 @@ pub fn handle_normal_input(
 
         KeyCode::Up | KeyCode::Char(keys::UP_ALT) => {
             if state.app.len > 0 && state.ui.focus == Focus::Configs {
-                state.app.selected_index =
-                    (state.app.selected_index + state.app.len - 1) % state.app.len;
+                if state.app.step(-1)
+                    && matches!(
+                        state.ui.right_panel,
+                        RightPanel::Settings | RightPanel::Tun | RightPanel::Apps | RightPanel::Dns
+                    )
+                {
+                    load_panel_config(app, state);
+                    state.ui.dns_selected = 0;
+                }
+            } else if state.ui.focus == Focus::RightPanel && state.ui.right_panel == RightPanel::Tun
+            {
+                state.ui.tun_selected =
+                    (state.ui.tun_selected + TunField::ALL.len() - 1) % TunField::ALL.len();
+            } else if state.ui.focus == Focus::RightPanel
+                && state.ui.right_panel == RightPanel::Apps
+                && !state.ui.processes.is_empty()
+            {
+                let len = state.ui.processes.len();
+                state.ui.apps_selected = (state.ui.apps_selected + len - 1) % len;
+            } else if state.ui.focus == Focus::RightPanel && state.ui.right_panel == RightPanel::Dns
+            {
+                let len = app.handler_ref().get_dns_ref().get_rules().len();
+                if len > 0 {
+                    state.ui.dns_selected = (state.ui.dns_selected + len - 1) % len;
+                }
+            } else if state.ui.focus == Focus::RightPanel
+                && state.ui.right_panel == RightPanel::Connections
+                && !state.connections.list.is_empty()
+            {
+                let len = state.connections.list.len();
+                state.connections.selected = (state.connections.selected + len - 1) % len;
             } else if state.ui.context_menu {
                 let context_len = if state.ui.settings_selected == ui::ROUTE_ACTION_INDEX {
                     route::ACTIONS.len() + 1
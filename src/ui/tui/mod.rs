@@ -1,21 +1,39 @@
-mod ifaces;
+mod conns;
 mod input;
+mod procs;
+mod render_apps;
+mod render_connections;
+mod render_dns;
+mod render_route;
 mod render_traffic;
+mod render_tun;
 mod setup;
 mod state;
 mod tuiguard;
 
-use input::{handle_add_config_input, handle_normal_input, handle_route_value_input};
+use input::{
+    handle_add_config_input, handle_config_search_input, handle_connection_filter_input,
+    handle_dns_rule_input, handle_normal_input, handle_route_value_input, handle_selector_input,
+    handle_tun_input,
+};
 
-use state::{Focus, InputAction, InputMode, RightPanel, TuiState};
+use state::{ConfigRow, Focus, InputAction, InputMode, RightPanel, TuiState};
 
 use setup::setup_tty;
 
+use conns::{ConnectionFeed, Counters, TrafficSample, spawn_feed};
+use render_apps::render_apps_panel;
+use render_connections::render_connections_panel;
+use render_dns::render_dns_panel;
+use render_route::render_route_rules;
 use render_traffic::render_traffic_bar;
+use render_tun::{render_tun_panel, tun_input_area};
 
+use crate::configurator::Configurator;
 use crate::consts::*;
+use crate::datamanager::catalog::Grouping;
+use crate::datamanager::render;
 use crate::minireq::*;
-use ifaces::*;
 #[cfg(unix)]
 use std::time::Instant;
 
@@ -30,6 +48,7 @@ use crate::datamanager::async_app::App;
 
 use crossterm::event::{self, Event, KeyCode, KeyEventKind};
 
+use std::cmp::Reverse;
 use std::collections::VecDeque;
 
 use ratatui::{
@@ -47,8 +66,6 @@ use ratatui::{
 use anyhow::Result;
 
 pub fn run(app: &mut App) -> Result<()> {
-    let iface = iface_detect();
-
     std::panic::set_hook(Box::new(|panic_info| {
         use std::io::Write;
 
@@ -63,25 +80,26 @@ pub fn run(app: &mut App) -> Result<()> {
         }
     }));
 
+    app.unlock_secrets()?;
     let mut tui = setup_tty()?;
 
-    let old_log = app.get_data_path().join("box.log");
+    let old_log = app.get_data_path().join(render::LOG_FILE);
     let _ = std::fs::write(&old_log, "");
 
-    let mut prev = read_iface(&iface)?;
     let mut prev_time = Instant::now();
-
-    let mut rx_rate: u64;
-    let mut tx_rate: u64;
-
-    let mut rx_list: VecDeque<u64> = VecDeque::new();
-    let mut tx_list: VecDeque<u64> = VecDeque::new();
+    let mut history: VecDeque<TrafficSample> = VecDeque::new();
+    let mut session = Counters::default();
+    let mut outbounds: Vec<(String, Counters)> = vec![];
 
     let mut state = TuiState::new(app)?;
 
     let current_ip = Arc::new(Mutex::new(net::LOADING_IP.to_string()));
     let ip_shared = Arc::clone(&current_ip);
 
+    let feed = Arc::new(Mutex::new(ConnectionFeed::default()));
+    spawn_feed(Arc::clone(&feed));
+    let mut feed_config: Option<String> = None;
+
     let change_flag = Arc::new(Mutex::new(true));
     let change_shared = Arc::clone(&change_flag);
 
@@ -189,6 +207,21 @@ pub fn run(app: &mut App) -> Result<()> {
                     InputMode::ValueInput => {
                         handle_route_value_input(&mut state, key.code);
                     }
+                    InputMode::TunInput => {
+                        handle_tun_input(app, &mut state, key.code)?;
+                    }
+                    InputMode::DnsRuleInput => {
+                        handle_dns_rule_input(app, &mut state, key.code)?;
+                    }
+                    InputMode::ConnectionFilter => {
+                        handle_connection_filter_input(&mut state, key.code);
+                    }
+                    InputMode::ConfigSearch => {
+                        handle_config_search_input(app, &mut state, key.code)?;
+                    }
+                    InputMode::SelectorSelect => {
+                        handle_selector_input(&mut state, key.code);
+                    }
                     InputMode::Normal => {
                         match handle_normal_input(app, &mut state, key.code, &change_flag)? {
                             InputAction::Continue => {}
@@ -199,31 +232,54 @@ pub fn run(app: &mut App) -> Result<()> {
             }
         }
 
-        if prev_time.elapsed() >= timing::TRAFFIC_REFRESH {
-            let now = Instant::now();
-            let dt = (now - prev_time).as_secs_f64().max(0.001);
-
-            let current = read_iface(&iface)?;
-
-            let drx = current.rx.saturating_sub(prev.rx) as f64;
-            let dtx = current.tx.saturating_sub(prev.tx) as f64;
-
-            rx_rate = (drx / dt) as u64;
-            tx_rate = (dtx / dt) as u64;
+        // -------- CONNECTIONS --------
+        if state.app.running != feed_config {
+            feed_config = state.app.running.clone();
+            let client = feed_config.as_deref().map(|x| app.clash_client_by_name(x));
+            state.connections.client = client.as_ref().and_then(|x| x.as_ref().ok()).cloned();
+            state.connections.mode = None;
+            state.connections.modes = feed_config
+                .as_deref()
+                .and_then(|x| {
+                    let mut config = Configurator::new();
+                    config
+                        .load_from_file(app.get_configs_path().join(format!("{x}.json")))
+                        .ok()
+                        .map(|x| x.clash_modes())
+                })
+                .unwrap_or_default();
+            if let Ok(mut x) = feed.lock() {
+                x.reset(
+                    state.connections.client.clone(),
+                    client.and_then(|x| x.err()).map(|e| e.to_string()),
+                );
+            }
+        }
+        if let Ok(x) = feed.lock() {
+            state.connections.refresh(&x.connections, x.error.clone());
+        }
 
-            rx_list.push_back(rx_rate);
-            tx_list.push_back(tx_rate);
-            while rx_list.len() > traffic::HISTORY_LIMIT {
-                rx_list.pop_front();
+        // -------- TRAFFIC --------
+        if prev_time.elapsed() >= timing::TRAFFIC_REFRESH {
+            if let Ok(x) = feed.lock() {
+                history.push_back(x.stats.rate);
+                session = x.stats.session;
+                outbounds = x
+                    .stats
+                    .outbounds
+                    .iter()
+                    .map(|(tag, bytes)| (tag.clone(), *bytes))
+                    .collect();
+                outbounds.sort_by_key(|x| Reverse(x.1.total()));
             }
-            while tx_list.len() > traffic::HISTORY_LIMIT {
-                tx_list.pop_front();
+            while history.len() > traffic::HISTORY_LIMIT {
+                history.pop_front();
             }
-
-            prev = current;
-            prev_time = now;
+            prev_time = Instant::now();
         }
 
+        state.app.sync(app);
+
         tui.terminal.draw(|f| {
             let size = f.area();
 
@@ -244,38 +300,65 @@ pub fn run(app: &mut App) -> Result<()> {
                 .constraints([Constraint::Fill(1), Constraint::Fill(1)])
                 .split(horizontal[0]);
 
-            let configs = app.get_list();
+            let rows = state.app.rows();
 
-            let items: Vec<ListItem> = configs
+            let items: Vec<ListItem> = rows
                 .iter()
-                .map(|name| {
-                    let is_running = state.app.running.as_deref() == Some(name.as_str());
-                    if is_running {
-                        ListItem::new(Line::from(vec![
-                            Span::styled(
-                                ui::RUNNING_SYMBOL,
+                .map(|row| {
+                    let info = match row {
+                        ConfigRow::Group(name) => {
+                            return ListItem::new(Span::styled(
+                                name.clone(),
                                 Style::default()
-                                    .fg(Color::Green)
+                                    .fg(Color::DarkGray)
                                     .add_modifier(Modifier::BOLD),
-                            ),
-                            Span::styled(
-                                name.clone(),
-                                Style::default().add_modifier(Modifier::BOLD),
-                            ),
-                        ]))
+                            ));
+                        }
+                        ConfigRow::Config(i) => &state.app.configs[*i],
+                    };
+                    let mut spans = vec![];
+                    if state.app.running.as_deref() == Some(info.name.as_str()) {
+                        spans.push(Span::styled(
+                            ui::RUNNING_SYMBOL,
+                            Style::default()
+                                .fg(Color::Green)
+                                .add_modifier(Modifier::BOLD),
+                        ));
+                        spans.push(Span::styled(
+                            info.name.clone(),
+                            Style::default().add_modifier(Modifier::BOLD),
+                        ));
                     } else {
-                        ListItem::new(name.clone())
+                        spans.push(Span::raw(info.name.clone()));
                     }
+                    if info.favourite() {
+                        spans.push(Span::styled(
+                            ui::FAVOURITE_SYMBOL,
+                            Style::default().fg(Color::Yellow),
+                        ));
+                    }
+                    ListItem::new(Line::from(spans))
                 })
                 .collect();
 
             let mut tui_state = ListState::default();
-            tui_state.select(Some(state.app.selected_index));
+            tui_state.select(
+                rows.iter()
+                    .position(|x| *x == ConfigRow::Config(state.app.selected_index)),
+            );
+
+            let mut title = text::CONFIGS_TITLE.to_string();
+            if state.app.grouping != Grouping::None {
+                title.push_str(&format!("  group: {}", state.app.grouping.name()));
+            }
+            if !state.app.search.is_empty() {
+                title.push_str(&format!("  search: {}", state.app.search));
+            }
 
             let list = List::new(items)
                 .block(
                     Block::default()
-                        .title(text::CONFIGS_TITLE)
+                        .title(title)
                         .borders(Borders::ALL)
                         .border_style(if state.ui.focus == Focus::Configs {
                             Style::default().fg(Color::Yellow)
@@ -321,6 +404,26 @@ pub fn run(app: &mut App) -> Result<()> {
                 f.render_widget(input, input_area);
             }
 
+            // SEARCH LINE
+            if state.input.mode == InputMode::ConfigSearch {
+                let input = Paragraph::new(format!("{}_", state.app.search))
+                    .block(
+                        Block::default()
+                            .title(text::SEARCH_TITLE)
+                            .borders(Borders::ALL)
+                            .border_type(BorderType::Rounded),
+                    )
+                    .style(Style::default().fg(Color::Green));
+
+                let input_area = ratatui::layout::Rect {
+                    x: vertical[0].x,
+                    y: vertical[0].y + vertical[0].height - ui::INPUT_HEIGHT,
+                    width: vertical[0].width,
+                    height: ui::INPUT_HEIGHT,
+                };
+                f.render_widget(input, input_area);
+            }
+
             // ADDING TUN CONFIG LINE
             if matches!(state.input.mode, InputMode::AddConfig { tun: true }) {
                 let (color, message) = if state.input.error {
@@ -348,7 +451,7 @@ pub fn run(app: &mut App) -> Result<()> {
             }
 
             // TRAFFIC BAR
-            render_traffic_bar(f, vertical[1], &iface, &ip_base, &rx_list, &tx_list);
+            render_traffic_bar(f, vertical[1], &ip_base, &history, session, &outbounds);
 
             // HELP PANEL
             let helper = Paragraph::new(Line::from(text::HELP))
@@ -560,7 +663,72 @@ pub fn run(app: &mut App) -> Result<()> {
                             })
                             .border_type(BorderType::Rounded),
                     );
-                    f.render_widget(settings, horizontal[1]);
+                    let parts = Layout::default()
+                        .direction(Direction::Vertical)
+                        .constraints([Constraint::Min(ui::SETTINGS_HEIGHT), Constraint::Fill(1)])
+                        .split(horizontal[1]);
+                    f.render_widget(settings, parts[0]);
+                    render_route_rules(
+                        f,
+                        parts[1],
+                        &app.handler_ref().get_route_ref().rules,
+                        state.ui.load_error.as_deref(),
+                    );
+                }
+                RightPanel::Tun | RightPanel::Apps | RightPanel::Dns
+                    if state.ui.load_error.is_some() =>
+                {
+                    let error = Paragraph::new(state.ui.load_error.clone().unwrap_or_default())
+                        .wrap(Wrap { trim: true })
+                        .block(
+                            Block::default()
+                                .title(text::LOAD_ERROR_TITLE)
+                                .borders(Borders::ALL)
+                                .border_style(Style::default().fg(Color::Red))
+                                .border_type(BorderType::Rounded),
+                        );
+                    f.render_widget(error, horizontal[1]);
+                }
+                RightPanel::Tun => {
+                    render_tun_panel(
+                        f,
+                        horizontal[1],
+                        app.handler_ref().get_tun_ref(),
+                        state.ui.tun_selected,
+                        state.ui.focus == Focus::RightPanel,
+                        state.input.error,
+                    );
+                }
+                RightPanel::Apps => {
+                    render_apps_panel(
+                        f,
+                        horizontal[1],
+                        &app.handler_ref().get_app_rules(),
+                        &state.ui.processes,
+                        state.ui.apps_selected,
+                        state.ui.focus == Focus::RightPanel,
+                        state.input.error,
+                    );
+                }
+                RightPanel::Dns => {
+                    render_dns_panel(
+                        f,
+                        horizontal[1],
+                        app.handler_ref().get_dns_ref(),
+                        state.ui.dns_selected,
+                        state.ui.focus == Focus::RightPanel,
+                        state.input.error,
+                    );
+                }
+                RightPanel::Connections => {
+                    render_connections_panel(
+                        f,
+                        horizontal[1],
+                        &state.connections,
+                        state.ui.focus == Focus::RightPanel,
+                        state.input.mode == InputMode::ConnectionFilter,
+                        state.input.error,
+                    );
                 }
             }
             // Context Menu
@@ -634,6 +802,52 @@ pub fn run(app: &mut App) -> Result<()> {
                 f.render_stateful_widget(list, context_panel_area, &mut tui_state);
             }
 
+            if state.input.mode == InputMode::SelectorSelect {
+                let members = &state.connections.members;
+                let area = ratatui::layout::Rect {
+                    x: horizontal[1].x + ui::CONTEXT_X_OFFSET,
+                    y: horizontal[1].y + ui::CONTEXT_Y_OFFSET,
+                    width: horizontal[1]
+                        .width
+                        .saturating_sub(ui::CONTEXT_WIDTH_PADDING),
+                    height: (members.len().max(1) as u16 + 2)
+                        .min(horizontal[1].height.saturating_sub(ui::CONTEXT_Y_OFFSET)),
+                };
+                f.render_widget(Clear, area);
+
+                let items: Vec<ListItem> = match members.is_empty() {
+                    true => vec![ListItem::new(text::NO_ITEMS)],
+                    false => members
+                        .iter()
+                        .map(|(selector, member, now)| {
+                            let mark = if *now { "*" } else { " " };
+                            ListItem::new(format!("{selector}  {mark} {member}"))
+                        })
+                        .collect(),
+                };
+                let mut list_state = ListState::default();
+                list_state.select(Some(state.connections.member_selected));
+
+                let list = List::new(items)
+                    .block(
+                        Block::default()
+                            .title(text::SELECTORS_TITLE)
+                            .borders(Borders::ALL)
+                            .border_type(BorderType::Rounded)
+                            .border_style(Style::default().fg(match state.input.error {
+                                true => Color::Red,
+                                false => Color::Yellow,
+                            })),
+                    )
+                    .highlight_style(
+                        Style::default()
+                            .fg(Color::LightGreen)
+                            .add_modifier(Modifier::BOLD),
+                    )
+                    .highlight_symbol(ui::SELECTED_SYMBOL);
+                f.render_stateful_widget(list, area, &mut list_state);
+            }
+
             if state.input.mode == InputMode::ValueInput {
                 let input = Paragraph::new(state.input.buffer.as_str())
                     .block(
@@ -656,6 +870,60 @@ pub fn run(app: &mut App) -> Result<()> {
                 f.render_widget(Clear, area);
                 f.render_widget(input, area);
             }
+
+            if state.input.mode == InputMode::TunInput {
+                let (color, message) = if state.input.error {
+                    (Color::Red, text::ERROR_INPUT)
+                } else {
+                    (Color::Green, text::ENTER_VALUE)
+                };
+
+                let input = Paragraph::new(state.input.buffer.as_str())
+                    .block(
+                        Block::default()
+                            .title(message)
+                            .borders(Borders::ALL)
+                            .border_type(BorderType::Rounded),
+                    )
+                    .style(Style::default().fg(color));
+
+                let area = tun_input_area(horizontal[1], state.ui.tun_selected);
+
+                f.render_widget(Clear, area);
+                f.render_widget(input, area);
+            }
+
+            if state.input.mode == InputMode::DnsRuleInput {
+                let (color, message) = if state.input.error {
+                    (Color::Red, text::ERROR_INPUT)
+                } else {
+                    (Color::Green, text::ENTER_DNS_RULE)
+                };
+
+                let input = Paragraph::new(state.input.buffer.as_str())
+                    .block(
+                        Block::default()
+                            .title(message)
+                            .borders(Borders::ALL)
+                            .border_type(BorderType::Rounded),
+                    )
+                    .style(Style::default().fg(color));
+
+                let area = ratatui::layout::Rect {
+                    x: horizontal[1].x + ui::VALUE_INPUT_X_OFFSET,
+                    y: horizontal[1].y
+                        + horizontal[1]
+                            .height
+                            .saturating_sub(ui::VALUE_INPUT_HEIGHT + 2),
+                    width: horizontal[1]
+                        .width
+                        .saturating_sub(ui::VALUE_INPUT_WIDTH_PADDING),
+                    height: ui::VALUE_INPUT_HEIGHT,
+                };
+
+                f.render_widget(Clear, area);
+                f.render_widget(input, area);
+            }
         })?;
     }
 
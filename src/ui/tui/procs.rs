@@ -0,0 +1,75 @@
+use std::fs;
+use std::path::Path;
+
+/// Running process as seen in `/proc`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Process {
+    pub name: String,
+    pub uid: u32,
+    pub path: Option<String>,
+}
+
+fn read_uid(pid: &str) -> Option<u32> {
+    let status = fs::read_to_string(format!("/proc/{pid}/status")).ok()?;
+    status
+        .lines()
+        .find_map(|x| x.strip_prefix("Uid:"))?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+fn basename(path: &str) -> Option<String> {
+    let name = Path::new(path).file_name()?.to_string_lossy().to_string();
+    (!name.is_empty()).then_some(name)
+}
+
+/// User space processes, one entry per name and uid
+pub fn list_processes() -> Vec<Process> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return vec![];
+    };
+
+    let mut result = vec![];
+    for entry in entries.flatten() {
+        let pid = entry.file_name().to_string_lossy().to_string();
+        if !pid.chars().all(|x| x.is_ascii_digit()) {
+            continue;
+        }
+
+        // kernel threads have empty cmdline
+        let cmdline = match fs::read(format!("/proc/{pid}/cmdline")) {
+            Ok(x) if !x.is_empty() => x,
+            _ => continue,
+        };
+
+        let Some(uid) = read_uid(&pid) else {
+            continue;
+        };
+        let path = fs::read_link(format!("/proc/{pid}/exe"))
+            .ok()
+            .map(|x| x.to_string_lossy().to_string());
+
+        // sing-box matches `process_name` against the executable name,
+        // `comm` is cut to 15 bytes so it is only the last resort
+        let argv0 = cmdline.split(|x| *x == 0).next().unwrap_or_default();
+        let name = path
+            .as_deref()
+            .and_then(basename)
+            .or_else(|| basename(&String::from_utf8_lossy(argv0)))
+            .or_else(|| {
+                let comm = fs::read_to_string(format!("/proc/{pid}/comm")).ok()?;
+                Some(comm.trim().to_string())
+            });
+        let Some(name) = name else {
+            continue;
+        };
+
+        result.push(Process { name, uid, path });
+    }
+
+    result.sort();
+    result.dedup_by(|a, b| a.name == b.name && a.uid == b.uid);
+    result
+}
@@ -0,0 +1,101 @@
+use ratatui::{
+    Frame,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, BorderType, Borders, List, ListItem, ListState, Paragraph},
+};
+
+use super::procs::Process;
+use crate::configurator::apps::{AppRule, AppTarget};
+use crate::consts::{text, ui};
+
+pub fn render_apps_panel(
+    f: &mut Frame,
+    area: Rect,
+    rules: &[AppRule],
+    processes: &[Process],
+    selected: usize,
+    focused: bool,
+    error: bool,
+) {
+    let block = Block::default()
+        .title(match error {
+            true => text::ERROR_SETTINGS_TITLE,
+            false => text::APPS_TITLE,
+        })
+        .borders(Borders::ALL)
+        .border_style(if error {
+            Style::default().fg(Color::Red)
+        } else if focused {
+            Style::default().fg(Color::Blue)
+        } else {
+            Style::default()
+        })
+        .border_type(BorderType::Rounded);
+
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    let rules_height = (rules.len().max(1) as u16 + 1).min(inner.height / 2);
+    let parts = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(rules_height),
+            Constraint::Fill(1),
+            Constraint::Length(1),
+        ])
+        .split(inner);
+
+    let mut lines: Vec<Line> = rules.iter().map(|x| Line::from(x.to_string())).collect();
+    if lines.is_empty() {
+        lines.push(Line::from(text::NO_APP_RULES));
+    }
+    f.render_widget(Paragraph::new(lines), parts[0]);
+
+    let items: Vec<ListItem> = processes
+        .iter()
+        .map(|x| {
+            let marked = rules.iter().any(|rule| match &rule.target {
+                AppTarget::Name(name) => *name == x.name,
+                AppTarget::Path(path) => x.path.as_ref() == Some(path),
+                AppTarget::Uid(uid) => *uid == x.uid,
+            });
+            let line = format!(
+                "{:20}uid {:<8}{}",
+                x.name,
+                x.uid,
+                x.path.as_deref().unwrap_or("")
+            );
+            if marked {
+                ListItem::new(line).style(Style::default().fg(Color::Green))
+            } else {
+                ListItem::new(line)
+            }
+        })
+        .collect();
+
+    let mut list_state = ListState::default();
+    if focused && !processes.is_empty() {
+        list_state.select(Some(selected));
+    }
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .title(text::PROCESSES_TITLE)
+                .borders(Borders::TOP),
+        )
+        .highlight_style(
+            Style::default()
+                .fg(Color::LightGreen)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol(ui::SELECTED_SYMBOL);
+    f.render_stateful_widget(list, parts[1], &mut list_state);
+
+    f.render_widget(
+        Paragraph::new(text::APPS_HELP).alignment(ratatui::layout::Alignment::Center),
+        parts[2],
+    );
+}
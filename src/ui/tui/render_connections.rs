@@ -0,0 +1,122 @@
+use ratatui::{
+    Frame,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    widgets::{Block, BorderType, Borders, Paragraph, Row, Table, TableState},
+};
+
+use super::conns::format_duration;
+use super::state::ConnectionsState;
+use crate::clashapi::format_bytes;
+use crate::consts::{text, ui};
+
+pub fn render_connections_panel(
+    f: &mut Frame,
+    area: Rect,
+    connections: &ConnectionsState,
+    focused: bool,
+    filtering: bool,
+    error: bool,
+) {
+    let mut title = format!("{} ({})", text::CONNECTIONS_TITLE, connections.list.len());
+    title.push_str(&format!("  sort: {}", connections.sort.label()));
+    if let Some(x) = &connections.mode {
+        title.push_str(&format!("  mode: {x}"));
+    }
+    if !connections.filter.is_empty() {
+        title.push_str(&format!("  filter: {}", connections.filter));
+    }
+
+    let block = Block::default()
+        .title(match error {
+            true => text::ERROR_SETTINGS_TITLE.to_string(),
+            false => title,
+        })
+        .borders(Borders::ALL)
+        .border_style(if error {
+            Style::default().fg(Color::Red)
+        } else if focused {
+            Style::default().fg(Color::Blue)
+        } else {
+            Style::default()
+        })
+        .border_type(BorderType::Rounded);
+
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    let parts = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Fill(1), Constraint::Length(1)])
+        .split(inner);
+
+    let message = match (&connections.client, &connections.error) {
+        (_, Some(x)) => Some(x.clone()),
+        (None, None) => Some(text::NOT_RUNNING.to_string()),
+        _ if connections.list.is_empty() => Some(text::NO_CONNECTIONS.to_string()),
+        _ => None,
+    };
+
+    match message {
+        Some(x) => f.render_widget(Paragraph::new(x), parts[0]),
+        None => {
+            let rows: Vec<Row> = connections
+                .list
+                .iter()
+                .map(|x| {
+                    let destination = format!(
+                        "{}:{}",
+                        x.metadata.destination_ip, x.metadata.destination_port
+                    );
+                    Row::new(vec![
+                        x.metadata.host.clone(),
+                        destination,
+                        x.rule.clone(),
+                        x.outbound().to_string(),
+                        format_bytes(x.upload),
+                        format_bytes(x.download),
+                        format_duration(&x.start),
+                    ])
+                })
+                .collect();
+
+            let table = Table::new(
+                rows,
+                [
+                    Constraint::Fill(3),
+                    Constraint::Fill(2),
+                    Constraint::Fill(3),
+                    Constraint::Fill(2),
+                    Constraint::Length(10),
+                    Constraint::Length(10),
+                    Constraint::Length(7),
+                ],
+            )
+            .header(
+                Row::new(text::CONNECTIONS_HEADER.to_vec())
+                    .style(Style::default().add_modifier(Modifier::BOLD)),
+            )
+            .row_highlight_style(
+                Style::default()
+                    .fg(Color::LightGreen)
+                    .add_modifier(Modifier::BOLD),
+            )
+            .highlight_symbol(ui::SELECTED_SYMBOL);
+
+            let mut table_state = TableState::default();
+            if focused {
+                table_state.select(Some(connections.selected));
+            }
+            f.render_stateful_widget(table, parts[0], &mut table_state);
+        }
+    }
+
+    let footer = match filtering {
+        true => Paragraph::new(format!("{}{}_", text::FILTER_PREFIX, connections.filter))
+            .style(Style::default().fg(Color::Green)),
+        false => {
+            Paragraph::new(text::CONNECTIONS_HELP).alignment(ratatui::layout::Alignment::Center)
+        }
+    };
+    f.render_widget(footer, parts[1]);
+}
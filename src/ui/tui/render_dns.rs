@@ -0,0 +1,101 @@
+use ratatui::{
+    Frame,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, BorderType, Borders, List, ListItem, ListState, Paragraph},
+};
+
+use crate::configurator::dns::DnsConfig;
+use crate::consts::{text, ui};
+
+pub fn render_dns_panel(
+    f: &mut Frame,
+    area: Rect,
+    dns: &DnsConfig,
+    selected: usize,
+    focused: bool,
+    error: bool,
+) {
+    let block = Block::default()
+        .title(match error {
+            true => text::ERROR_SETTINGS_TITLE,
+            false => text::DNS_TITLE,
+        })
+        .borders(Borders::ALL)
+        .border_style(if error {
+            Style::default().fg(Color::Red)
+        } else if focused {
+            Style::default().fg(Color::Blue)
+        } else {
+            Style::default()
+        })
+        .border_type(BorderType::Rounded);
+
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    let tags = dns.get_tags();
+    let servers_height = (tags.len().max(1) as u16 + 1).min(inner.height / 2);
+    let parts = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(servers_height),
+            Constraint::Fill(1),
+            Constraint::Length(1),
+        ])
+        .split(inner);
+
+    let mut lines: Vec<Line> = tags
+        .iter()
+        .map(|tag| match dns.default.as_ref() == Some(tag) {
+            true => Line::from(format!("{tag} (final)")),
+            false => Line::from(tag.as_str()),
+        })
+        .collect();
+    if lines.is_empty() {
+        lines.push(Line::from(text::NO_ITEMS));
+    }
+    f.render_widget(
+        Paragraph::new(lines).block(
+            Block::default()
+                .title(text::DNS_SERVERS_TITLE)
+                .borders(Borders::NONE),
+        ),
+        parts[0],
+    );
+
+    let rules = dns.get_rules();
+    let items: Vec<ListItem> = match rules.is_empty() {
+        true => vec![ListItem::new(text::NO_DNS_RULES)],
+        false => rules
+            .iter()
+            .enumerate()
+            .map(|(i, rule)| ListItem::new(format!("[{:2}]: {}", i + 1, rule)))
+            .collect(),
+    };
+
+    let mut list_state = ListState::default();
+    if focused && !rules.is_empty() {
+        list_state.select(Some(selected));
+    }
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .title(text::DNS_RULES_TITLE)
+                .borders(Borders::TOP),
+        )
+        .highlight_style(
+            Style::default()
+                .fg(Color::LightGreen)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol(ui::SELECTED_SYMBOL);
+    f.render_stateful_widget(list, parts[1], &mut list_state);
+
+    f.render_widget(
+        Paragraph::new(text::DNS_HELP).alignment(ratatui::layout::Alignment::Center),
+        parts[2],
+    );
+}
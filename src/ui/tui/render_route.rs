@@ -0,0 +1,54 @@
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Style},
+    widgets::{Block, BorderType, Borders, List, ListItem, Paragraph, Wrap},
+};
+
+use crate::configurator::route::routerule::RouteRule;
+use crate::consts::text;
+
+/// Route rules of the selected config under Settings, numbered like `relation route list`
+pub fn render_route_rules(
+    f: &mut Frame,
+    area: Rect,
+    rules: &[RouteRule],
+    load_error: Option<&str>,
+) {
+    let block = Block::default()
+        .title(match load_error {
+            Some(_) => text::LOAD_ERROR_TITLE,
+            None => text::ROUTE_RULES_TITLE,
+        })
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded);
+
+    if let Some(error) = load_error {
+        let error = Paragraph::new(error)
+            .wrap(Wrap { trim: true })
+            .style(Style::default().fg(Color::Red))
+            .block(block);
+        f.render_widget(error, area);
+        return;
+    }
+
+    let mut items = vec![];
+    push_rules(&mut items, rules, "", 0);
+    if items.is_empty() {
+        items.push(ListItem::new(text::NO_ROUTE_RULES));
+    }
+    f.render_widget(List::new(items).block(block), area);
+}
+
+fn push_rules(items: &mut Vec<ListItem>, rules: &[RouteRule], prefix: &str, depth: usize) {
+    for (i, rule) in rules.iter().enumerate() {
+        let number = format!("{prefix}{}", i + 1);
+        items.push(ListItem::new(format!(
+            "{}[{number:>2}]: {rule}",
+            "  ".repeat(depth)
+        )));
+        if let RouteRule::Logical(x) = rule {
+            push_rules(items, &x.rules, &format!("{number}."), depth + 1);
+        }
+    }
+}
@@ -4,53 +4,51 @@ use ratatui::{
     Frame,
     layout::Rect,
     style::{Color, Style},
-    widgets::{
-        Block, BorderType, Borders,
-        block::{Position, Title},
-    },
+    text::Line,
+    widgets::{Block, BorderType, Borders},
 };
 
+use super::conns::{Counters, TrafficSample};
+use crate::clashapi::format_bytes;
 use crate::consts::{text, traffic};
 
+/// Download on the top half, upload on the bottom, direct part is drawn over proxy
 pub fn render_traffic_bar(
     f: &mut Frame,
     area: Rect,
-    iface: &str,
     ip_base: &str,
-    rx_list: &VecDeque<u64>,
-    tx_list: &VecDeque<u64>,
+    history: &VecDeque<TrafficSample>,
+    session: Counters,
+    outbounds: &[(String, Counters)],
 ) {
-    let max_rate = rx_list
+    let max_rate = history
         .iter()
-        .chain(tx_list.iter())
-        .copied()
+        .flat_map(|x| [x.proxy.down + x.direct.down, x.proxy.up + x.direct.up])
         .max()
         .unwrap_or(traffic::MIN_RATE)
         .max(traffic::MIN_RATE);
 
-    let current_rate = rx_list
-        .back()
-        .copied()
-        .unwrap_or_default()
-        .max(tx_list.back().copied().unwrap_or_default());
-
-    let title = if current_rate as f64 >= traffic::MB {
-        format!(
-            "{} ({:.1}) MB/s",
-            text::TRAFFIC_TITLE,
-            current_rate as f64 / traffic::MB
-        )
-    } else {
-        format!(
-            "{} ({:.0}) KB/s",
-            text::TRAFFIC_TITLE,
-            current_rate as f64 / traffic::KB
-        )
-    };
+    let current = history.back().copied().unwrap_or_default();
+    let title = format!(
+        "{} proxy {} direct {}",
+        text::TRAFFIC_TITLE,
+        format_rate(current.proxy.total()),
+        format_rate(current.direct.total())
+    );
+
+    let mut totals = format!(
+        "↓{} ↑{}",
+        format_bytes(session.down),
+        format_bytes(session.up)
+    );
+    for (tag, bytes) in outbounds {
+        totals.push_str(&format!("  {tag} {}", format_bytes(bytes.total())));
+    }
 
     let traffic_block = Block::default()
         .title(title)
-        .title(Title::from(format!("{}: {}", iface, ip_base)).position(Position::Bottom))
+        .title_bottom(Line::from(ip_base.to_string()))
+        .title_bottom(Line::from(totals).right_aligned())
         .borders(Borders::ALL)
         .border_type(BorderType::Rounded);
 
@@ -80,39 +78,70 @@ pub fn render_traffic_bar(
 
     let samples_limit = traffic_width * 2;
 
-    let rx_points = latest_points(rx_list, samples_limit);
-    let tx_points = latest_points(tx_list, samples_limit);
-
-    render_braille_series(
-        f,
-        traffic_inner.x,
-        rx_area_y,
-        traffic_width,
-        rx_rows,
-        &rx_points,
-        max_rate,
-        false,
-        Color::Cyan,
-    );
+    let series = [
+        (
+            rx_area_y,
+            rx_rows,
+            false,
+            Color::Cyan,
+            latest_points(history, samples_limit, |x| x.proxy.down + x.direct.down),
+            latest_points(history, samples_limit, |x| x.direct.down),
+        ),
+        (
+            tx_area_y,
+            tx_rows,
+            true,
+            Color::Magenta,
+            latest_points(history, samples_limit, |x| x.proxy.up + x.direct.up),
+            latest_points(history, samples_limit, |x| x.direct.up),
+        ),
+    ];
+
+    for (y, rows, top_down, color, total, direct) in series {
+        render_braille_series(
+            f,
+            traffic_inner.x,
+            y,
+            traffic_width,
+            rows,
+            &total,
+            max_rate,
+            top_down,
+            color,
+            false,
+        );
+        render_braille_series(
+            f,
+            traffic_inner.x,
+            y,
+            traffic_width,
+            rows,
+            &direct,
+            max_rate,
+            top_down,
+            traffic::DIRECT_COLOR,
+            true,
+        );
+    }
+}
 
-    render_braille_series(
-        f,
-        traffic_inner.x,
-        tx_area_y,
-        traffic_width,
-        tx_rows,
-        &tx_points,
-        max_rate,
-        true,
-        Color::Magenta,
-    );
+fn format_rate(rate: u64) -> String {
+    if rate as f64 >= traffic::MB {
+        format!("{:.1} MB/s", rate as f64 / traffic::MB)
+    } else {
+        format!("{:.0} KB/s", rate as f64 / traffic::KB)
+    }
 }
 
-fn latest_points(list: &VecDeque<u64>, limit: usize) -> Vec<u64> {
+fn latest_points(
+    list: &VecDeque<TrafficSample>,
+    limit: usize,
+    value: impl Fn(&TrafficSample) -> u64,
+) -> Vec<u64> {
     list.iter()
         .rev()
         .take(limit)
-        .copied()
+        .map(value)
         .collect::<Vec<_>>()
         .into_iter()
         .rev()
@@ -129,6 +158,7 @@ fn render_braille_series(
     max_rate: u64,
     top_down: bool,
     color: Color,
+    overlay: bool,
 ) {
     if width == 0 || rows == 0 || points.is_empty() {
         return;
@@ -154,6 +184,11 @@ fn render_braille_series(
         // чтобы последняя ячейка не была наполовину пустой.
         let right_value = pair.get(1).copied().unwrap_or(left_value);
 
+        // Наложение только перекрашивает уже нарисованные ячейки.
+        if overlay && left_value == 0 && right_value == 0 {
+            continue;
+        }
+
         let left_level = value_to_braille_level(left_value, max_rate, max_level);
         let right_level = value_to_braille_level(right_value, max_rate, max_level);
 
@@ -161,6 +196,17 @@ fn render_braille_series(
             let left_part = row_braille_part(left_level, row, rows, top_down);
             let right_part = row_braille_part(right_level, row, rows, top_down);
 
+            if overlay {
+                if left_part + right_part > 0
+                    && let Some(cell) = f
+                        .buffer_mut()
+                        .cell_mut((start_x + cell_id as u16, y + row as u16))
+                {
+                    cell.set_style(Style::default().fg(color));
+                }
+                continue;
+            }
+
             let direction = if top_down { 100 } else { 0 };
             let key = direction + left_part * 10 + right_part;
 
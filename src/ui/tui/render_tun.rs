@@ -0,0 +1,68 @@
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, BorderType, Borders, Paragraph, Wrap},
+};
+
+use crate::configurator::inbound::tun::{TunConfig, TunField};
+use crate::consts::{text, ui};
+
+pub fn render_tun_panel(
+    f: &mut Frame,
+    area: Rect,
+    tun: Option<&TunConfig>,
+    selected: usize,
+    focused: bool,
+    error: bool,
+) {
+    let block = Block::default()
+        .title(match error {
+            true => text::ERROR_SETTINGS_TITLE,
+            false => text::TUN_TITLE,
+        })
+        .borders(Borders::ALL)
+        .border_style(if error {
+            Style::default().fg(Color::Red)
+        } else if focused {
+            Style::default().fg(Color::Blue)
+        } else {
+            Style::default()
+        })
+        .border_type(BorderType::Rounded);
+
+    let Some(tun) = tun else {
+        f.render_widget(Paragraph::new(text::NO_TUN).block(block), area);
+        return;
+    };
+
+    let mut lines = vec![Line::from("")];
+    for (index, field) in TunField::ALL.iter().enumerate() {
+        let style = if focused && index == selected {
+            Style::default()
+                .fg(Color::Green)
+                .add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().add_modifier(Modifier::BOLD)
+        };
+
+        lines.push(Line::from(vec![
+            Span::styled(format!("{:24}", field.name()), style),
+            Span::raw(tun.get_field(*field).unwrap_or(text::EMPTY.to_string())),
+        ]));
+    }
+
+    let panel = Paragraph::new(lines).wrap(Wrap { trim: true }).block(block);
+    f.render_widget(panel, area);
+}
+
+pub fn tun_input_area(area: Rect, selected: usize) -> Rect {
+    Rect {
+        x: area.x + ui::VALUE_INPUT_X_OFFSET,
+        y: (area.y + selected as u16 + ui::VALUE_INPUT_Y_OFFSET)
+            .min(area.y + area.height.saturating_sub(ui::VALUE_INPUT_HEIGHT)),
+        width: area.width.saturating_sub(ui::VALUE_INPUT_WIDTH_PADDING),
+        height: ui::VALUE_INPUT_HEIGHT,
+    }
+}
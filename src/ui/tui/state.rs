@@ -2,6 +2,12 @@ use std::path::PathBuf;
 
 use anyhow::Result;
 
+use super::conns::{self, ConnectionSort};
+use super::procs::Process;
+use crate::clashapi::{ClashClient, Connection};
+use crate::datamanager::catalog::{self, ConfigInfo, Grouping};
+use crate::usage::UsageStore;
+
 #[cfg(not(feature = "daemon"))]
 use crate::datamanager::app::App;
 
@@ -13,6 +19,76 @@ pub struct AppState {
     pub len: usize,
     pub running: Option<String>,
     pub enter_mode: bool,
+    /// Metadata of configs in `App::get_list` order
+    pub configs: Vec<ConfigInfo>,
+    pub search: String,
+    pub grouping: Grouping,
+}
+
+/// Line of configs list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigRow {
+    Group(String),
+    /// Index in `App::get_list`
+    Config(usize),
+}
+
+impl AppState {
+    /// Reloads metadata when configs were added, removed or renamed
+    pub fn sync(&mut self, app: &App) {
+        let names = app.get_list();
+        if self.configs.len() == names.len()
+            && self.configs.iter().zip(&names).all(|(a, b)| a.name == *b)
+        {
+            return;
+        }
+        let usage = UsageStore::load(&UsageStore::path(&app.get_data_path())).unwrap_or_default();
+        self.configs = catalog::load(&app.get_configs_path(), &names, &usage);
+    }
+
+    /// Configs matching search, favourites first, under group names when grouped
+    pub fn rows(&self) -> Vec<ConfigRow> {
+        let mut matching: Vec<&ConfigInfo> =
+            self.configs.iter().filter(|x| x.matches(&self.search)).collect();
+        matching.sort_by_key(|x| !x.favourite());
+        if self.grouping == Grouping::None {
+            return matching.into_iter().map(|x| ConfigRow::Config(x.index)).collect();
+        }
+
+        let mut res = vec![];
+        for (name, configs) in catalog::grouped(matching, self.grouping) {
+            res.push(ConfigRow::Group(name));
+            res.extend(configs.into_iter().map(|x| ConfigRow::Config(x.index)));
+        }
+        res
+    }
+
+    /// Config indices in the order they are shown
+    pub fn order(&self) -> Vec<usize> {
+        self.rows()
+            .into_iter()
+            .filter_map(|x| match x {
+                ConfigRow::Config(i) => Some(i),
+                ConfigRow::Group(_) => None,
+            })
+            .collect()
+    }
+
+    /// Moves selection by `step` through shown configs, the first one is selected when
+    /// the current one is hidden. Returns whether selection changed
+    pub fn step(&mut self, step: isize) -> bool {
+        let order = self.order();
+        if order.is_empty() {
+            return false;
+        }
+        let next = match order.iter().position(|x| *x == self.selected_index) {
+            Some(x) => order[(x as isize + step).rem_euclid(order.len() as isize) as usize],
+            None => order[0],
+        };
+        let changed = next != self.selected_index;
+        self.selected_index = next;
+        changed
+    }
 }
 
 pub struct InputState {
@@ -40,6 +116,38 @@ pub struct UiState {
     pub popup_selected: usize,
     pub custom: bool,
     pub settings_selected: usize,
+    pub tun_selected: usize,
+    pub apps_selected: usize,
+    pub processes: Vec<Process>,
+    pub dns_selected: usize,
+    /// Why selected config could not be loaded for Tun, Apps and Dns panels
+    pub load_error: Option<String>,
+}
+
+pub struct ConnectionsState {
+    /// Client of the running config
+    pub client: Option<ClashClient>,
+    /// Filtered and sorted connections shown on the panel
+    pub list: Vec<Connection>,
+    pub error: Option<String>,
+    pub selected: usize,
+    pub sort: ConnectionSort,
+    pub filter: String,
+    /// Clash modes of the running config
+    pub modes: Vec<String>,
+    /// Mode set from the panel, unknown until it is changed
+    pub mode: Option<String>,
+    /// Selector, member and whether it is selected, shown in selector popup
+    pub members: Vec<(String, String, bool)>,
+    pub member_selected: usize,
+}
+
+impl ConnectionsState {
+    pub fn refresh(&mut self, connections: &[Connection], error: Option<String>) {
+        self.list = conns::visible(connections, &self.filter, self.sort);
+        self.error = error;
+        self.selected = self.selected.min(self.list.len().saturating_sub(1));
+    }
 }
 
 pub struct TuiState {
@@ -47,6 +155,7 @@ pub struct TuiState {
     pub ui: UiState,
     pub settings: SettingsState,
     pub input: InputState,
+    pub connections: ConnectionsState,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -54,6 +163,11 @@ pub enum InputMode {
     Normal,
     AddConfig { tun: bool },
     ValueInput,
+    TunInput,
+    DnsRuleInput,
+    ConnectionFilter,
+    ConfigSearch,
+    SelectorSelect,
 }
 
 pub enum InputAction {
@@ -65,6 +179,10 @@ pub enum InputAction {
 pub enum RightPanel {
     Logs,
     Settings,
+    Tun,
+    Apps,
+    Dns,
+    Connections,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -104,12 +222,15 @@ impl TuiState {
             RightPanel::Settings
         };
 
-        Ok(Self {
+        let mut state = Self {
             app: AppState {
                 selected_index,
                 len: app.get_len(),
                 running,
                 enter_mode: false,
+                configs: vec![],
+                search: String::new(),
+                grouping: Grouping::None,
             },
             ui: UiState {
                 right_panel,
@@ -117,6 +238,11 @@ impl TuiState {
                 context_menu: false,
                 popup_selected: 0,
                 settings_selected: 0,
+                tun_selected: 0,
+                apps_selected: 0,
+                processes: vec![],
+                dns_selected: 0,
+                load_error: None,
                 custom: false,
             },
             input: InputState {
@@ -135,7 +261,21 @@ impl TuiState {
                 manage_value1: None, 
                 manage_value2: None,
             },
-        })
+            connections: ConnectionsState {
+                client: None,
+                list: vec![],
+                error: None,
+                selected: 0,
+                sort: ConnectionSort::Duration,
+                filter: String::new(),
+                modes: vec![],
+                mode: None,
+                members: vec![],
+                member_selected: 0,
+            },
+        };
+        state.app.sync(app);
+        Ok(state)
     }
 
     // pub fn moder(&self) -> InputMode {
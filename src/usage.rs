@@ -0,0 +1,251 @@
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::ops::AddAssign;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Hourly buckets are kept for a week, daily ones for a bit more than a year
+const HOURLY_KEEP: u64 = 7 * 86400;
+const DAILY_KEEP: u64 = 400 * 86400;
+
+pub const QUOTA_ACTIONS: &[&str] = &["warn", "stop", "switch"];
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub struct Bytes {
+    pub up: u64,
+    pub down: u64,
+}
+
+impl AddAssign for Bytes {
+    fn add_assign(&mut self, rhs: Self) {
+        self.up += rhs.up;
+        self.down += rhs.down;
+    }
+}
+
+impl Bytes {
+    pub fn total(&self) -> u64 {
+        self.up + self.down
+    }
+}
+
+/// Buckets keyed by UTC `2024-01-02T03` and `2024-01-02`
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct ConfigUsage {
+    pub hourly: BTreeMap<String, Bytes>,
+    pub daily: BTreeMap<String, Bytes>,
+    /// Unix seconds of the last start
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_used: Option<u64>,
+}
+
+impl ConfigUsage {
+    /// Sum of daily buckets which start with `prefix`, `2024-01` for month
+    pub fn total(&self, prefix: &str) -> Bytes {
+        let mut res = Bytes::default();
+        for (_, x) in self.daily.iter().filter(|x| x.0.starts_with(prefix)) {
+            res += *x;
+        }
+        res
+    }
+
+    /// Totals of every month in the store
+    pub fn months(&self) -> BTreeMap<String, Bytes> {
+        let mut res: BTreeMap<String, Bytes> = BTreeMap::new();
+        for (day, x) in &self.daily {
+            *res.entry(day[..7].to_string()).or_default() += *x;
+        }
+        res
+    }
+}
+
+/// Traffic of configs recorded by relationd
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct UsageStore {
+    pub configs: BTreeMap<String, ConfigUsage>,
+}
+
+impl UsageStore {
+    /// Store file in data dir
+    pub fn path(data_dir: &Path) -> PathBuf {
+        data_dir.join("usage.json")
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)
+                .with_context(|| format!("Usage store '{}' is broken", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(anyhow!(e)),
+        }
+    }
+
+    /// Loads, changes and saves the store holding a lock, relationd and the CLI both write it
+    pub fn update(path: &Path, change: impl FnOnce(&mut Self)) -> Result<()> {
+        let _lock = lock(path)?;
+        let mut store = Self::load(path)?;
+        change(&mut store);
+        store.save(path)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let tmp_file = path.with_extension("tmp");
+        fs::write(&tmp_file, serde_json::to_string(self)?)?;
+        fs::rename(tmp_file, path)?;
+        Ok(())
+    }
+
+    pub fn record(&mut self, config: &str, bytes: Bytes, now: u64) {
+        let usage = self.configs.entry(config.to_string()).or_default();
+        *usage.hourly.entry(hour_key(now)).or_default() += bytes;
+        *usage.daily.entry(day_key(now)).or_default() += bytes;
+
+        let oldest = hour_key(now.saturating_sub(HOURLY_KEEP));
+        usage.hourly.retain(|key, _| *key >= oldest);
+        let oldest = day_key(now.saturating_sub(DAILY_KEEP));
+        usage.daily.retain(|key, _| *key >= oldest);
+    }
+
+    /// Marks config as started at `now`
+    pub fn touch(&mut self, config: &str, now: u64) {
+        self.configs
+            .entry(config.to_string())
+            .or_default()
+            .last_used = Some(now);
+    }
+
+    pub fn get(&self, config: &str) -> Option<&ConfigUsage> {
+        self.configs.get(config)
+    }
+
+    /// Keeps history of renamed config
+    pub fn rename(&mut self, from: &str, to: &str) {
+        if let Some(x) = self.configs.remove(from) {
+            self.configs.insert(to.to_string(), x);
+        }
+    }
+}
+
+/// Monthly limit of config, stored in `settings.toml`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Quota {
+    /// Size like `50GB` or `500 MiB`
+    pub monthly: String,
+    /// `warn`, `stop` or `switch` [default: warn]
+    pub action: Option<String>,
+    /// Config started instead for `switch`
+    pub switch_to: Option<String>,
+}
+
+impl Quota {
+    pub fn new(monthly: &str, action: Option<&str>, switch_to: Option<&str>) -> Result<Self> {
+        parse_size(monthly)?;
+        let action = action.unwrap_or("warn");
+        if !QUOTA_ACTIONS.contains(&action) {
+            return Err(anyhow!(
+                "Unknown quota action '{action}', use one of: {}",
+                QUOTA_ACTIONS.join(", ")
+            ));
+        }
+        if action == "switch" && switch_to.is_none() {
+            return Err(anyhow!("Quota action switch needs a config to switch to"));
+        }
+
+        Ok(Self {
+            monthly: monthly.to_string(),
+            action: Some(action.to_string()),
+            switch_to: switch_to.map(|x| x.to_string()),
+        })
+    }
+
+    pub fn limit(&self) -> Result<u64> {
+        parse_size(&self.monthly)
+    }
+
+    pub fn action(&self) -> &str {
+        self.action.as_deref().unwrap_or("warn")
+    }
+}
+
+/// Exclusive lock on `usage.lock` next to the store, released when the file is dropped
+fn lock(path: &Path) -> Result<fs::File> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path.with_extension("lock"))?;
+    #[cfg(unix)]
+    {
+        use std::os::fd::AsRawFd;
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(anyhow!(
+                "Cant lock usage store: {}",
+                std::io::Error::last_os_error()
+            ));
+        }
+    }
+    Ok(file)
+}
+
+/// `1024`, `500MB`, `1.5 GiB`, units are binary
+pub fn parse_size(value: &str) -> Result<u64> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| anyhow!("Incorrect size '{value}'"))?;
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        x => return Err(anyhow!("Unknown size unit '{x}'")),
+    };
+    Ok((number * multiplier as f64) as u64)
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |x| x.as_secs())
+}
+
+pub fn hour_key(secs: u64) -> String {
+    format!("{}T{:02}", day_key(secs), secs % 86400 / 3600)
+}
+
+pub fn day_key(secs: u64) -> String {
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+pub fn month_key(secs: u64) -> String {
+    day_key(secs)[..7].to_string()
+}
+
+/// Date of day number since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}